@@ -56,7 +56,7 @@ import t1=el3 from {el1:"banishment",el2:"this",el3:"world"}
 #      An imported object can reexport a new $import function, which allows modules to define specific reimport behavior.
 #      The default implementation should just return the object itself without side effects, for cached modules.
 
-# TODO: two additional clauses can control how a script reacts depending on how it's invoked:
+# two additional clauses control how a script reacts depending on how it's invoked:
 export {
   # this code is only run when the script is imported.
   # It could be a good place for exported variables, but it's probably useful to still keep them outside...
@@ -134,9 +134,9 @@ it's not necessary to specify `local`. It could however make the code cleaner to
 
 # mutable variables are even simpler than constants:
 # > can be assigned to as many times as needed
-# > used before assignment => return a none-like value (TODO: make it clear that the variable doesn't exist)
+# > used before assignment => return a none-like value, which `defined(x)` tells apart from an actual none
 # var undeclared but assigned => implicitly defined as mutable (unless !strict assign)
-# var undeclared but used => return none-like value, like in the uninitialized case
+# var undeclared but used => return none-like value, like in the uninitialized case (NameError with !strict assign)
 local answer = 2*HALF_THE_ANSWER
 export const THE_ANSWER = answer
 
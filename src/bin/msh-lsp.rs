@@ -0,0 +1,341 @@
+use std::{collections::HashMap, error::Error};
+
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+    },
+    request::{Completion, FoldingRangeRequest, GotoDefinition, HoverRequest},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionResponse, Diagnostic, DiagnosticSeverity,
+    Documentation, FoldingRange, FoldingRangeKind, FoldingRangeProviderCapability, GotoDefinitionResponse, Hover,
+    HoverContents, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use mscript::interpreter::{
+    analysis::{self, Analysis, Severity, SymbolKind},
+    editor::FoldKind,
+};
+
+/**
+`msh-lsp`: a language server for mscript, speaking LSP over stdio. Documents are synced in full,
+and analyzed again on every change; see `interpreter::analysis` for what the analysis covers.
+ */
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["!".to_owned()]),
+            ..Default::default()
+        }),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        ..Default::default()
+    })?;
+    connection.initialize(capabilities)?;
+    Server {
+        connection,
+        documents: HashMap::new(),
+    }
+    .run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+/// An open document, with its analysis kept up to date.
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let analysis = Analysis::new(&text);
+        Document { text, analysis }
+    }
+
+    /// Convert an LSP position (0-based line, UTF-16 offset) to a line (1-based) and column (in characters).
+    fn to_source(&self, position: Position) -> (isize, isize) {
+        let line = self.text.lines().nth(position.line as usize).unwrap_or("");
+        let mut utf16 = 0;
+        let column = line
+            .chars()
+            .take_while(|c| {
+                utf16 += c.len_utf16() as u32;
+                utf16 <= position.character
+            })
+            .count();
+        (position.line as isize + 1, column as isize)
+    }
+
+    /// Convert a line (1-based) and column (in characters) to an LSP position.
+    fn to_lsp(&self, line: isize, column: isize) -> Position {
+        let line = (line - 1).max(0) as usize;
+        let text = self.text.lines().nth(line).unwrap_or("");
+        let character = text.chars().take(column.max(0) as usize).map(char::len_utf16).sum::<usize>();
+        Position::new(line as u32, character as u32)
+    }
+
+    /// The text of a line before a position.
+    fn prefix(&self, position: Position) -> String {
+        let (line, column) = self.to_source(position);
+        let text = self.text.lines().nth(line as usize - 1).unwrap_or("");
+        text.chars().take(column as usize).collect()
+    }
+}
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, Document>,
+}
+
+/// Why `cast_request` or `cast_notification` didn't extract a message.
+enum CastError<M, E> {
+    /// the message is for another method, so it's passed on
+    Mismatch(M),
+    /// the params are malformed: requests are answered with an error, notifications are ignored
+    Invalid(E),
+}
+
+fn cast_request<R: lsp_types::request::Request>(
+    req: Request,
+) -> Result<(RequestId, R::Params), CastError<Request, Response>> {
+    let id = req.id.clone();
+    match req.extract(R::METHOD) {
+        Ok(extracted) => Ok(extracted),
+        Err(ExtractError::MethodMismatch(req)) => Err(CastError::Mismatch(req)),
+        Err(ExtractError::JsonError { method, error }) => Err(CastError::Invalid(Response::new_err(
+            id,
+            lsp_server::ErrorCode::InvalidParams as i32,
+            format!("malformed `{}` request: {}", method, error),
+        ))),
+    }
+}
+
+fn cast_notification<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Result<N::Params, CastError<Notification, ()>> {
+    match notification.extract(N::METHOD) {
+        Ok(params) => Ok(params),
+        Err(ExtractError::MethodMismatch(notification)) => Err(CastError::Mismatch(notification)),
+        Err(ExtractError::JsonError { method, error }) => {
+            // stdout carries the protocol, so this goes to stderr, which clients show as the server's log
+            eprintln!("ignoring malformed `{}` notification: {}", method, error);
+            Err(CastError::Invalid(()))
+        }
+    }
+}
+
+impl Server {
+    fn run(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(req);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, req: Request) -> Response {
+        let req = match cast_request::<HoverRequest>(req) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let hover = self.documents.get(&params.text_document.uri).and_then(|doc| {
+                    let (line, column) = doc.to_source(params.position);
+                    doc.analysis.hover(line, column).map(|value| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: None,
+                    })
+                });
+                return Response::new_ok(id, hover);
+            }
+            Err(CastError::Mismatch(req)) => req,
+            Err(CastError::Invalid(response)) => return response,
+        };
+        let req = match cast_request::<GotoDefinition>(req) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let uri = params.text_document.uri;
+                let location = self.documents.get(&uri).and_then(|doc| {
+                    let (line, column) = doc.to_source(params.position);
+                    let symbol = doc.analysis.definition(line, column)?;
+                    // builtins aren't defined anywhere in the script
+                    if symbol.kind == SymbolKind::Builtin {
+                        return None;
+                    }
+                    let start = doc.to_lsp(symbol.line, symbol.column);
+                    Some(GotoDefinitionResponse::Scalar(Location::new(uri.clone(), Range::new(start, start))))
+                });
+                return Response::new_ok(id, location);
+            }
+            Err(CastError::Mismatch(req)) => req,
+            Err(CastError::Invalid(response)) => return response,
+        };
+        let req = match cast_request::<Completion>(req) {
+            Ok((id, params)) => {
+                let params = params.text_document_position;
+                let items = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|doc| Self::completions(doc, params.position))
+                    .unwrap_or_default();
+                return Response::new_ok(id, CompletionResponse::Array(items));
+            }
+            Err(CastError::Mismatch(req)) => req,
+            Err(CastError::Invalid(response)) => return response,
+        };
+        let req = match cast_request::<FoldingRangeRequest>(req) {
+            Ok((id, params)) => {
+                let ranges: Vec<_> = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|doc| {
+                        doc.analysis
+                            .editor
+                            .folds
+                            .iter()
+                            .map(|fold| FoldingRange {
+                                start_line: (fold.start_line - 1) as u32,
+                                start_character: None,
+                                end_line: (fold.end_line - 1) as u32,
+                                end_character: None,
+                                kind: Some(match fold.kind {
+                                    FoldKind::Region => FoldingRangeKind::Region,
+                                    FoldKind::Comment => FoldingRangeKind::Comment,
+                                }),
+                                collapsed_text: fold.collapsed_text.clone(),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                return Response::new_ok(id, ranges);
+            }
+            Err(CastError::Mismatch(req)) => req,
+            Err(CastError::Invalid(response)) => return response,
+        };
+        Response::new_err(req.id, lsp_server::ErrorCode::MethodNotFound as i32, format!("unknown method `{}`", req.method))
+    }
+
+    /// Static instructions after `#!`, otherwise the visible identifiers and the keywords.
+    fn completions(doc: &Document, position: Position) -> Vec<CompletionItem> {
+        let prefix = doc.prefix(position);
+        if prefix.trim_start().starts_with("#!") && !prefix.contains(char::is_whitespace) {
+            return doc
+                .analysis
+                .static_instructions()
+                .iter()
+                .map(|name| CompletionItem {
+                    label: name.clone(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    detail: Some("static instruction".to_owned()),
+                    ..Default::default()
+                })
+                .collect();
+        }
+        let (line, column) = doc.to_source(position);
+        let symbols = doc.analysis.visible_symbols(line, column).into_iter().map(|symbol| CompletionItem {
+            label: symbol.name.clone(),
+            kind: Some(match symbol.kind {
+                SymbolKind::Function => CompletionItemKind::FUNCTION,
+                SymbolKind::Constant => CompletionItemKind::CONSTANT,
+                _ => CompletionItemKind::VARIABLE,
+            }),
+            detail: Some(symbol.signature.clone()),
+            documentation: symbol.doc.clone().map(Documentation::String),
+            ..Default::default()
+        });
+        let keywords = analysis::KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        });
+        symbols.chain(keywords).collect()
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let notification = match cast_notification::<DidOpenTextDocument>(notification) {
+            Ok(params) => {
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), Document::new(params.text_document.text));
+                return self.publish_diagnostics(uri);
+            }
+            Err(CastError::Mismatch(notification)) => notification,
+            Err(CastError::Invalid(())) => return Ok(()),
+        };
+        let notification = match cast_notification::<DidChangeTextDocument>(notification) {
+            Ok(params) => {
+                let uri = params.text_document.uri;
+                // with full sync, the last change is the whole document
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), Document::new(change.text));
+                }
+                return self.publish_diagnostics(uri);
+            }
+            Err(CastError::Mismatch(notification)) => notification,
+            Err(CastError::Invalid(())) => return Ok(()),
+        };
+        if let Ok(params) = cast_notification::<DidCloseTextDocument>(notification) {
+            self.documents.remove(&params.text_document.uri);
+        }
+        Ok(())
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let Some(doc) = self.documents.get(&uri) else { return Ok(()) };
+        let diagnostics = doc
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                // errors without a location (line 0) are shown at the start of the document
+                let start = doc.to_lsp(diagnostic.line.max(1), diagnostic.column);
+                Diagnostic {
+                    range: Range::new(start, start),
+                    severity: Some(match diagnostic.severity {
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                    }),
+                    source: Some("msh".to_owned()),
+                    message: diagnostic.msg.clone(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::request::Request as _;
+
+    #[test]
+    fn malformed_params() {
+        let req = Request::new(RequestId::from(1), HoverRequest::METHOD.to_owned(), serde_json::json!({"line": 1}));
+        let Err(CastError::Invalid(response)) = cast_request::<HoverRequest>(req) else { panic!() };
+        assert_eq!(response.id, RequestId::from(1));
+        assert_eq!(response.error.unwrap().code, lsp_server::ErrorCode::InvalidParams as i32);
+        // another method isn't malformed, it's left to the next cast
+        let req = Request::new(RequestId::from(2), HoverRequest::METHOD.to_owned(), serde_json::json!({}));
+        assert!(matches!(cast_request::<Completion>(req), Err(CastError::Mismatch(_))));
+
+        let notification = Notification::new(DidOpenTextDocument::METHOD.to_owned(), serde_json::json!([]));
+        assert!(matches!(cast_notification::<DidOpenTextDocument>(notification), Err(CastError::Invalid(()))));
+    }
+}
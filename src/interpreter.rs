@@ -1,4 +1,11 @@
-// pub mod ast;
-// pub mod stackmachine;
+pub mod stackmachine;
+pub mod compiler;
+pub mod docgen;
+pub mod editor;
+pub mod analysis;
+pub mod formatter;
+pub mod lint;
+pub mod log;
 pub mod types;
 pub mod scopes;
+pub mod static_inst;
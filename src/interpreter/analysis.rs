@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+
+use crate::parser::{
+    ast::*,
+    lexer::{Lexer, TokenKind},
+    parse_recovering,
+};
+
+use super::{
+    compiler::{collect_docs, compile_with},
+    editor::{scan_editor_info, EditorInfo},
+    lint::lint_source,
+    scopes::VarScope,
+    static_inst::StaticRegistry,
+    types::builtin::declare_builtins,
+};
+
+/// The keywords of the language, as offered for completion.
+pub const KEYWORDS: &[&str] = &[
+    "local", "const", "export", "global", "unloc", "func", "arg", "run", "import", "from", "as", "if", "then",
+    "else", "loop", "while", "for", "in", "break", "continue", "true", "false",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem in a script, located at the line (counting from 1) and column (counting from 0) where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub msg: String,
+    pub line: isize,
+    pub column: isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Constant,
+    /// a variable that is assigned without being declared first
+    Implicit,
+    Function,
+    Parameter,
+    /// a script argument, declared with `arg`
+    Argument,
+    Builtin,
+}
+
+/// A declaration found in a script, or a builtin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// where the name is declared; builtins are at line 0
+    pub line: isize,
+    pub column: isize,
+    /// the declaration as shown on hover, eg. `local x: int` or `func hello(name: str) -> none`
+    pub signature: String,
+    pub doc: Option<String>,
+}
+
+/// A scope of the script, like the ones the `VarScope`s will be at runtime.
+struct Scope {
+    parent: Option<usize>,
+    /// the (line, column) range the scope covers, inclusive
+    start: (isize, isize),
+    end: (isize, isize),
+    symbols: Vec<Symbol>,
+}
+
+impl Scope {
+    fn contains(&self, pos: (isize, isize)) -> bool {
+        self.start <= pos && pos <= self.end
+    }
+}
+
+/// Builds the scopes of a script and the symbols declared in them.
+struct SymbolCollector<'a> {
+    source: &'a str,
+    scopes: Vec<Scope>,
+    current: usize,
+    docs: HashMap<(isize, isize), String>,
+}
+
+impl SymbolCollector<'_> {
+    fn enter(&mut self, span: Span) {
+        self.scopes.push(Scope {
+            parent: Some(self.current),
+            start: span.start,
+            end: span.end,
+            symbols: Vec::new(),
+        });
+        self.current = self.scopes.len() - 1;
+    }
+
+    fn leave(&mut self) {
+        self.current = self.scopes[self.current].parent.unwrap();
+    }
+
+    /// The docstring of the statement starting at a span.
+    fn doc(&self, span: Span) -> Option<String> {
+        self.docs.get(&span.start).cloned()
+    }
+
+    fn declare(&mut self, at: Span, name: String, kind: SymbolKind, signature: String, doc: Option<String>) {
+        let (line, column) = at.start;
+        self.scopes[self.current].symbols.push(Symbol {
+            name,
+            kind,
+            line,
+            column,
+            signature,
+            doc,
+        });
+    }
+
+    fn is_visible(&self, name: &str) -> bool {
+        let mut scope = Some(self.current);
+        while let Some(i) = scope {
+            if self.scopes[i].symbols.iter().any(|symbol| symbol.name == name) {
+                return true;
+            }
+            scope = self.scopes[i].parent;
+        }
+        false
+    }
+
+    fn collect_funcdef(&mut self, span: Span, func: &FuncDef) {
+        let name = func.name.name.clone();
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|param| {
+                let default = param
+                    .default
+                    .as_ref()
+                    .map(|default| format!(" = {}", default.span.slice(self.source)))
+                    .unwrap_or_default();
+                format!("{}{}{}", param.name.name, typehint_suffix(param.ty.as_ref()), default)
+            })
+            .collect();
+        let signature = format!("func {}({}) -> {}", name, params.join(", "), func.returns);
+        let doc = self.doc(span);
+        // declared before the body, so the function can call itself
+        self.declare(span, name, SymbolKind::Function, signature, doc);
+
+        self.enter(span);
+        for param in &func.params {
+            let signature = format!("param {}{}", param.name.name, typehint_suffix(param.ty.as_ref()));
+            self.declare(param.span, param.name.name.clone(), SymbolKind::Parameter, signature, None);
+            if let Some(default) = &param.default {
+                self.visit_expr(default);
+            }
+        }
+        for stmt in &func.body.body {
+            self.visit_stmt(stmt);
+        }
+        self.leave();
+    }
+}
+
+fn typehint_suffix(ty: Option<&TypeExpr>) -> String {
+    ty.map(|t| format!(": {}", t)).unwrap_or_default()
+}
+
+impl Visitor for SymbolCollector<'_> {
+    fn visit_block(&mut self, block: &Block) {
+        self.enter(block.span);
+        walk_block(self, block);
+        self.leave();
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::For { pattern, iter, body } => {
+                self.visit_expr(iter);
+                // like in the compiler, the loop variables live in a scope around the loop body
+                self.enter(stmt.span);
+                for name in pattern.names() {
+                    let signature = format!("local {}", name.name);
+                    self.declare(pattern.span, name.name.clone(), SymbolKind::Variable, signature, None);
+                }
+                self.visit_stmt(body);
+                self.leave();
+            }
+            StmtKind::Var(decl) => {
+                if let Some(value) = &decl.value {
+                    self.visit_expr(value);
+                }
+                let (kind, keyword) = if decl.constant {
+                    (SymbolKind::Constant, "const")
+                } else {
+                    (SymbolKind::Variable, "local")
+                };
+                let typehint = typehint_suffix(decl.ty.as_ref());
+                let doc = self.doc(stmt.span);
+                for name in decl.pattern.names() {
+                    let signature = format!("{} {}{}", keyword, name.name, typehint);
+                    self.declare(stmt.span, name.name.clone(), kind, signature, doc.clone());
+                }
+            }
+            StmtKind::Arg { name, ty, .. } => {
+                let signature = format!("arg {}{}", name.name, typehint_suffix(ty.as_ref()));
+                let doc = self.doc(stmt.span);
+                self.declare(stmt.span, name.name.clone(), SymbolKind::Argument, signature, doc);
+            }
+            StmtKind::Func(func) => self.collect_funcdef(stmt.span, func),
+            StmtKind::Assign { target, value, .. } => {
+                self.visit_expr(value);
+                // assigning to an undeclared name declares it, just like at runtime
+                if let ExprKind::Ident(name) = &target.kind {
+                    if !self.is_visible(name) {
+                        let doc = self.doc(stmt.span);
+                        self.declare(stmt.span, name.clone(), SymbolKind::Implicit, name.clone(), doc);
+                    }
+                } else {
+                    self.visit_expr(target);
+                }
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+}
+
+/**
+What an editor needs to know about a script: its problems, the symbols declared in each of its scopes,
+and its editor metadata. This is computed from scratch for each version of the script.
+ */
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub editor: EditorInfo,
+    /// the scopes of the script; the first one holds the builtins, the second one is the script itself
+    scopes: Vec<Scope>,
+    /// the identifiers of the script that refer to variables (not fields), as (line, column, name)
+    identifiers: Vec<(isize, isize, String)>,
+    statics: Vec<String>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let statics = StaticRegistry::default();
+        let (file, errors) = parse_recovering(source);
+        let mut diagnostics: Vec<_> = errors
+            .into_iter()
+            .map(|error| Diagnostic {
+                severity: Severity::Error,
+                msg: error.msg,
+                line: error.line,
+                column: error.column,
+            })
+            .collect();
+        // the compiler's errors and the lints would only repeat the syntax errors
+        if diagnostics.is_empty() {
+            let compiled = compile_with(source, statics.clone());
+            let (severity, problems) = match compiled {
+                Ok(script) => (Severity::Warning, script.warnings),
+                Err(errors) => (Severity::Error, errors),
+            };
+            diagnostics.extend(problems.into_iter().map(|problem| Diagnostic {
+                severity,
+                msg: problem.msg,
+                line: problem.line,
+                column: problem.column,
+            }));
+            diagnostics.extend(lint_source(source).unwrap_or_default().into_iter().map(|lint| Diagnostic {
+                severity: Severity::Warning,
+                msg: format!("{} [{}]", lint.msg, lint.rule),
+                line: lint.line,
+                column: lint.column,
+            }));
+        }
+        let editor = scan_editor_info(source);
+        diagnostics.extend(editor.warnings.iter().map(|warning| Diagnostic {
+            severity: Severity::Warning,
+            msg: warning.msg.clone(),
+            line: warning.line,
+            column: warning.column,
+        }));
+
+        let mut builtins = VarScope::new_global(false);
+        declare_builtins(&mut builtins);
+        let mut builtin_symbols: Vec<_> = builtins
+            .names()
+            .into_iter()
+            .map(|name| Symbol {
+                doc: builtins.get(&name).and_then(|field| field.read().unwrap().docstring()),
+                signature: format!("builtin {}", name),
+                name,
+                kind: SymbolKind::Builtin,
+                line: 0,
+                column: 0,
+            })
+            .collect();
+        builtin_symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut collector = SymbolCollector {
+            source,
+            scopes: vec![
+                Scope {
+                    parent: None,
+                    start: (0, 0),
+                    end: (isize::MAX, isize::MAX),
+                    symbols: builtin_symbols,
+                },
+                Scope {
+                    parent: Some(0),
+                    start: (0, 0),
+                    end: (isize::MAX, isize::MAX),
+                    symbols: Vec::new(),
+                },
+            ],
+            current: 1,
+            docs: collect_docs(source),
+        };
+        walk_file(&mut collector, &file);
+
+        Analysis {
+            diagnostics,
+            editor,
+            scopes: collector.scopes,
+            identifiers: Self::identifiers(source),
+            statics: statics.names(),
+        }
+    }
+
+    /// The identifiers of a script, except for those right after a `.` which name fields, not variables.
+    fn identifiers(source: &str) -> Vec<(isize, isize, String)> {
+        let mut identifiers = Vec::new();
+        let mut after_dot = false;
+        for token in Lexer::new(source) {
+            if token.kind == TokenKind::Id && !after_dot {
+                identifiers.push((token.line, token.column, token.text.to_owned()));
+            }
+            after_dot = token.kind == TokenKind::Dot;
+        }
+        identifiers
+    }
+
+    /// The innermost scope containing a position.
+    fn scope_at(&self, pos: (isize, isize)) -> usize {
+        let mut innermost = 1;
+        // scopes are created in source order, so nested scopes come after the ones enclosing them
+        for (i, scope) in self.scopes.iter().enumerate().skip(2) {
+            if scope.contains(pos) {
+                innermost = i;
+            }
+        }
+        innermost
+    }
+
+    /// The identifier at a position, if there is one.
+    pub fn identifier_at(&self, line: isize, column: isize) -> Option<&str> {
+        self.identifiers
+            .iter()
+            .find(|(l, c, name)| *l == line && *c <= column && column <= *c + name.chars().count() as isize)
+            .map(|(_, _, name)| name.as_str())
+    }
+
+    /**
+    Resolve a name used at a position the way `VarScope` does at runtime: look in the innermost scope first,
+    then in the ones enclosing it. Within a scope, the last declaration before the position wins; if there
+    is none, the first one after it (eg. a function called above its definition).
+     */
+    pub fn resolve(&self, name: &str, line: isize, column: isize) -> Option<&Symbol> {
+        let pos = (line, column);
+        let mut scope = Some(self.scope_at(pos));
+        while let Some(i) = scope {
+            let candidates: Vec<_> = self.scopes[i].symbols.iter().filter(|s| s.name == name).collect();
+            let before = candidates.iter().rev().find(|s| (s.line, s.column) <= pos);
+            if let Some(symbol) = before.or(candidates.first()) {
+                return Some(symbol);
+            }
+            scope = self.scopes[i].parent;
+        }
+        None
+    }
+
+    /// The declaration of the identifier at a position.
+    pub fn definition(&self, line: isize, column: isize) -> Option<&Symbol> {
+        let name = self.identifier_at(line, column)?;
+        self.resolve(name, line, column)
+    }
+
+    /// The hover text for the identifier at a position, as Markdown: its declaration and documentation.
+    pub fn hover(&self, line: isize, column: isize) -> Option<String> {
+        let symbol = self.definition(line, column)?;
+        let mut text = format!("```msh\n{}\n```", symbol.signature);
+        if let Some(doc) = &symbol.doc {
+            text.push_str("\n\n");
+            text.push_str(doc);
+        }
+        Some(text)
+    }
+
+    /// The symbols visible at a position, innermost first, without the ones they shadow.
+    pub fn visible_symbols(&self, line: isize, column: isize) -> Vec<&Symbol> {
+        let mut seen = Vec::new();
+        let mut symbols = Vec::new();
+        let mut scope = Some(self.scope_at((line, column)));
+        while let Some(i) = scope {
+            for symbol in &self.scopes[i].symbols {
+                if !seen.contains(&&symbol.name) {
+                    seen.push(&symbol.name);
+                    symbols.push(symbol);
+                }
+            }
+            scope = self.scopes[i].parent;
+        }
+        symbols
+    }
+
+    /// The names of the static instructions the compiler understands, for completion after `#!`.
+    pub fn static_instructions(&self) -> &[String] {
+        &self.statics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_scopes() {
+        let source = "## The greeting.\n\
+            local greeting: str = 'hi'\n\
+            func greet(name: str) -> none {\n\
+            \x20 local greeting = name\n\
+            \x20 greeting\n\
+            }\n\
+            greeting\n\
+            counter = 1\n";
+        let analysis = Analysis::new(source);
+        let inner = analysis.definition(5, 3).unwrap();
+        assert_eq!((inner.line, inner.signature.as_str()), (4, "local greeting"));
+        let outer = analysis.definition(7, 0).unwrap();
+        assert_eq!((outer.line, outer.doc.as_deref()), (2, Some("The greeting.")));
+        assert_eq!(analysis.hover(7, 2).unwrap(), "```msh\nlocal greeting: str\n```\n\nThe greeting.");
+        assert_eq!(analysis.definition(4, 20).unwrap().kind, SymbolKind::Parameter);
+        assert_eq!(analysis.definition(8, 0).unwrap().kind, SymbolKind::Implicit);
+        assert_eq!(
+            analysis.hover(3, 6).unwrap(),
+            "```msh\nfunc greet(name: str) -> none\n```"
+        );
+        assert_eq!(analysis.definition(1, 0), None);
+
+        let names: Vec<_> = analysis.visible_symbols(5, 0).iter().map(|s| s.name.as_str()).collect();
+        assert!(names.starts_with(&["name", "greeting", "greet", "counter"]));
+        assert!(names.contains(&"len"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let analysis = Analysis::new("local = 1\n");
+        assert!(!analysis.diagnostics.is_empty());
+        assert!(analysis.diagnostics.iter().all(|d| d.severity == Severity::Error));
+        let analysis = Analysis::new("#!frobnicate\nlocal x = 1\nx\n");
+        assert_eq!(analysis.diagnostics.len(), 1);
+        assert_eq!(analysis.diagnostics[0].severity, Severity::Warning);
+        let analysis = Analysis::new("local x = 1\n");
+        assert_eq!(analysis.diagnostics[0].msg, "`x` is never used [unused_local]");
+    }
+}
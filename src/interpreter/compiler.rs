@@ -0,0 +1,1234 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::parser::{
+    ast::*,
+    lexer::{Lexer, TokenKind},
+    parse,
+};
+
+use super::{
+    log,
+    stackmachine::{Invocation, Statement},
+    static_inst::{Metadata, Settings, StaticContext, StaticInst, StaticRegistry},
+    types::{
+        boolean::MBoolImpl,
+        float::MFloatImpl,
+        function::FunctionCode,
+        int::{IntValue, MIntImpl},
+        none::MNone,
+        object::MObjectRef,
+        path::MPathImpl,
+        string::MStringImpl,
+        BinaryOperator, UnaryOperator,
+    },
+};
+
+/// An error (or warning) found while compiling a syntax tree, located at the start of the node where it occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub msg: String,
+    pub line: isize,
+    pub column: isize,
+}
+
+/**
+Turns the syntax tree into a flat list of instructions for the `StackMachine`.
+
+The visitor appends instructions as it walks the tree. The invariant is that every
+expression leaves exactly one value on the value stack, and every statement leaves
+both stacks as it found them.
+ */
+pub struct CompilingVisitor {
+    instructions: Vec<Statement>,
+    errors: Vec<CompileError>,
+    /// the number of scopes entered at the current point of the code
+    scope_depth: usize,
+    /// the loops enclosing the current point of the code, innermost last
+    loops: Vec<LoopContext>,
+    /**
+    the variables declared in each scope entered at the current point of the code, innermost last.
+    Constants map to whether they are known to be assigned already, other variables to `None`.
+     */
+    declared: Vec<HashMap<String, Option<bool>>>,
+    /// the number of `arg` statements compiled so far, or `None` inside a function body, where `arg` isn't allowed
+    script_args: Option<usize>,
+    /// the doc comments in the source, by the position (line and column) of the token they document
+    docs: HashMap<(isize, isize), String>,
+    statics: StaticRegistry,
+    /// what the static instructions compiled so far have set
+    static_ctx: StaticContext,
+    warnings: Vec<CompileError>,
+}
+
+/// The result of compiling a script: its instructions, and what its static instructions set.
+pub struct CompiledScript {
+    pub instructions: Vec<Statement>,
+    pub settings: Settings,
+    pub metadata: Vec<Metadata>,
+    /// problems that don't prevent running the script, like unknown static instructions
+    pub warnings: Vec<CompileError>,
+}
+
+/// Bookkeeping for `break` and `continue` in a loop that's being compiled.
+struct LoopContext {
+    /// where `continue` jumps to
+    start: usize,
+    /// the `Jump` instructions emitted for `break`, to be pointed at the end of the loop once it's known
+    breaks: Vec<usize>,
+    /// the scope depth inside the loop; jumping out of nested blocks has to leave their scopes
+    scope_depth: usize,
+}
+
+impl Default for CompilingVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl CompilingVisitor {
+    pub fn new() -> Self {
+        CompilingVisitor {
+            instructions: Vec::new(),
+            errors: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+            declared: vec![HashMap::new()],
+            script_args: Some(0),
+            docs: HashMap::new(),
+            statics: StaticRegistry::default(),
+            static_ctx: StaticContext::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// A visitor that understands the static instructions of the given registry instead of the builtin ones.
+    pub fn with_statics(statics: StaticRegistry) -> Self {
+        CompilingVisitor {
+            statics,
+            ..Self::new()
+        }
+    }
+
+    /// consume the visitor, returning the compiled instructions if no errors occurred.
+    pub fn finish(self) -> Result<Vec<Statement>, Vec<CompileError>> {
+        self.finish_script().map(|script| script.instructions)
+    }
+
+    /// Like `finish`, but also returns the settings, editor metadata and warnings.
+    pub fn finish_script(self) -> Result<CompiledScript, Vec<CompileError>> {
+        if self.errors.is_empty() {
+            Ok(CompiledScript {
+                instructions: self.instructions,
+                settings: self.static_ctx.settings,
+                metadata: self.static_ctx.metadata,
+                warnings: self.warnings,
+            })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    /// Compile a whole script, starting with its `#!exec` line.
+    pub fn compile_file(&mut self, file: &File) {
+        if let Some(exec) = &file.exec {
+            let inst = StaticInst {
+                name: "exec".to_owned(),
+                args: vec![exec.path.clone()],
+                line: exec.span.line(),
+            };
+            self.apply_static(exec.span, inst);
+        }
+        walk_file(self, file);
+    }
+
+    /// Run the handler of a static instruction, emitting the instructions it asks for.
+    fn apply_static(&mut self, span: Span, inst: StaticInst) {
+        if !self.statics.is_registered(&inst.name) {
+            self.warn(span, format!("unknown static instruction `#!{}`", inst.name));
+            return;
+        }
+        if let Err(e) = self.statics.apply(&inst, &mut self.static_ctx) {
+            self.error(span, e);
+        }
+        for statement in std::mem::take(&mut self.static_ctx.instructions) {
+            self.emit(statement);
+        }
+    }
+
+    fn emit(&mut self, statement: Statement) {
+        self.instructions.push(statement);
+    }
+
+    fn push_scope(&mut self) {
+        self.emit(Statement::PushScope);
+        self.scope_depth += 1;
+        self.declared.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.emit(Statement::PopScope);
+        self.scope_depth -= 1;
+        self.declared.pop();
+    }
+
+    /// Declare a variable in the current scope; constants are `Some(initialized)`.
+    fn declare(&mut self, span: Span, name: String, constant: Option<bool>) {
+        if matches!(self.declared.last().unwrap().get(&name), Some(Some(_))) {
+            self.error(span, format!("constant `{}` cannot be redeclared", name));
+        }
+        let declaration = match constant {
+            Some(_) => Statement::DeclareConst(name.clone()),
+            None => Statement::DeclareLocal(name.clone()),
+        };
+        self.emit(declaration);
+        self.declared.last_mut().unwrap().insert(name, constant);
+    }
+
+    /// Forget a variable declared in the current scope, which constants don't allow.
+    fn undeclare(&mut self, span: Span, name: &str, action: &str) {
+        if let Some(Some(_)) = self.declared.last_mut().unwrap().remove(name) {
+            self.error(span, format!("constant `{}` cannot be {}", name, action));
+        }
+    }
+
+    /**
+    Check an assignment to a variable against the constants known at this point. A constant that's
+    assigned in the scope it was declared in counts as initialized from then on; anything else
+    (eg. assigning it inside a loop) is left to the readonly field at runtime.
+     */
+    fn check_assignable(&mut self, span: Span, name: &str) {
+        let innermost = self.declared.len() - 1;
+        let found = self.declared.iter().enumerate().rev().find_map(|(depth, scope)| Some((depth, *scope.get(name)?)));
+        match found {
+            Some((_, Some(true))) => self.error(span, format!("cannot assign to constant `{}` twice", name)),
+            Some((depth, Some(false))) if depth == innermost => {
+                self.declared[depth].insert(name.to_owned(), Some(true));
+            }
+            _ => {}
+        }
+    }
+
+    fn error(&mut self, span: Span, msg: impl Into<String>) {
+        let (line, column) = span.start;
+        self.errors.push(CompileError {
+            msg: msg.into(),
+            line,
+            column,
+        });
+    }
+
+    /// Report a problem that doesn't keep the script from running.
+    fn warn(&mut self, span: Span, msg: String) {
+        let (line, column) = span.start;
+        self.warnings.push(CompileError { msg, line, column });
+    }
+
+    /**
+    Compile an assignment target, leaving its field on the field stack. Only the object part
+    of the target is evaluated here (eg. `a.b` in `a.b.c`); the field itself is only read or written
+    later, which is what makes compound assignment evaluate the target exactly once.
+     */
+    fn compile_target(&mut self, target: &Expr) {
+        match &target.kind {
+            ExprKind::Ident(id) => {
+                self.check_assignable(target.span, id);
+                self.emit(Statement::FieldScope(id.clone()))
+            }
+            ExprKind::Global(id) => self.emit(Statement::FieldGlobal(id.name.clone())),
+            ExprKind::Dot { target, field } => {
+                self.visit_expr(target);
+                self.emit(Statement::FieldDot(field.name.clone()));
+            }
+            ExprKind::Index { target, index } => {
+                self.visit_expr(target);
+                self.visit_expr(index);
+                self.emit(Statement::FieldIndex);
+            }
+            ExprKind::Paren(inner) => self.compile_target(inner),
+            _ => self.error(target.span, "invalid assignment target"),
+        }
+    }
+
+    /// Like `compile_target`, but only looks the field up: it is neither declared nor counted as an assignment.
+    fn compile_help_target(&mut self, target: &Expr) {
+        match &target.kind {
+            ExprKind::Ident(id) => self.emit(Statement::LookupScope(id.clone())),
+            ExprKind::Global(id) => self.emit(Statement::LookupGlobal(id.name.clone())),
+            _ => self.compile_target(target),
+        }
+    }
+
+    /**
+    Compile `++x`, `x--` etc. The operand is resolved to a field once, its value is updated
+    through the type's `Inc`/`Dec` operator and then written back. Depending on `post`,
+    the expression evaluates to the old or the new value.
+     */
+    fn compile_incdec(&mut self, operand: &Expr, op: UnaryOperator, post: bool) {
+        self.compile_target(operand);
+        self.emit(Statement::ReadField);
+        if post {
+            self.emit(Statement::Dup);
+            self.emit(Statement::UnOperator(op));
+        } else {
+            self.emit(Statement::UnOperator(op));
+            self.emit(Statement::Dup);
+        }
+        self.emit(Statement::WriteField);
+    }
+
+    /// A static instruction as it appears in the source.
+    pub(crate) fn static_inst(name: &Ident, args: &[String]) -> StaticInst {
+        StaticInst {
+            name: name.name.clone(),
+            args: args.to_vec(),
+            line: name.span.line(),
+        }
+    }
+
+    /**
+    Assign the topmost value to a pattern, consuming it. Tuple and list patterns unpack the value into
+    exactly as many elements as they have parts, or at least as many with a `*rest` capture. Dict patterns
+    look up each of their keys.
+     */
+    fn compile_pattern_store(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Name(id) => self.emit(Statement::StoreScope(id.name.clone())),
+            PatternKind::Dict(entries) => {
+                for entry in entries {
+                    self.emit(Statement::Dup);
+                    match entry {
+                        DictPatternEntry::Shorthand(id) => {
+                            self.emit(Statement::LoadStatic(MStringImpl::from(&id.name).wrap()));
+                            self.emit(Statement::Index);
+                            self.emit(Statement::StoreScope(id.name.clone()));
+                        }
+                        DictPatternEntry::Pair { key, pattern } => {
+                            self.compile_dict_key(key);
+                            self.emit(Statement::Index);
+                            self.compile_pattern_store(pattern);
+                        }
+                    }
+                }
+                self.emit(Statement::Pop);
+            }
+            PatternKind::Tuple(entries) | PatternKind::List(entries) => {
+                let stars: Vec<bool> = entries.iter().map(|entry| matches!(entry, PatternEntry::Rest(_))).collect();
+                self.compile_unpack(pattern.span, &stars);
+                for entry in entries {
+                    match entry {
+                        PatternEntry::Rest(id) => self.emit(Statement::StoreScope(id.name.clone())),
+                        PatternEntry::Pattern(pattern) => self.compile_pattern_store(pattern),
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+    Assign the topmost value to an assignment target, consuming it. List, tuple and dict literals
+    as targets destructure the value just like patterns do, with any assignable expression in place of
+    the names: `(a.x, xs[0]) = pair`.
+     */
+    fn compile_store(&mut self, target: &Expr) {
+        match &target.kind {
+            ExprKind::Tuple(entries) | ExprKind::List(entries) => {
+                let stars: Vec<bool> = entries.iter().map(|entry| matches!(entry, ListEntry::Spread(_))).collect();
+                self.compile_unpack(target.span, &stars);
+                for entry in entries {
+                    self.compile_store(entry.expr());
+                }
+            }
+            ExprKind::Dict(entries) => {
+                for entry in entries {
+                    match entry {
+                        DictEntry::Spread(expr) => self.error(expr.span, "`**` can't be used in an assignment target"),
+                        DictEntry::Shorthand(id) => {
+                            self.emit(Statement::Dup);
+                            self.check_assignable(id.span, &id.name);
+                            self.emit(Statement::LoadStatic(MStringImpl::from(&id.name).wrap()));
+                            self.emit(Statement::Index);
+                            self.emit(Statement::StoreScope(id.name.clone()));
+                        }
+                        DictEntry::Pair { key, value } => {
+                            self.emit(Statement::Dup);
+                            self.compile_dict_key(key);
+                            self.emit(Statement::Index);
+                            self.compile_store(value);
+                        }
+                    }
+                }
+                self.emit(Statement::Pop);
+            }
+            ExprKind::Paren(inner) => self.compile_store(inner),
+            ExprKind::Global(id) => self.emit(Statement::StoreGlobal(id.name.clone())),
+            _ => {
+                self.compile_target(target);
+                self.emit(Statement::WriteField);
+            }
+        }
+    }
+
+    /// Unpack the topmost value for a sequence pattern whose parts marked `true` are `*rest` captures.
+    fn compile_unpack(&mut self, span: Span, stars: &[bool]) {
+        let mut rest = stars.iter().enumerate().filter(|(_, &star)| star).map(|(i, _)| i);
+        match (rest.next(), rest.next()) {
+            (None, _) => self.emit(Statement::Unpack(stars.len())),
+            (Some(i), None) => self.emit(Statement::UnpackRest(i, stars.len() - i - 1)),
+            (Some(_), Some(_)) => self.error(span, "a pattern can only have one `*` capture"),
+        }
+    }
+
+    /// Compile a key of a dict literal or pattern. Identifiers as keys are taken literally.
+    fn compile_dict_key(&mut self, key: &Expr) {
+        match &key.kind {
+            ExprKind::Ident(id) => self.emit(Statement::LoadStatic(MStringImpl::from(id).wrap())),
+            _ => self.visit_expr(key),
+        }
+    }
+
+    /**
+    Compile `for pattern in iter body`. The iterator stays on the value stack for the duration
+    of the loop; the loop variables live in a scope of their own, around the body's scope.
+     */
+    fn compile_for(&mut self, pattern: &Pattern, iter: &Expr, body: &Stmt) {
+        self.visit_expr(iter);
+        self.emit(Statement::Iter);
+        self.push_scope();
+        for name in pattern.names() {
+            self.declare(pattern.span, name.name.clone(), None);
+        }
+
+        let start = self.instructions.len();
+        // the target is patched in once the end of the loop is known
+        self.emit(Statement::Next(0));
+        self.compile_pattern_store(pattern);
+        self.loops.push(LoopContext {
+            start,
+            breaks: Vec::new(),
+            scope_depth: self.scope_depth,
+        });
+        self.visit_stmt(body);
+        let loop_context = self.loops.pop().unwrap();
+        self.emit(Statement::Jump(start));
+
+        // `break` still has to get rid of the iterator, which `Next` does by itself
+        let break_target = self.instructions.len();
+        self.emit(Statement::Pop);
+        let end = self.instructions.len();
+        self.pop_scope();
+        self.instructions[start] = Statement::Next(end);
+        for jump in loop_context.breaks {
+            self.instructions[jump] = Statement::Jump(break_target);
+        }
+    }
+
+    /// Compile `break` or `continue`, leaving the scopes entered inside the loop.
+    fn compile_loop_jump(&mut self, span: Span, is_break: bool) {
+        let (start, loop_depth) = match self.loops.last() {
+            Some(loop_context) => (loop_context.start, loop_context.scope_depth),
+            None => {
+                let keyword = if is_break { "break" } else { "continue" };
+                self.error(span, format!("`{}` outside of a loop", keyword));
+                return;
+            }
+        };
+        for _ in loop_depth..self.scope_depth {
+            self.emit(Statement::PopScope);
+        }
+        if is_break {
+            let jump = self.instructions.len();
+            self.emit(Statement::Jump(0));
+            self.loops.last_mut().unwrap().breaks.push(jump);
+        } else {
+            self.emit(Statement::Jump(start));
+        }
+    }
+
+    /// Compile an `export { }` or `run { }` block, which is skipped unless the script is invoked in the given way.
+    fn compile_invoked(&mut self, invocation: Invocation, block: &Block) {
+        let skip = self.instructions.len();
+        self.emit(Statement::JumpUnlessInvoked(invocation, 0));
+        self.visit_block(block);
+        let end = self.instructions.len();
+        self.instructions[skip] = Statement::JumpUnlessInvoked(invocation, end);
+    }
+
+    fn compile_vardecl(&mut self, span: Span, decl: &VarDecl) {
+        let constant = decl.constant.then_some(decl.value.is_some());
+        let names: Vec<String> = decl.pattern.names().into_iter().map(|name| name.name.clone()).collect();
+        for name in &names {
+            self.declare(span, name.clone(), constant);
+        }
+        if let Some(docstring) = self.docs.get(&span.start).cloned() {
+            for name in names {
+                self.emit(Statement::SetDoc(name, docstring.clone()));
+            }
+        }
+        if let Some(value) = &decl.value {
+            self.visit_expr(value);
+            if decl.clone {
+                self.emit(Statement::CloneValue);
+            }
+            self.compile_pattern_store(&decl.pattern);
+        }
+        if decl.export {
+            for name in decl.pattern.names() {
+                self.emit(Statement::Export(name.name.clone()));
+            }
+        }
+    }
+
+    /**
+    Compile a function definition. The default values are evaluated here, when the function is defined;
+    the body is compiled into instructions of its own, which run in a new scope for each call, with
+    the parameters declared in it.
+     */
+    fn compile_func(&mut self, span: Span, func: &FuncDef) {
+        let name = func.name.name.clone();
+        // declared first, so the function can call itself
+        self.declare(span, name.clone(), None);
+        let docstring = self.docs.get(&span.start).cloned();
+        if let Some(docstring) = &docstring {
+            self.emit(Statement::SetDoc(name.clone(), docstring.clone()));
+        }
+        let mut params: Vec<(String, bool)> = Vec::new();
+        for param in &func.params {
+            if params.iter().any(|(other, _)| other == &param.name.name) {
+                self.error(param.span, format!("parameter `{}` given twice", param.name.name));
+            }
+            if let Some(default) = &param.default {
+                self.visit_expr(default);
+            }
+            params.push((param.name.name.clone(), param.default.is_some()));
+        }
+
+        let outer_instructions = std::mem::take(&mut self.instructions);
+        let outer_loops = std::mem::take(&mut self.loops);
+        let outer_args = self.script_args.take();
+        self.declared.push(params.iter().map(|(name, _)| (name.clone(), None)).collect());
+        walk_block(self, &func.body);
+        self.declared.pop();
+        self.script_args = outer_args;
+        self.loops = outer_loops;
+        let instructions = std::mem::replace(&mut self.instructions, outer_instructions);
+
+        let code = FunctionCode { name: name.clone(), params, docstring, instructions };
+        self.emit(Statement::MakeFunction(Arc::new(code)));
+        self.emit(Statement::StoreScope(name.clone()));
+        if func.export {
+            self.emit(Statement::Export(name));
+        }
+    }
+
+    /// Compile `arg name = default`, which declares a variable holding an argument the script was called with.
+    fn compile_arg(&mut self, span: Span, name: &Ident, default: Option<&Expr>) {
+        let index = match self.script_args {
+            Some(index) => index,
+            None => {
+                self.error(span, "`arg` can only be used in a script, not in a function");
+                return;
+            }
+        };
+        self.script_args = Some(index + 1);
+        self.declare(span, name.name.clone(), None);
+        let load = self.instructions.len();
+        self.emit(Statement::LoadArg(index, name.name.clone(), None));
+        if let Some(default) = default {
+            self.visit_expr(default);
+            let end = self.instructions.len();
+            self.instructions[load] = Statement::LoadArg(index, name.name.clone(), Some(end));
+        }
+        self.emit(Statement::StoreScope(name.name.clone()));
+    }
+
+    /**
+    Compile an import, which evaluates to the imported module and binds it (or the selected exports)
+    in the current scope. The source is called like a function, eg. `import ./lib()`, but arguments
+    aren't supported yet.
+     */
+    fn compile_import(&mut self, span: Span, import: &Import) {
+        let source = match &import.source.kind {
+            ExprKind::Call { callee, args, kwargs } => {
+                if !args.is_empty() || !kwargs.is_empty() {
+                    self.error(import.source.span, "arguments to an imported script aren't supported yet");
+                }
+                callee.as_ref()
+            }
+            _ => &import.source,
+        };
+        let name = import.module_name();
+        self.visit_expr(source);
+        self.emit(Statement::Import(name.clone().unwrap_or_else(|| "module".to_owned())));
+        match &import.kind {
+            ImportKind::Module(_) => match name {
+                Some(name) => {
+                    self.declare(span, name.clone(), None);
+                    self.emit(Statement::Dup);
+                    self.emit(Statement::StoreScope(name));
+                }
+                None => self.error(span, "can't tell what to name this import, use `import name = ...`"),
+            },
+            ImportKind::From(selectors) => {
+                for selector in selectors {
+                    match selector {
+                        ImportSelector::All => self.emit(Statement::ImportAll),
+                        ImportSelector::Name { alias, name } => {
+                            let binding = alias.as_ref().unwrap_or(name);
+                            self.declare(binding.span, binding.name.clone(), None);
+                            self.emit(Statement::Dup);
+                            self.emit(Statement::Dot(name.name.clone()));
+                            self.emit(Statement::StoreScope(binding.name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn compile_assignment(&mut self, target: &Expr, op: AssignOp, value: &Expr) {
+        match op {
+            AssignOp::Compound(op) => {
+                self.compile_target(target);
+                self.emit(Statement::ReadField);
+                self.visit_expr(value);
+                self.emit(Statement::BinOperator(Self::binary_operator(op)));
+                self.emit(Statement::WriteField);
+            }
+            AssignOp::Assign | AssignOp::Clone => {
+                // like in Python, the value is evaluated before any part of the target
+                self.visit_expr(value);
+                if op == AssignOp::Clone {
+                    self.emit(Statement::CloneValue);
+                }
+                self.compile_store(target);
+            }
+        }
+    }
+
+    /// Compile the entries of a list or tuple literal, returning which of them are spreads.
+    fn compile_list_entries(&mut self, entries: &[ListEntry]) -> Vec<bool> {
+        entries
+            .iter()
+            .map(|entry| {
+                self.visit_expr(entry.expr());
+                matches!(entry, ListEntry::Spread(_))
+            })
+            .collect()
+    }
+
+    fn compile_call(&mut self, callee: &Expr, args: &[Expr], kwargs: &[KwArg]) {
+        if let Some(target) = Self::help_target(callee, args, kwargs) {
+            // `help(x)` has to see the documentation of the variable `x`, not just that of its value
+            self.compile_help_target(target);
+            self.emit(Statement::Help);
+            return;
+        }
+        self.visit_expr(callee);
+        for arg in args {
+            self.visit_expr(arg);
+        }
+        let mut kwnames: Vec<String> = Vec::new();
+        for kwarg in kwargs {
+            let name = &kwarg.name.name;
+            if kwnames.contains(name) {
+                self.error(kwarg.name.span, format!("keyword argument `{}` given twice", name));
+            }
+            self.visit_expr(&kwarg.value);
+            kwnames.push(name.clone());
+        }
+        self.emit(Statement::Call(args.len(), kwnames));
+    }
+
+    /// The argument of a call like `help(x)`, if it refers to a variable or field whose documentation `help` should show.
+    fn help_target<'a>(callee: &Expr, args: &'a [Expr], kwargs: &[KwArg]) -> Option<&'a Expr> {
+        if !matches!(&callee.kind, ExprKind::Ident(name) if name == "help") || !kwargs.is_empty() {
+            return None;
+        }
+        match args {
+            [arg] => match arg.kind {
+                ExprKind::Ident(_) | ExprKind::Global(_) | ExprKind::Dot { .. } | ExprKind::Index { .. } => Some(arg),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn binary_operator(op: BinaryOp) -> BinaryOperator {
+        match op {
+            BinaryOp::And => BinaryOperator::And,
+            BinaryOp::Or => BinaryOperator::Or,
+            BinaryOp::BitAnd => BinaryOperator::BitAnd,
+            BinaryOp::BitOr => BinaryOperator::BitOr,
+            BinaryOp::Xor => BinaryOperator::Xor,
+            BinaryOp::AtOperator => BinaryOperator::AtOperator,
+            BinaryOp::Pow => BinaryOperator::Pow,
+            BinaryOp::Mul => BinaryOperator::Mul,
+            BinaryOp::Div => BinaryOperator::Div,
+            BinaryOp::Mod => BinaryOperator::Mod,
+            BinaryOp::Plus => BinaryOperator::Plus,
+            BinaryOp::Minus => BinaryOperator::Minus,
+            BinaryOp::Eq => BinaryOperator::Eq,
+            BinaryOp::Neq => BinaryOperator::Neq,
+            BinaryOp::In => BinaryOperator::In,
+        }
+    }
+}
+
+/**
+Find the doc comments in the source. The parser skips comments, so this runs the lexer once more
+and attaches each run of doc comments to the next token (skipping line breaks and plain comments), ie. the start of
+the declaration they document. Variable declarations and function definitions use them; the grammar has no `struct`,
+so there are no struct fields to document.
+ */
+pub(crate) fn collect_docs(source: &str) -> HashMap<(isize, isize), String> {
+    let mut docs = HashMap::new();
+    let mut pending = Vec::new();
+    for token in Lexer::new(source) {
+        match token.kind {
+            TokenKind::Eof => break,
+            TokenKind::DocComment | TokenKind::DocBComment => pending.push(token.text.to_owned()),
+            TokenKind::Nl | TokenKind::Comment | TokenKind::BComment => {}
+            _ if !pending.is_empty() => {
+                docs.insert(token.pos(), clean_docstring(&pending));
+                pending.clear();
+            }
+            _ => {}
+        }
+    }
+    docs
+}
+
+/// Strip the comment markers from doc comments, as well as the indentation of a block comment.
+pub(crate) fn clean_docstring(comments: &[String]) -> String {
+    let mut lines = Vec::new();
+    for comment in comments {
+        if let Some(block) = comment.strip_prefix("##<") {
+            let block = block.strip_suffix(">##").unwrap_or(block);
+            let indent = block
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            lines.extend(block.lines().map(|line| line.get(indent..).unwrap_or("").trim_end().to_owned()));
+        } else {
+            let line = comment.trim_start_matches('#').trim_end();
+            lines.push(line.strip_prefix(' ').unwrap_or(line).to_owned());
+        }
+    }
+    let first = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let last = lines.iter().rposition(|line| !line.is_empty()).map_or(first, |i| i + 1);
+    lines[first..last].join("\n")
+}
+
+/// Resolve the escape sequences in a string literal, stripping the surrounding quotes.
+pub fn unescape_literal(text: &str) -> Result<String, String> {
+    let inner = &text[1..text.len() - 1];
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => result.push('\x08'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some(c @ ('\\' | '\'' | '$')) => result.push(c),
+            Some(c @ ('x' | 'u')) => {
+                let len = if c == 'x' { 2 } else { 4 };
+                let code: String = chars.by_ref().take(len).collect();
+                let value = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(format!("invalid escape sequence `\\{}{}`", c, code))?;
+                result.push(value);
+            }
+            Some(c) => return Err(format!("invalid escape sequence `\\{}`", c)),
+            None => return Err("unterminated escape sequence".to_owned()),
+        }
+    }
+    Ok(result)
+}
+
+impl Visitor for CompilingVisitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Static { name, args } => {
+                let inst = Self::static_inst(name, args);
+                self.apply_static(stmt.span, inst);
+            }
+            StmtKind::For { pattern, iter, body } => self.compile_for(pattern, iter, body),
+            StmtKind::Break => self.compile_loop_jump(stmt.span, true),
+            StmtKind::Continue => self.compile_loop_jump(stmt.span, false),
+            StmtKind::If { .. } | StmtKind::While { .. } | StmtKind::DoWhile { .. } => {
+                self.error(stmt.span, "`if` and `while` are not supported by the compiler yet");
+            }
+            StmtKind::Export(block) => self.compile_invoked(Invocation::Import, block),
+            StmtKind::Run(block) => self.compile_invoked(Invocation::Run, block),
+            StmtKind::Var(decl) => self.compile_vardecl(stmt.span, decl),
+            StmtKind::Func(func) => self.compile_func(stmt.span, func),
+            StmtKind::Arg { name, default, .. } => self.compile_arg(stmt.span, name, default.as_ref()),
+            StmtKind::Assign { target, op, value } => self.compile_assignment(target, *op, value),
+            StmtKind::Expr(expr) => {
+                if let ExprKind::Global(id) = &expr.kind {
+                    self.undeclare(expr.span, &id.name, "moved to the global scope");
+                    self.emit(Statement::DeclareGlobal(id.name.clone()));
+                    return;
+                }
+                // expression statements don't keep their value
+                self.visit_expr(expr);
+                self.emit(Statement::Pop);
+            }
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.push_scope();
+        walk_block(self, block);
+        self.pop_scope();
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Ident(id) => self.emit(Statement::LoadScope(id.clone())),
+            ExprKind::Global(id) => self.emit(Statement::LoadGlobal(id.name.clone())),
+            ExprKind::Unloc(id) => {
+                self.undeclare(expr.span, &id.name, "undeclared");
+                self.emit(Statement::Unloc(id.name.clone()));
+            }
+            ExprKind::Str(text) => match unescape_literal(text) {
+                Ok(value) => self.emit(Statement::LoadStatic(MStringImpl::from(value).wrap())),
+                Err(msg) => self.error(expr.span, msg),
+            },
+            ExprKind::Path(path) => self.emit(Statement::LoadStatic(MPathImpl::new(path.clone()).wrap())),
+            ExprKind::Int(text) => match IntValue::parse_literal(text) {
+                Some(value) => self.emit(Statement::LoadStatic(MIntImpl::from(value).wrap())),
+                None => self.error(expr.span, "invalid int format"),
+            },
+            ExprKind::Float(text) => match MFloatImpl::parse(text) {
+                Ok(value) => self.emit(Statement::LoadStatic(value.wrap())),
+                Err(_) => self.error(expr.span, "invalid float format"),
+            },
+            ExprKind::Bool(value) => self.emit(Statement::LoadStatic(MBoolImpl::from(*value).wrap())),
+            ExprKind::List(entries) => {
+                let spreads = self.compile_list_entries(entries);
+                self.emit(Statement::BuildList(spreads));
+            }
+            ExprKind::Tuple(entries) => {
+                let spreads = self.compile_list_entries(entries);
+                self.emit(Statement::BuildTuple(spreads));
+            }
+            ExprKind::Dict(entries) => {
+                let mut spreads = Vec::new();
+                for entry in entries {
+                    match entry {
+                        DictEntry::Spread(expr) => self.visit_expr(expr),
+                        DictEntry::Shorthand(id) => {
+                            // `{a}` is short for `{a: a}`
+                            self.emit(Statement::LoadStatic(MStringImpl::from(&id.name).wrap()));
+                            self.emit(Statement::LoadScope(id.name.clone()));
+                        }
+                        DictEntry::Pair { key, value } => {
+                            self.compile_dict_key(key);
+                            self.visit_expr(value);
+                        }
+                    }
+                    spreads.push(matches!(entry, DictEntry::Spread(_)));
+                }
+                self.emit(Statement::BuildDict(spreads));
+            }
+            ExprKind::Paren(inner) => self.visit_expr(inner),
+            ExprKind::Index { target, index } => {
+                self.visit_expr(target);
+                self.visit_expr(index);
+                self.emit(Statement::Index);
+            }
+            ExprKind::Slice { target, start, stop, step } => {
+                self.visit_expr(target);
+                for bound in [start, stop, step] {
+                    match bound {
+                        Some(bound) => self.visit_expr(bound),
+                        None => self.emit(Statement::LoadStatic(MNone::refer() as MObjectRef)),
+                    }
+                }
+                self.emit(Statement::Slice);
+            }
+            ExprKind::Call { callee, args, kwargs } => self.compile_call(callee, args, kwargs),
+            ExprKind::Dot { target, field } => {
+                self.visit_expr(target);
+                self.emit(Statement::Dot(field.name.clone()));
+            }
+            ExprKind::Unary { op, operand } => {
+                self.visit_expr(operand);
+                let op = match op {
+                    UnaryOp::Not => UnaryOperator::Not,
+                    UnaryOp::BitNot => UnaryOperator::Bitnot,
+                };
+                self.emit(Statement::UnOperator(op));
+            }
+            ExprKind::Update { op, postfix, target } => {
+                let op = match op {
+                    UpdateOp::Inc => UnaryOperator::Inc,
+                    UpdateOp::Dec => UnaryOperator::Dec,
+                };
+                self.compile_incdec(target, op, *postfix);
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                // TODO: short circuiting for `&&` and `||`
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+                self.emit(Statement::BinOperator(Self::binary_operator(*op)));
+            }
+            ExprKind::Cast { value, ty: TypeExpr::Name(ty) } => {
+                self.visit_expr(value);
+                self.emit(Statement::Cast(ty.name.clone()));
+            }
+            ExprKind::Import(import) => self.compile_import(expr.span, import),
+        }
+    }
+}
+
+/// Parse and compile a source file into instructions for the `StackMachine`.
+pub fn compile(source: &str) -> Result<Vec<Statement>, Vec<CompileError>> {
+    let script = compile_with(source, StaticRegistry::default())?;
+    let logger = log::default_logger();
+    for warning in &script.warnings {
+        logger.warn(&format!("line {}: {}", warning.line, warning.msg));
+    }
+    Ok(script.instructions)
+}
+
+/// Compile a script with custom static instructions, keeping what they set as well as the warnings.
+pub fn compile_with(source: &str, statics: StaticRegistry) -> Result<CompiledScript, Vec<CompileError>> {
+    let file = parse(source).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| CompileError {
+                msg: error.msg,
+                line: error.line,
+                column: error.column,
+            })
+            .collect::<Vec<_>>()
+    })?;
+
+    let mut visitor = CompilingVisitor::with_statics(statics);
+    visitor.docs = collect_docs(source);
+    visitor.compile_file(&file);
+    visitor.finish_script()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::log::LogLevel;
+
+    fn count(instructions: &[Statement], pred: impl Fn(&Statement) -> bool) -> usize {
+        instructions.iter().filter(|s| pred(s)).count()
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_target_once() {
+        let instructions = compile("a.b[c] += 'x'\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::LoadScope(id) if id == "a")), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::LoadScope(id) if id == "c")), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::FieldIndex)), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::ReadField)), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::WriteField)), 1);
+    }
+
+    #[test]
+    fn increment_result_value() {
+        // the post increment keeps a copy of the old value below the new one
+        let instructions = compile("obj.count++\n").unwrap();
+        assert!(matches!(
+            &instructions[..],
+            [
+                Statement::LoadScope(_),
+                Statement::FieldDot(_),
+                Statement::ReadField,
+                Statement::Dup,
+                Statement::UnOperator(UnaryOperator::Inc),
+                Statement::WriteField,
+                Statement::Pop,
+            ]
+        ));
+        let instructions = compile("--list[i]\n").unwrap();
+        assert!(matches!(
+            &instructions[2..],
+            [
+                Statement::FieldIndex,
+                Statement::ReadField,
+                Statement::UnOperator(UnaryOperator::Dec),
+                Statement::Dup,
+                Statement::WriteField,
+                Statement::Pop,
+            ]
+        ));
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        assert!(compile("'abc' = 'def'\n").is_err());
+    }
+
+    #[test]
+    fn container_literals() {
+        let instructions = compile("[1, *xs, 2]\n").unwrap();
+        assert!(matches!(&instructions[3], Statement::BuildList(spreads) if spreads == &[false, true, false]));
+        let instructions = compile("(a,)\n").unwrap();
+        assert!(matches!(&instructions[1], Statement::BuildTuple(spreads) if spreads == &[false]));
+        // `(a)` is just brackets
+        let instructions = compile("(a)\n").unwrap();
+        assert!(matches!(&instructions[..], [Statement::LoadScope(_), Statement::Pop]));
+        let instructions = compile("{a, b: 1, (c): 2, **d}\n").unwrap();
+        assert!(matches!(&instructions[7], Statement::BuildDict(spreads) if spreads == &[false, false, false, true]));
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::LoadScope(id) if id == "b")), 0);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::LoadScope(id) if id == "c")), 1);
+    }
+
+    #[test]
+    fn calls() {
+        let instructions = compile("f(1, 2, x=3)\n").unwrap();
+        assert!(matches!(&instructions[4], Statement::Call(2, kwnames) if kwnames == &["x"]));
+        assert!(compile("f(x=1, x=2)\n").is_err());
+    }
+
+    #[test]
+    fn globals() {
+        let instructions = compile("global a\nglobal b = global a\nunloc c\n").unwrap();
+        assert!(matches!(&instructions[0], Statement::DeclareGlobal(id) if id == "a"));
+        assert!(matches!(&instructions[1], Statement::LoadGlobal(id) if id == "a"));
+        assert!(matches!(&instructions[2], Statement::StoreGlobal(id) if id == "b"));
+        assert!(matches!(&instructions[3], Statement::Unloc(id) if id == "c"));
+        assert!(matches!(instructions[4], Statement::Pop));
+        assert!(compile("const a = 1\nglobal a\n").is_err());
+        assert!(compile("const a = 1\nunloc a\n").is_err());
+    }
+
+    #[test]
+    fn constants() {
+        let instructions = compile("const (a, b) = pair\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::DeclareConst(_))), 2);
+        assert!(compile("const a = 1\na = 2\n").is_err());
+        assert!(compile("const a = 1\n{ a += 1 }\n").is_err());
+        assert!(compile("const a = 1\nlocal a = 2\n").is_err());
+        assert!(compile("const a\na = 1\na = 2\n").is_err());
+        assert!(compile("const a\na = 1\n").is_ok());
+        // a shadowing variable is a different one
+        assert!(compile("const a = 1\n{ local a = 2\na = 3 }\n").is_ok());
+        // whether this assigns twice depends on the loop, so it's only caught at runtime
+        assert!(compile("const a\nfor x in xs { a = x }\n").is_ok());
+    }
+
+    #[test]
+    fn destructuring() {
+        let instructions = compile("local [a, *rest, (b, c)] = xs\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::DeclareLocal(_))), 4);
+        assert!(instructions.iter().any(|s| matches!(s, Statement::UnpackRest(1, 1))));
+        assert!(instructions.iter().any(|s| matches!(s, Statement::Unpack(2))));
+        // dict patterns look up each key on a copy of the value, then drop it
+        let instructions = compile("local {name, (k): v} = d\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::Dup)), 2);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::Index)), 2);
+        // assignment targets can be any assignable expression
+        let instructions = compile("(a.x, xs[0]) = (xs[0], a.x)\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::WriteField)), 2);
+        assert!(compile("(a, b) += x\n").is_err());
+        let instructions = compile("local (a, b) <= pair\n[a, b] <= pair\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::CloneValue)), 2);
+        assert!(compile("[*a, *b] = xs\n").is_err());
+        assert!(compile("{**a} = d\n").is_err());
+    }
+
+    #[test]
+    fn for_loop_jumps() {
+        let instructions = compile("for (a, b) in xs { { break }; continue }\n").unwrap();
+        let next = instructions.iter().position(|s| matches!(s, Statement::Next(_))).unwrap();
+        let end = match instructions[next] {
+            Statement::Next(end) => end,
+            _ => unreachable!(),
+        };
+        assert!(matches!(instructions[next + 1], Statement::Unpack(2)));
+        // the loop ends by popping the iterator (only reached by `break`) and then the loop scope
+        assert!(matches!(instructions[end - 2], Statement::Jump(target) if target == next));
+        assert!(matches!(instructions[end - 1], Statement::Pop));
+        assert!(matches!(instructions[end], Statement::PopScope));
+        // `break` leaves both the body's and the inner block's scope
+        let jumps: Vec<_> = instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| match s {
+                Statement::Jump(target) => Some((i, *target)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(jumps[0].1, end - 1);
+        assert!(matches!(instructions[jumps[0].0 - 2..jumps[0].0], [Statement::PopScope, Statement::PopScope]));
+        assert_eq!(jumps[1].1, next);
+        assert!(compile("break\n").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let source = "func greet(name, greeting = 'hi') -> none {\n  log.append(greeting + name)\n}\n";
+        let instructions = compile(source).unwrap();
+        // the default is evaluated at the definition, the body only when the function is called
+        assert!(matches!(
+            &instructions[..],
+            [
+                Statement::DeclareLocal(_),
+                Statement::LoadStatic(_),
+                Statement::MakeFunction(_),
+                Statement::StoreScope(name),
+            ] if name == "greet"
+        ));
+        let code = match &instructions[2] {
+            Statement::MakeFunction(code) => code,
+            _ => unreachable!(),
+        };
+        assert_eq!(format!("{:?}", code), "func greet(name, greeting)");
+        assert_eq!(code.params, vec![("name".to_owned(), false), ("greeting".to_owned(), true)]);
+        assert_eq!(count(&code.instructions, |s| matches!(s, Statement::Call(1, _))), 1);
+        let instructions = compile("export func f() -> none {}\n").unwrap();
+        assert!(matches!(instructions.last(), Some(Statement::Export(name)) if name == "f"));
+        assert!(compile("func f(a, a) -> none {}\n").is_err());
+        assert!(compile("for x in xs { func f() -> none { break } }\n").is_err());
+        assert!(compile("func f() -> none { arg x }\n").is_err());
+    }
+
+    #[test]
+    fn script_arguments() {
+        let instructions = compile("arg a\narg b = 2\n").unwrap();
+        assert!(matches!(&instructions[1], Statement::LoadArg(0, name, None) if name == "a"));
+        // with an argument, the code of the default is skipped
+        assert!(matches!(&instructions[4], Statement::LoadArg(1, name, Some(6)) if name == "b"));
+        assert!(matches!(&instructions[6], Statement::StoreScope(name) if name == "b"));
+    }
+
+    #[test]
+    fn imports() {
+        // the module is bound under the name of the file, and stays the value of the expression
+        let instructions = compile("import ./lib.m()\n").unwrap();
+        assert!(matches!(
+            &instructions[..],
+            [
+                Statement::LoadStatic(_),
+                Statement::Import(name),
+                Statement::DeclareLocal(_),
+                Statement::Dup,
+                Statement::StoreScope(binding),
+                Statement::Pop,
+            ] if name == "lib" && binding == "lib"
+        ));
+        let instructions = compile("import a, b = c, * from lib()\n").unwrap();
+        assert!(matches!(&instructions[1], Statement::Import(name) if name == "lib"));
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::Dot(field) if field == "c")), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::StoreScope(id) if id == "b")), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::ImportAll)), 1);
+        assert!(compile("import ./lib(1)\n").is_err());
+        assert!(compile("import ('lib')()\n").is_err());
+        assert!(compile("import m = ('lib')()\n").is_ok());
+    }
+
+    #[test]
+    fn doc_comments() {
+        let source = "## The answer.\n## Don't ask.\nlocal (a, b) = xs\n##<\n    Indented\n      block\n>##\nconst c = 1\nlocal d\n\
+            ## Does nothing.\nexport func f() -> none {}\n";
+        let instructions = compile(source).unwrap();
+        let docs: Vec<_> = instructions
+            .iter()
+            .filter_map(|s| match s {
+                Statement::SetDoc(name, doc) => Some((name.as_str(), doc.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            docs,
+            vec![
+                ("a", "The answer.\nDon't ask."),
+                ("b", "The answer.\nDon't ask."),
+                ("c", "Indented\n  block"),
+                ("f", "Does nothing."),
+            ]
+        );
+        let code = instructions.iter().find_map(|s| match s {
+            Statement::MakeFunction(code) => Some(code),
+            _ => None,
+        });
+        assert_eq!(code.unwrap().docstring.as_deref(), Some("Does nothing."));
+        // `help` on a variable looks at the variable, not just its value
+        let instructions = compile("help(a.b)\nhelp(1)\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::Help)), 1);
+        // it only looks variables up, so it doesn't assign constants
+        assert!(compile("const a = 1\nhelp(a)\n").is_ok());
+        assert!(compile("const a\nhelp(a)\na = 1\n").is_ok());
+        let instructions = compile("help(a)\n").unwrap();
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::LookupScope(_))), 1);
+        assert_eq!(count(&instructions, |s| matches!(s, Statement::FieldScope(_))), 0);
+    }
+
+    #[test]
+    fn static_instructions() {
+        let mut statics = StaticRegistry::default();
+        statics.register("deny", |name: String, _, ctx| {
+            ctx.instructions.push(Statement::Unloc(name));
+            Ok(())
+        });
+        let source = "#!exec /bin/msh\n#!tab 2\n#!sep\n#!strict assign dolstr\n#!deny x\n#!frobnicate\nlocal x = 1\n";
+        let script = compile_with(source, statics).ok().unwrap();
+        assert_eq!(script.settings.exec, Some("/bin/msh".to_owned()));
+        assert!(script.settings.strict.assign && script.settings.strict.dolstr && !script.settings.strict.import);
+        let metadata: Vec<_> = script.metadata.iter().map(|m| (m.name.as_str(), m.line)).collect();
+        assert_eq!(metadata, vec![("tab", 2), ("sep", 3)]);
+        assert!(matches!(script.instructions[0], Statement::StrictAssign(true)));
+        assert!(matches!(&script.instructions[1], Statement::Unloc(name) if name == "x"));
+        let warnings: Vec<_> = script.warnings.iter().map(|w| (w.line, w.msg.as_str())).collect();
+        assert_eq!(warnings, vec![(6, "unknown static instruction `#!frobnicate`")]);
+
+        let errors = compile("#!tab two\n").err().unwrap();
+        assert_eq!(errors[0].msg, "`#!tab`: expected a number, got `two`");
+        assert!(compile("#!strict sloppy\n").is_err());
+        let script = compile_with("#!log ./trace.log trace\n", StaticRegistry::default()).ok().unwrap();
+        assert_eq!(script.settings.log.level, Some(LogLevel::Trace));
+        assert_eq!(script.settings.log.file, Some("./trace.log".to_owned()));
+        assert!(compile("#!log loudly\n").is_err());
+    }
+
+    #[test]
+    fn casts() {
+        // `as` binds loosest, so the whole sum is converted
+        let instructions = compile("x + 1 as str\n").unwrap();
+        let cast = instructions.iter().position(|s| matches!(s, Statement::Cast(ty) if ty == "str")).unwrap();
+        assert!(matches!(instructions[cast - 1], Statement::BinOperator(BinaryOperator::Plus)));
+    }
+
+    #[test]
+    fn invocation_blocks() {
+        let instructions = compile("export {\n  export $call = f\n}\nrun {\n  f()\n}\n").unwrap();
+        // each block is skipped as a whole, scope included, unless the script is invoked its way
+        let skips: Vec<_> = instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| match s {
+                Statement::JumpUnlessInvoked(invocation, target) => Some((i, *invocation, *target)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(skips.len(), 2);
+        let (run_start, run_end) = (skips[1].0, skips[1].2);
+        assert_eq!((skips[0].0, skips[0].1, skips[0].2), (0, Invocation::Import, run_start));
+        assert_eq!((skips[1].1, run_end), (Invocation::Run, instructions.len()));
+        assert!(matches!(instructions[run_start - 1], Statement::PopScope));
+        let export = instructions.iter().position(|s| matches!(s, Statement::Export(id) if id == "$call")).unwrap();
+        assert!(matches!(&instructions[export - 1], Statement::StoreScope(id) if id == "$call"));
+    }
+
+    #[test]
+    fn literal_escapes() {
+        assert_eq!(unescape_literal(r"'a\'b\n\x41é'"), Ok("a'b\nAé".to_owned()));
+        assert!(unescape_literal(r"'\q'").is_err());
+    }
+}
@@ -0,0 +1,507 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::parser::{
+    ast::{FuncDef, Span, Stmt, StmtKind, VarDecl},
+    parse,
+};
+
+use super::compiler::collect_docs;
+
+/**
+The structured form of a docstring: a free-form description, followed by tags like
+`!param name the name of the user` and `!return nothing`.
+A tag continues on the following lines until an empty line or the next tag.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocComment {
+    pub description: String,
+    /// the documented parameters with their descriptions, in the order they are documented
+    pub params: Vec<(String, String)>,
+    pub returns: Option<String>,
+}
+
+/// Where the lines of a docstring currently go while parsing it.
+enum Section {
+    Description,
+    Param(usize),
+    Return,
+}
+
+impl DocComment {
+    /// Parse the tags out of a docstring, along with warnings about malformed or repeated tags.
+    pub fn parse(docstring: &str) -> (Self, Vec<String>) {
+        let mut doc = DocComment::default();
+        let mut warnings = Vec::new();
+        let mut description = Vec::new();
+        let mut section = Section::Description;
+        for line in docstring.lines() {
+            let trimmed = line.trim();
+            if let Some(tag) = trimmed.strip_prefix('!') {
+                let (tag, rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                let rest = rest.trim();
+                match tag {
+                    "param" => {
+                        let (name, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                        if name.is_empty() {
+                            warnings.push("`!param` needs the name of a parameter".to_owned());
+                            section = Section::Description;
+                            continue;
+                        }
+                        if doc.params.iter().any(|(param, _)| param == name) {
+                            warnings.push(format!("parameter `{}` is documented twice", name));
+                        }
+                        doc.params.push((name.to_owned(), text.trim().to_owned()));
+                        section = Section::Param(doc.params.len() - 1);
+                    }
+                    "return" | "returns" => {
+                        if doc.returns.is_some() {
+                            warnings.push("the return value is documented twice".to_owned());
+                        }
+                        doc.returns = Some(rest.to_owned());
+                        section = Section::Return;
+                    }
+                    _ => {
+                        warnings.push(format!("unknown tag `!{}`", tag));
+                        description.push(line);
+                        section = Section::Description;
+                    }
+                }
+                continue;
+            }
+            let text = match section {
+                // an empty line ends a tag, without being part of the description itself
+                _ if trimmed.is_empty() && !matches!(section, Section::Description) => {
+                    section = Section::Description;
+                    continue;
+                }
+                Section::Description => {
+                    description.push(line);
+                    continue;
+                }
+                Section::Param(i) => &mut doc.params[i].1,
+                Section::Return => doc.returns.as_mut().unwrap(),
+            };
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(trimmed);
+        }
+        doc.description = description.join("\n").trim_matches('\n').to_owned();
+        (doc, warnings)
+    }
+}
+
+/// A formal parameter of a documented function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub typehint: Option<String>,
+    /// the source text of the default value
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemKind {
+    Function { params: Vec<Param>, returns: Option<String> },
+    Variable { constant: bool, typehint: Option<String> },
+}
+
+/// A function or variable declared at the top level of a script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocItem {
+    pub name: String,
+    pub kind: ItemKind,
+    pub exported: bool,
+    pub line: isize,
+    pub doc: Option<DocComment>,
+}
+
+impl DocItem {
+    /// The declaration as shown in the reference, eg. `hello(name: str) -> none` or `const v1: int`.
+    pub fn signature(&self) -> String {
+        match &self.kind {
+            ItemKind::Function { params, returns } => {
+                let params: Vec<_> = params
+                    .iter()
+                    .map(|param| {
+                        let mut text = param.name.clone();
+                        if let Some(typehint) = &param.typehint {
+                            write!(text, ": {}", typehint).unwrap();
+                        }
+                        if let Some(default) = &param.default {
+                            write!(text, " = {}", default).unwrap();
+                        }
+                        text
+                    })
+                    .collect();
+                let returns = returns.as_ref().map(|r| format!(" -> {}", r)).unwrap_or_default();
+                format!("{}({}){}", self.name, params.join(", "), returns)
+            }
+            ItemKind::Variable { constant, typehint } => {
+                let keyword = if *constant { "const" } else { "local" };
+                let typehint = typehint.as_ref().map(|t| format!(": {}", t)).unwrap_or_default();
+                format!("{} {}{}", keyword, self.name, typehint)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocWarning {
+    pub line: isize,
+    pub msg: String,
+}
+
+/// The API reference of a single script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleDoc {
+    pub name: String,
+    pub items: Vec<DocItem>,
+    pub warnings: Vec<DocWarning>,
+}
+
+/// Collects the documented declarations of a syntax tree.
+struct DocCollector<'a> {
+    source: &'a str,
+    docs: HashMap<(isize, isize), String>,
+    items: Vec<DocItem>,
+    warnings: Vec<DocWarning>,
+}
+
+impl DocCollector<'_> {
+    fn warn(&mut self, line: isize, msg: String) {
+        self.warnings.push(DocWarning { line, msg });
+    }
+
+    /// The parsed docstring attached to a declaration, if any.
+    fn doc_for(&mut self, span: Span) -> Option<DocComment> {
+        let docstring = self.docs.get(&span.start)?.clone();
+        let (doc, warnings) = DocComment::parse(&docstring);
+        for msg in warnings {
+            self.warn(span.line(), msg);
+        }
+        Some(doc)
+    }
+
+    /// Walk the statements of a script. Blocks have their own scope, so only `export` blocks are entered,
+    /// and only for declarations that are exported themselves.
+    fn visit_stmts(&mut self, stmts: &[Stmt], exported_only: bool) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Export(block) => self.visit_stmts(&block.body, true),
+                StmtKind::Func(func) if !exported_only || func.export => self.visit_funcdef(stmt.span, func),
+                StmtKind::Var(decl) if !exported_only || decl.export => self.visit_vardecl(stmt.span, decl),
+                _ => {}
+            }
+        }
+    }
+
+    fn visit_funcdef(&mut self, span: Span, func: &FuncDef) {
+        let name = func.name.name.clone();
+        let line = span.line();
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|param| Param {
+                name: param.name.name.clone(),
+                typehint: param.ty.as_ref().map(ToString::to_string),
+                default: param.default.as_ref().map(|default| default.span.slice(self.source).to_owned()),
+            })
+            .collect();
+        let doc = self.doc_for(span);
+        if let Some(doc) = &doc {
+            // the parameters are matched by name, so misspelled ones show up twice
+            for (documented, _) in &doc.params {
+                if !params.iter().any(|param| &param.name == documented) {
+                    self.warn(line, format!("`{}` has no parameter `{}`", name, documented));
+                }
+            }
+            for param in &params {
+                if !doc.params.iter().any(|(documented, _)| documented == &param.name) {
+                    self.warn(line, format!("parameter `{}` of `{}` is not documented", param.name, name));
+                }
+            }
+        }
+        self.items.push(DocItem {
+            name,
+            kind: ItemKind::Function { params, returns: Some(func.returns.to_string()) },
+            exported: func.export,
+            line,
+            doc,
+        });
+    }
+
+    fn visit_vardecl(&mut self, span: Span, decl: &VarDecl) {
+        let line = span.line();
+        let doc = self.doc_for(span);
+        if let Some(doc) = &doc {
+            if !doc.params.is_empty() || doc.returns.is_some() {
+                self.warn(line, "`!param` and `!return` only apply to functions".to_owned());
+            }
+        }
+        for name in decl.pattern.names() {
+            self.items.push(DocItem {
+                name: name.name.clone(),
+                kind: ItemKind::Variable {
+                    constant: decl.constant,
+                    typehint: decl.ty.as_ref().map(ToString::to_string),
+                },
+                exported: decl.export,
+                line,
+                doc: doc.clone(),
+            });
+        }
+    }
+}
+
+/// Collect the documented declarations of a script, warning about docstrings that don't match them.
+pub fn document_source(name: &str, source: &str) -> Result<ModuleDoc, String> {
+    let file = parse(source)
+        .map_err(|errors| errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))?;
+
+    let mut collector = DocCollector {
+        source,
+        docs: collect_docs(source),
+        items: Vec::new(),
+        warnings: Vec::new(),
+    };
+    collector.visit_stmts(&file.body, false);
+    Ok(ModuleDoc {
+        name: name.to_owned(),
+        items: collector.items,
+        warnings: collector.warnings,
+    })
+}
+
+/// Render the reference of a script as Markdown.
+pub fn render_markdown(module: &ModuleDoc) -> String {
+    let mut out = format!("# {}\n", module.name);
+    let exports: Vec<_> = module.items.iter().filter(|item| item.exported).collect();
+    if !exports.is_empty() {
+        out.push_str("\n## Exports\n\n");
+        for item in exports {
+            writeln!(out, "- `{}`", item.name).unwrap();
+        }
+    }
+    for (title, functions) in [("Functions", true), ("Variables", false)] {
+        let items: Vec<_> = module
+            .items
+            .iter()
+            .filter(|item| matches!(item.kind, ItemKind::Function { .. }) == functions)
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        write!(out, "\n## {}\n", title).unwrap();
+        for item in items {
+            let export = if item.exported { "export " } else { "" };
+            write!(out, "\n### `{}{}`\n", export, item.signature()).unwrap();
+            let Some(doc) = &item.doc else { continue };
+            if !doc.description.is_empty() {
+                write!(out, "\n{}\n", doc.description).unwrap();
+            }
+            if let ItemKind::Function { params, returns } = &item.kind {
+                if !doc.params.is_empty() {
+                    out.push_str("\n**Parameters**\n\n");
+                    for (name, text) in &doc.params {
+                        let typehint = params
+                            .iter()
+                            .find(|param| &param.name == name)
+                            .and_then(|param| param.typehint.as_ref())
+                            .map(|t| format!(" (`{}`)", t))
+                            .unwrap_or_default();
+                        writeln!(out, "- `{}`{}: {}", name, typehint, text).unwrap();
+                    }
+                }
+                if let Some(text) = &doc.returns {
+                    let typehint = returns.as_ref().map(|t| format!(" (`{}`)", t)).unwrap_or_default();
+                    write!(out, "\n**Returns**{}: {}\n", typehint, text).unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Render the reference of a script as a standalone HTML page.
+pub fn render_html(module: &ModuleDoc) -> String {
+    let name = escape_html(&module.name);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        name, name
+    );
+    let exports: Vec<_> = module.items.iter().filter(|item| item.exported).collect();
+    if !exports.is_empty() {
+        out.push_str("<h2>Exports</h2>\n<ul>\n");
+        for item in exports {
+            let name = escape_html(&item.name);
+            writeln!(out, "<li><a href=\"#{}\"><code>{}</code></a></li>", name, name).unwrap();
+        }
+        out.push_str("</ul>\n");
+    }
+    for (title, functions) in [("Functions", true), ("Variables", false)] {
+        let items: Vec<_> = module
+            .items
+            .iter()
+            .filter(|item| matches!(item.kind, ItemKind::Function { .. }) == functions)
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        writeln!(out, "<h2>{}</h2>", title).unwrap();
+        for item in items {
+            let export = if item.exported { "export " } else { "" };
+            writeln!(
+                out,
+                "<h3 id=\"{}\"><code>{}{}</code></h3>",
+                escape_html(&item.name),
+                export,
+                escape_html(&item.signature())
+            )
+            .unwrap();
+            let Some(doc) = &item.doc else { continue };
+            for paragraph in doc.description.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                writeln!(out, "<p>{}</p>", escape_html(paragraph.trim())).unwrap();
+            }
+            if let ItemKind::Function { params, returns } = &item.kind {
+                if !doc.params.is_empty() {
+                    out.push_str("<h4>Parameters</h4>\n<dl>\n");
+                    for (name, text) in &doc.params {
+                        let typehint = params
+                            .iter()
+                            .find(|param| &param.name == name)
+                            .and_then(|param| param.typehint.as_ref())
+                            .map(|t| format!(": {}", t))
+                            .unwrap_or_default();
+                        writeln!(
+                            out,
+                            "<dt><code>{}{}</code></dt><dd>{}</dd>",
+                            escape_html(name),
+                            escape_html(&typehint),
+                            escape_html(text)
+                        )
+                        .unwrap();
+                    }
+                    out.push_str("</dl>\n");
+                }
+                if let Some(text) = &doc.returns {
+                    let typehint = returns.as_ref().map(|t| format!(" <code>{}</code>", escape_html(t))).unwrap_or_default();
+                    writeln!(out, "<h4>Returns{}</h4>\n<p>{}</p>", typehint, escape_html(text)).unwrap();
+                }
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/**
+Write the Markdown and HTML reference of a script, or of every `.m` script in a directory tree,
+into `out_dir`, mirroring the layout of the tree. A directory also gets an index of its scripts.
+Returns the warnings about the docstrings, prefixed with the script and line they refer to.
+ */
+pub fn generate(root: &Path, out_dir: &Path) -> Result<Vec<String>, String> {
+    let scripts: Vec<PathBuf> = if root.is_dir() {
+        let pattern = root.join("**").join("*.m");
+        glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?
+    } else {
+        vec![root.to_owned()]
+    };
+    let base = if root.is_dir() { root } else { root.parent().unwrap_or(Path::new("")) };
+
+    let mut warnings = Vec::new();
+    let mut modules = Vec::new();
+    for script in scripts {
+        let source = fs::read_to_string(&script).map_err(|e| format!("{}: {}", script.display(), e))?;
+        let relative = script.strip_prefix(base).unwrap_or(&script).with_extension("");
+        let name = relative.to_string_lossy().replace('\\', "/");
+        let module = document_source(&name, &source).map_err(|e| format!("{}: {}", script.display(), e))?;
+        for warning in &module.warnings {
+            warnings.push(format!("{}:{}: {}", script.display(), warning.line, warning.msg));
+        }
+
+        let target = out_dir.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        for (extension, contents) in [("md", render_markdown(&module)), ("html", render_html(&module))] {
+            let path = target.with_extension(extension);
+            fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        modules.push(name);
+    }
+
+    if root.is_dir() {
+        let mut markdown = "# API reference\n\n".to_owned();
+        let mut html = "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>API reference</title>\n</head>\n<body>\n<h1>API reference</h1>\n<ul>\n".to_owned();
+        for name in &modules {
+            writeln!(markdown, "- [{}]({}.md)", name, name).unwrap();
+            let name = escape_html(name);
+            writeln!(html, "<li><a href=\"{}.html\">{}</a></li>", name, name).unwrap();
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+        fs::write(out_dir.join("index.md"), markdown).map_err(|e| e.to_string())?;
+        fs::write(out_dir.join("index.html"), html).map_err(|e| e.to_string())?;
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tags() {
+        let (doc, warnings) = DocComment::parse(
+            "Say hello.\n\n!param name the name\n  of the user\n!return nothing\n\nMore text.\n!param name again",
+        );
+        assert_eq!(doc.description, "Say hello.\n\nMore text.");
+        assert_eq!(
+            doc.params,
+            vec![
+                ("name".to_owned(), "the name of the user".to_owned()),
+                ("name".to_owned(), "again".to_owned())
+            ]
+        );
+        assert_eq!(doc.returns, Some("nothing".to_owned()));
+        assert_eq!(warnings, vec!["parameter `name` is documented twice".to_owned()]);
+    }
+
+    #[test]
+    fn check_params() {
+        let source = "##<\nCapitalize a string.\n\n!param str the string to capitalize\n!return the string\n>##\n\
+            export func capitalize(s: str, all: bool = false) -> str { s }\n\
+            ## The answer.\nconst answer: int = 42\n";
+        let module = document_source("strings", source).unwrap();
+        let messages: Vec<_> = module.warnings.iter().map(|w| (w.line, w.msg.as_str())).collect();
+        assert_eq!(
+            messages,
+            vec![
+                (7, "`capitalize` has no parameter `str`"),
+                (7, "parameter `s` of `capitalize` is not documented"),
+                (7, "parameter `all` of `capitalize` is not documented"),
+            ]
+        );
+        assert_eq!(module.items[0].signature(), "capitalize(s: str, all: bool = false) -> str");
+        assert_eq!(module.items[1].signature(), "const answer: int");
+
+        let markdown = render_markdown(&module);
+        assert!(markdown.contains("## Exports\n\n- `capitalize`\n"));
+        assert!(markdown.contains("### `export capitalize(s: str, all: bool = false) -> str`\n\nCapitalize a string.\n"));
+        assert!(markdown.contains("**Returns** (`str`): the string\n"));
+        assert!(markdown.contains("### `const answer: int`\n\nThe answer.\n"));
+        assert!(render_html(&module).contains("<h3 id=\"answer\"><code>const answer: int</code></h3>"));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::parser::lexer::{Lexer, TokenKind};
+
+use super::{
+    compiler::{clean_docstring, CompileError},
+    static_inst::StaticArgs,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldKind {
+    /// a region between `#!fold` (or `#!bfold`) and `#!endfold`
+    Region,
+    /// a doc comment spanning several lines
+    Comment,
+}
+
+/**
+A range of lines an editor can fold, from `start_line` to `end_line` inclusive (counting from 1).
+Regions include the doc comment right before their `#!fold`; when folded, they show that comment instead
+of their content, unless they were opened with `#!bfold <name> nocomment`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldingRange {
+    pub kind: FoldKind,
+    pub name: Option<String>,
+    pub start_line: isize,
+    pub end_line: isize,
+    /// what to show in place of the folded range
+    pub collapsed_text: Option<String>,
+}
+
+/// The editor metadata of a script, as given by its static instructions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorInfo {
+    pub folds: Vec<FoldingRange>,
+    /// the lines with a `#!sep`, where the editor can draw a separator
+    pub separators: Vec<isize>,
+    /// the width of a tab, from `#!tab`
+    pub tab_width: Option<usize>,
+    /// malformed instructions and folds that aren't properly closed
+    pub warnings: Vec<CompileError>,
+}
+
+/// A run of doc comments, remembered until the next token shows whether it belongs to a fold.
+struct DocRun {
+    texts: Vec<String>,
+    start_line: isize,
+    end_line: isize,
+}
+
+/// A `#!fold` that hasn't been closed yet.
+struct OpenFold {
+    name: Option<String>,
+    start_line: isize,
+    collapsed_text: Option<String>,
+}
+
+/**
+Collect the folding ranges, separators and tab width of a script. This only runs the lexer,
+so it works on scripts that don't parse (yet), as they usually are while being edited.
+ */
+pub fn scan_editor_info(source: &str) -> EditorInfo {
+    let mut info = EditorInfo::default();
+    let mut open_folds: Vec<OpenFold> = Vec::new();
+    let mut docs: Option<DocRun> = None;
+    // the name, arguments and line of the static instruction being read
+    let mut inst: Option<(Option<String>, Vec<String>, isize)> = None;
+    for token in Lexer::new(source) {
+        let line = token.line;
+        if let Some((name, args, _)) = inst.as_mut() {
+            if matches!(token.kind, TokenKind::Comment | TokenKind::BComment) {
+                continue;
+            }
+            if !matches!(token.kind, TokenKind::Nl | TokenKind::Eof) {
+                match name {
+                    None => *name = Some(token.text.to_owned()),
+                    Some(_) => args.push(token.text.to_owned()),
+                }
+                continue;
+            }
+            let (name, args, line) = inst.take().unwrap();
+            apply(&mut info, &mut open_folds, docs.take(), name.unwrap_or_default(), &args, line);
+        }
+        match token.kind {
+            TokenKind::Eof => break,
+            TokenKind::DocComment | TokenKind::DocBComment => {
+                let text = token.text.to_owned();
+                // line comments end with their line break, block comments don't
+                let end_line = line + text.trim_end_matches('\n').matches('\n').count() as isize;
+                let run = docs.get_or_insert(DocRun { texts: Vec::new(), start_line: line, end_line });
+                run.texts.push(text);
+                run.end_line = end_line;
+            }
+            TokenKind::Nl | TokenKind::Comment | TokenKind::BComment => {}
+            TokenKind::StaticInst => inst = Some((None, Vec::new(), line)),
+            _ => {
+                if let Some(run) = docs.take() {
+                    fold_comment(&mut info, run);
+                }
+            }
+        }
+    }
+    if let Some(run) = docs.take() {
+        fold_comment(&mut info, run);
+    }
+    for fold in open_folds {
+        warn(&mut info, fold.start_line, "`#!fold` is never closed by `#!endfold`".to_owned());
+    }
+    info.folds.sort_by_key(|fold| fold.start_line);
+    info
+}
+
+fn warn(info: &mut EditorInfo, line: isize, msg: String) {
+    info.warnings.push(CompileError { msg, line, column: 0 });
+}
+
+/// Doc comments that don't introduce a fold can be folded themselves, if they span several lines.
+fn fold_comment(info: &mut EditorInfo, run: DocRun) {
+    if run.end_line > run.start_line {
+        info.folds.push(FoldingRange {
+            kind: FoldKind::Comment,
+            name: None,
+            start_line: run.start_line,
+            end_line: run.end_line,
+            collapsed_text: clean_docstring(&run.texts).lines().next().map(str::to_owned),
+        });
+    }
+}
+
+/// Handle one static instruction; the ones an editor doesn't care about are skipped.
+fn apply(
+    info: &mut EditorInfo,
+    open_folds: &mut Vec<OpenFold>,
+    docs: Option<DocRun>,
+    name: String,
+    args: &[String],
+    line: isize,
+) {
+    let mut docs = docs;
+    match name.as_str() {
+        "fold" | "bfold" => {
+            let (fold_name, nocomment) = match (name.as_str(), args) {
+                ("fold", _) => match Option::<String>::parse(args) {
+                    Ok(fold_name) => (fold_name, false),
+                    Err(e) => return warn(info, line, format!("`#!fold`: {}", e)),
+                },
+                (_, [fold_name]) => (Some(fold_name.clone()), false),
+                (_, [fold_name, option]) if option == "nocomment" => (Some(fold_name.clone()), true),
+                _ => return warn(info, line, "`#!bfold`: expected a name, optionally followed by `nocomment`".to_owned()),
+            };
+            let run = if nocomment { None } else { docs.take() };
+            open_folds.push(OpenFold {
+                name: fold_name,
+                start_line: run.as_ref().map_or(line, |run| run.start_line),
+                collapsed_text: run.map(|run| clean_docstring(&run.texts)),
+            });
+        }
+        "endfold" => {
+            let fold_name = match Option::<String>::parse(args) {
+                Ok(fold_name) => fold_name,
+                Err(e) => return warn(info, line, format!("`#!endfold`: {}", e)),
+            };
+            // a nameless `#!endfold` closes the innermost fold, a named one the innermost fold of that name
+            let position = match &fold_name {
+                None => open_folds.len().checked_sub(1),
+                Some(_) => open_folds.iter().rposition(|fold| fold.name == fold_name),
+            };
+            match position {
+                Some(i) => {
+                    for unclosed in open_folds.drain(i + 1..).collect::<Vec<_>>() {
+                        warn(info, unclosed.start_line, "`#!fold` is never closed by `#!endfold`".to_owned());
+                    }
+                    let fold = open_folds.pop().unwrap();
+                    info.folds.push(FoldingRange {
+                        kind: FoldKind::Region,
+                        name: fold.name,
+                        start_line: fold.start_line,
+                        end_line: line,
+                        collapsed_text: fold.collapsed_text,
+                    });
+                }
+                None => warn(info, line, "`#!endfold` without a matching `#!fold`".to_owned()),
+            }
+        }
+        "sep" => match <()>::parse(args) {
+            Ok(()) => info.separators.push(line),
+            Err(e) => warn(info, line, format!("`#!sep`: {}", e)),
+        },
+        "tab" => match usize::parse(args) {
+            Ok(width) => info.tab_width = Some(width),
+            Err(e) => warn(info, line, format!("`#!tab`: {}", e)),
+        },
+        _ => {}
+    }
+    // doc comments before other instructions are still foldable on their own
+    if let Some(run) = docs {
+        fold_comment(info, run);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_and_separators() {
+        let source = "#!tab 2\n\
+            ## Stimuli from the cortex.\n\
+            ## Folds to this comment.\n\
+            #!fold neura\n\
+            local stim = 1\n\
+            #!sep\n\
+            #!bfold inner nocomment\n\
+            local x = 2\n\
+            #!endfold inner\n\
+            #!endfold neura\n\
+            ##<\n\
+            Just a long comment\n\
+            >##\n\
+            #!endfold\n";
+        let info = scan_editor_info(source);
+        assert_eq!(info.tab_width, Some(2));
+        assert_eq!(info.separators, vec![6]);
+        assert_eq!(
+            info.folds,
+            vec![
+                FoldingRange {
+                    kind: FoldKind::Region,
+                    name: Some("neura".to_owned()),
+                    start_line: 2,
+                    end_line: 10,
+                    collapsed_text: Some("Stimuli from the cortex.\nFolds to this comment.".to_owned()),
+                },
+                FoldingRange {
+                    kind: FoldKind::Region,
+                    name: Some("inner".to_owned()),
+                    start_line: 7,
+                    end_line: 9,
+                    collapsed_text: None,
+                },
+                FoldingRange {
+                    kind: FoldKind::Comment,
+                    name: None,
+                    start_line: 11,
+                    end_line: 13,
+                    collapsed_text: Some("Just a long comment".to_owned()),
+                },
+            ]
+        );
+        assert_eq!(info.warnings.len(), 1);
+        assert_eq!((info.warnings[0].line, info.warnings[0].msg.as_str()), (14, "`#!endfold` without a matching `#!fold`"));
+    }
+}
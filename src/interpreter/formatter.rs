@@ -0,0 +1,397 @@
+use std::collections::HashSet;
+
+use crate::parser::{
+    ast::*,
+    lexer::{Lexer, Token, TokenKind},
+    parse, SyntaxError,
+};
+
+use super::editor::scan_editor_info;
+
+/// The indentation of scripts without a `#!tab` instruction.
+pub const DEFAULT_TAB_WIDTH: usize = 2;
+
+/**
+What the syntax tree tells about the tokens, which the tokens alone don't: which braces delimit blocks
+(rather than dicts or patterns), and which operators and brackets bind tightly to their neighbours.
+Tokens are identified by their (line, column).
+ */
+#[derive(Default)]
+struct Layout {
+    blocks: HashSet<(isize, isize)>,
+    /// no space before these, like the parentheses of a call
+    tight_before: HashSet<(isize, isize)>,
+    /// no space after these, like prefix operators
+    tight_after: HashSet<(isize, isize)>,
+}
+
+/// Computes the `Layout` of a script, finding the tokens of the syntax tree's nodes by their spans.
+struct LayoutBuilder<'a> {
+    /// the tokens the syntax tree is made of, ie. without line breaks and comments
+    tokens: Vec<&'a Token<'a>>,
+    layout: Layout,
+}
+
+impl LayoutBuilder<'_> {
+    /// The first token at or after a position.
+    fn token_from(&self, pos: (isize, isize)) -> Option<(isize, isize)> {
+        let i = self.tokens.partition_point(|token| token.pos() < pos);
+        self.tokens.get(i).map(|token| token.pos())
+    }
+
+    /// The last token before a position.
+    fn token_before(&self, pos: (isize, isize)) -> Option<(isize, isize)> {
+        let i = self.tokens.partition_point(|token| token.pos() < pos);
+        i.checked_sub(1).map(|i| self.tokens[i].pos())
+    }
+
+    fn before(&mut self, token: Option<(isize, isize)>) {
+        self.layout.tight_before.extend(token);
+    }
+
+    fn after(&mut self, token: Option<(isize, isize)>) {
+        self.layout.tight_after.extend(token);
+    }
+}
+
+impl Visitor for LayoutBuilder<'_> {
+    fn visit_block(&mut self, block: &Block) {
+        let braces = [self.token_from(block.span.start), self.token_before(block.span.end)];
+        self.layout.blocks.extend(braces.into_iter().flatten());
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let StmtKind::Func(func) = &stmt.kind {
+            self.before(self.token_from(func.name.span.end));
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        if let PatternKind::Tuple(entries) | PatternKind::List(entries) = &pattern.kind {
+            for entry in entries {
+                if let PatternEntry::Rest(name) = entry {
+                    self.after(self.token_before(name.span.start));
+                }
+            }
+        }
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Call { callee, kwargs, .. } => {
+                self.before(self.token_from(callee.span.end));
+                // keyword arguments are written like `f(sep=', ')`
+                for kwarg in kwargs {
+                    let eq = self.token_from(kwarg.name.span.end);
+                    self.before(eq);
+                    self.after(eq);
+                }
+            }
+            ExprKind::Index { target, .. } => self.before(self.token_from(target.span.end)),
+            ExprKind::Slice { target, start, stop, step } => {
+                let bracket = self.token_from(target.span.end);
+                self.before(bracket);
+                // the colons between the bounds, not those inside of them
+                let bounds: Vec<Span> = [start, stop, step].into_iter().flatten().map(|bound| bound.span).collect();
+                let colons: Vec<_> = self
+                    .tokens
+                    .iter()
+                    .map(|token| (token.kind, token.pos()))
+                    .filter(|&(kind, pos)| {
+                        kind == TokenKind::Colon
+                            && Some(pos) > bracket
+                            && expr.span.contains(pos)
+                            && !bounds.iter().any(|bound| bound.contains(pos))
+                    })
+                    .map(|(_, pos)| pos)
+                    .collect();
+                self.layout.tight_before.extend(&colons);
+                self.layout.tight_after.extend(&colons);
+            }
+            ExprKind::Unary { .. } | ExprKind::Update { postfix: false, .. } => {
+                self.after(self.token_from(expr.span.start))
+            }
+            ExprKind::Update { postfix: true, .. } => self.before(self.token_before(expr.span.end)),
+            ExprKind::List(entries) | ExprKind::Tuple(entries) => {
+                for entry in entries {
+                    if let ListEntry::Spread(value) = entry {
+                        self.after(self.token_before(value.span.start));
+                    }
+                }
+            }
+            ExprKind::Dict(entries) => {
+                for entry in entries {
+                    if let DictEntry::Spread(value) = entry {
+                        self.after(self.token_before(value.span.start));
+                    }
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Whether two tokens written without a space between them would be lexed differently, eg. `!` and `!x`.
+fn would_glue(left: &str, right: &str) -> bool {
+    // the first two characters of the operators longer than one character
+    const PAIRS: &[&str] = &[
+        "&&", "&=", "||", "|=", "!!", "!=", "**", "*=", "++", "+=", "--", "-=", "->", "^=", "/=", "%=", "@=", "==",
+        "<=", ">=", "#!", "#<", "##",
+    ];
+    match (left.chars().last(), right.chars().next()) {
+        (Some(l), Some(r)) => {
+            PAIRS.contains(&format!("{}{}", l, r).as_str())
+                // signs and dots become part of numbers, and `~` starts a path
+                || ("+-.".contains(l) && r.is_ascii_digit())
+                || (l.is_ascii_digit() && r == '.')
+                || l == '~'
+        }
+        _ => false,
+    }
+}
+
+/// Re-emits the tokens of a script, deciding on the whitespace between them.
+struct Printer<'a> {
+    layout: &'a Layout,
+    source_lines: Vec<&'a str>,
+    unit: String,
+    out: String,
+    /// the nesting of blocks
+    depth: usize,
+    /// the brackets opened inside the current line of code, which indent its continuation lines
+    open: usize,
+    /// line breaks since the last token; a semicolon counts as one
+    breaks: usize,
+    /// the last token written
+    prev: Option<Token<'a>>,
+    prev_end_line: isize,
+}
+
+impl<'a> Printer<'a> {
+    fn is_block(&self, token: &Token) -> bool {
+        self.layout.blocks.contains(&token.pos())
+    }
+
+    fn needs_space(&self, token: &Token) -> bool {
+        let Some(prev) = &self.prev else { return false };
+        if token.is_comment() {
+            return true;
+        }
+        let tight = self.layout.tight_after.contains(&prev.pos())
+            || self.layout.tight_before.contains(&token.pos())
+            || matches!(
+                token.kind,
+                TokenKind::Comma | TokenKind::Colon | TokenKind::Dot | TokenKind::RParen | TokenKind::RBrack
+            )
+            || (token.kind == TokenKind::RBrace && !self.is_block(token))
+            || matches!(prev.kind, TokenKind::LParen | TokenKind::LBrack | TokenKind::Dot)
+            || (prev.kind == TokenKind::LBrace && !self.is_block(prev));
+        !tight || would_glue(prev.text, token.text)
+    }
+
+    /// Start a new line for a token, indented by the blocks and open brackets it is in.
+    fn newline(&mut self, count: usize, token: &Token) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        for _ in 0..count {
+            self.out.push('\n');
+        }
+        let closes = matches!(token.kind, TokenKind::RParen | TokenKind::RBrack)
+            || (token.kind == TokenKind::RBrace && !self.is_block(token));
+        let levels = self.depth + self.open - usize::from(closes && self.open > 0);
+        self.out.push_str(&self.unit.repeat(levels));
+    }
+
+    fn write(&mut self, token: &Token<'a>, text: &str) {
+        let block_open = matches!(&self.prev, Some(prev) if prev.kind == TokenKind::LBrace && self.is_block(prev));
+        let breaks = if self.out.is_empty() {
+            0
+        } else if block_open || (token.kind == TokenKind::RBrace && self.is_block(token)) {
+            // no empty lines at the start or end of a block
+            1
+        } else if self.open > 0 && token.line > self.prev_end_line {
+            // inside brackets, line breaks aren't tokens, but the ones in the source are kept
+            1
+        } else {
+            // at most one empty line in a row
+            self.breaks.min(2)
+        };
+        if breaks > 0 {
+            self.newline(breaks, token);
+        } else if self.needs_space(token) {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.breaks = 0;
+        self.prev = Some(*token);
+        self.prev_end_line = token.end().0;
+    }
+
+    /// A static instruction is kept as written, from its `#!` to the end of the line.
+    fn static_inst(&mut self, token: &Token<'a>) {
+        let line = self.source_lines.get(token.line as usize - 1).copied().unwrap_or("");
+        let rest: String = line.chars().skip(token.column as usize + token.text.chars().count()).collect();
+        let text = match token.kind {
+            TokenKind::StaticExec => format!("{} {}", token.text, rest.trim()),
+            _ => format!("{}{}", token.text, rest.trim()),
+        };
+        self.write(token, text.trim_end());
+    }
+
+    fn print(mut self, tokens: &[Token<'a>]) -> String {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            i += 1;
+            match token.kind {
+                TokenKind::Nl => self.breaks += 1,
+                TokenKind::Semicolon => self.breaks = self.breaks.max(1),
+                TokenKind::StaticInst | TokenKind::StaticExec => {
+                    self.static_inst(token);
+                    // the rest of the line is part of the instruction
+                    while i < tokens.len() && tokens[i].kind != TokenKind::Nl {
+                        i += 1;
+                    }
+                }
+                TokenKind::LBrace if self.is_block(token) => {
+                    let next = tokens[i..]
+                        .iter()
+                        .position(|t| !matches!(t.kind, TokenKind::Nl | TokenKind::Semicolon))
+                        .map(|offset| i + offset);
+                    match next {
+                        // an empty block is written as `{}`
+                        Some(close) if self.is_block(&tokens[close]) && tokens[close].kind == TokenKind::RBrace => {
+                            self.write(token, "{}");
+                            self.prev = Some(tokens[close]);
+                            i = close + 1;
+                        }
+                        _ => {
+                            self.write(token, "{");
+                            self.depth += 1;
+                        }
+                    }
+                }
+                TokenKind::RBrace if self.is_block(token) => {
+                    self.depth = self.depth.saturating_sub(1);
+                    self.write(token, "}");
+                }
+                TokenKind::DocComment => {
+                    // line doc comments end with their line break
+                    self.write(token, token.text.trim_end());
+                    self.breaks = 1;
+                }
+                _ => {
+                    let text = if token.is_comment() { token.text.trim_end() } else { token.text };
+                    self.write(token, text);
+                    match token.kind {
+                        TokenKind::LParen | TokenKind::LBrack | TokenKind::LBrace => self.open += 1,
+                        TokenKind::RParen | TokenKind::RBrack | TokenKind::RBrace => {
+                            self.open = self.open.saturating_sub(1)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let mut out = self.out.trim_end().to_owned();
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/**
+Format a script in the canonical style: one statement per line (no semicolons), blocks indented by the
+width given by `#!tab` ([`DEFAULT_TAB_WIDTH`] without one), single spaces around binary operators and
+at most one empty line in a row. Comments, doc comments and static instructions are kept; so are the line
+breaks inside brackets. Scripts with syntax errors are not formatted.
+ */
+pub fn format_source(source: &str) -> Result<String, Vec<SyntaxError>> {
+    let file = parse(source)?;
+
+    let tokens: Vec<_> = Lexer::new(source).filter(|token| token.kind != TokenKind::Eof).collect();
+
+    let mut builder = LayoutBuilder {
+        tokens: tokens.iter().filter(|token| !token.is_comment() && token.kind != TokenKind::Nl).collect(),
+        layout: Layout::default(),
+    };
+    walk_file(&mut builder, &file);
+    let layout = builder.layout;
+
+    let tab_width = scan_editor_info(source).tab_width.unwrap_or(DEFAULT_TAB_WIDTH);
+    let printer = Printer {
+        layout: &layout,
+        source_lines: source.lines().collect(),
+        unit: " ".repeat(tab_width),
+        out: String::new(),
+        depth: 0,
+        open: 0,
+        breaks: 0,
+        prev: None,
+        prev_end_line: 0,
+    };
+    Ok(printer.print(&tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_style() {
+        let source = "\n\
+            local   x=1;local y = [ 1,2 , *rest ]\n\
+            \n\
+            \n\
+            if !done then {x+=1} else {\n\
+            \n\
+            f( x , sep='-' )[1 :2]\n\
+            }\n\
+            {\n\
+            }\n";
+        let expected = "local x = 1\n\
+            local y = [1, 2, *rest]\n\
+            \n\
+            if !done then {\n\
+            \x20\x20x += 1\n\
+            } else {\n\
+            \x20\x20f(x, sep='-')[1:2]\n\
+            }\n\
+            {}\n";
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn comments_and_tab_width() {
+        let source = "#!tab 4\n\
+            ## The answer.\n\
+            const answer = 42 # not computed\n\
+            {\n\
+            # inside\n\
+            local list = [1,  #< one >#\n\
+            2]\n\
+            }\n";
+        let expected = "#!tab 4\n\
+            ## The answer.\n\
+            const answer = 42 # not computed\n\
+            {\n\
+            \x20\x20\x20\x20# inside\n\
+            \x20\x20\x20\x20local list = [1, #< one >#\n\
+            \x20\x20\x20\x20\x20\x20\x20\x202]\n\
+            }\n";
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let errors = format_source("local = 1\n").unwrap_err();
+        assert_eq!(errors[0].line, 1);
+    }
+}
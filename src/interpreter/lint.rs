@@ -0,0 +1,523 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::parser::{ast::*, parse, SyntaxError};
+
+use super::{
+    compiler::CompilingVisitor,
+    static_inst::{StaticArgs, StrictFlags},
+};
+
+/// The checks of the linter. Each has an ID, which `#!allow` uses to suppress it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// assigning a name that isn't declared, which may capture a global of that name
+    ImplicitGlobal,
+    /// declaring a local with the name of a variable from an enclosing scope
+    ShadowedLocal,
+    UnusedImport,
+    UnusedLocal,
+    /// `unloc` of a global or undeclared name
+    UnlocNonLocal,
+    ConstAssign,
+    /// statements after `break` or `continue`
+    UnreachableCode,
+    /// `export` inside a function while `#!strict import` is set
+    ExportInFunction,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::ImplicitGlobal,
+        Rule::ShadowedLocal,
+        Rule::UnusedImport,
+        Rule::UnusedLocal,
+        Rule::UnlocNonLocal,
+        Rule::ConstAssign,
+        Rule::UnreachableCode,
+        Rule::ExportInFunction,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::ImplicitGlobal => "implicit_global",
+            Rule::ShadowedLocal => "shadowed_local",
+            Rule::UnusedImport => "unused_import",
+            Rule::UnusedLocal => "unused_local",
+            Rule::UnlocNonLocal => "unloc_non_local",
+            Rule::ConstAssign => "const_assign",
+            Rule::UnreachableCode => "unreachable_code",
+            Rule::ExportInFunction => "export_in_function",
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.id() == s)
+            .ok_or_else(|| format!("unknown lint rule `{}`", s))
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// The rules of `#!allow`, like `#!allow unused_local shadowed_local`.
+impl StaticArgs for Vec<Rule> {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        match args {
+            [] => Err("expected the rules to allow".to_owned()),
+            _ => args.iter().map(|arg| arg.parse()).collect(),
+        }
+    }
+}
+
+/// A problem found by the linter, located at the line (counting from 1) and column (counting from 0) where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub msg: String,
+    pub line: isize,
+    pub column: isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VarKind {
+    Local,
+    Const,
+    /// exported variables are used by whoever imports the script
+    Exported,
+    Import,
+    /// a variable assigned without being declared
+    Implicit,
+    Global,
+    Function,
+    /// a function parameter or script argument
+    Param,
+}
+
+struct Var {
+    name: String,
+    kind: VarKind,
+    line: isize,
+    used: bool,
+}
+
+struct LintScope {
+    vars: Vec<Var>,
+    /// whether this is the scope of a function body
+    function: bool,
+    /**
+    Names used while this scope was open that weren't declared yet. A function body can use a variable
+    that's only declared after the function, so these count as uses of later declarations.
+     */
+    unresolved: HashSet<String>,
+}
+
+/// Walks the syntax tree, keeping track of the scopes like `SymbolCollector` does for the analysis.
+struct Linter {
+    scopes: Vec<LintScope>,
+    lints: Vec<Lint>,
+    strict_import: bool,
+    /// the rules allowed by `#!allow`, with the lines of the statement they apply to
+    allowed: Vec<(Vec<Rule>, isize, isize)>,
+}
+
+impl Linter {
+    fn report(&mut self, rule: Rule, (line, column): (isize, isize), msg: String) {
+        self.lints.push(Lint { rule, msg, line, column });
+    }
+
+    fn push(&mut self, function: bool) {
+        self.scopes.push(LintScope {
+            vars: Vec::new(),
+            function,
+            unresolved: HashSet::new(),
+        });
+    }
+
+    /// Leave a scope, reporting the variables that were never used.
+    fn pop(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for var in scope.vars {
+            // like in many languages, a leading underscore marks a variable as unused on purpose
+            if var.used || scope.unresolved.contains(&var.name) || var.name.starts_with('_') {
+                continue;
+            }
+            match var.kind {
+                VarKind::Local | VarKind::Const => {
+                    let msg = format!("`{}` is never used", var.name);
+                    self.report(Rule::UnusedLocal, (var.line, 0), msg);
+                }
+                VarKind::Import => {
+                    let msg = format!("`{}` is imported, but never used", var.name);
+                    self.report(Rule::UnusedImport, (var.line, 0), msg);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn declare(&mut self, name: String, kind: VarKind, line: isize) {
+        let used = false;
+        self.scopes.last_mut().unwrap().vars.push(Var { name, kind, line, used });
+    }
+
+    /// The innermost declaration of a name, as (scope, index).
+    fn lookup(&self, name: &str) -> Option<(usize, usize)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, scope)| Some((i, scope.vars.iter().rposition(|var| var.name == name)?)))
+    }
+
+    fn use_name(&mut self, name: &str) {
+        match self.lookup(name) {
+            Some((scope, i)) => self.scopes[scope].vars[i].used = true,
+            None => {
+                for scope in &mut self.scopes {
+                    scope.unresolved.insert(name.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Declare a local, reporting if it shadows a variable of an enclosing scope.
+    fn declare_local(&mut self, name: String, kind: VarKind, pos: (isize, isize)) {
+        let (current, outer) = self.scopes.split_last().unwrap();
+        let shadowed = current.vars.iter().all(|var| var.name != name).then(|| {
+            outer
+                .iter()
+                .rev()
+                .find_map(|scope| scope.vars.iter().rev().find(|var| var.name == name))
+                .filter(|var| var.kind != VarKind::Global)
+                .map(|var| var.line)
+        });
+        if let Some(Some(line)) = shadowed {
+            let msg = format!("`{}` shadows the variable declared on line {}", name, line);
+            self.report(Rule::ShadowedLocal, pos, msg);
+        }
+        self.declare(name, kind, pos.0);
+    }
+
+    fn check_export(&mut self, pos: (isize, isize)) {
+        if self.strict_import && self.scopes.iter().any(|scope| scope.function) {
+            let msg = "`export` inside a function: with `#!strict import`, only callers that `import` the call get the value"
+                .to_owned();
+            self.report(Rule::ExportInFunction, pos, msg);
+        }
+    }
+
+    fn finish(mut self) -> Vec<Lint> {
+        while !self.scopes.is_empty() {
+            self.pop();
+        }
+        let allowed = self.allowed;
+        let mut lints: Vec<_> = self
+            .lints
+            .into_iter()
+            .filter(|lint| {
+                !allowed
+                    .iter()
+                    .any(|(rules, start, end)| rules.contains(&lint.rule) && (*start..=*end).contains(&lint.line))
+            })
+            .collect();
+        lints.sort_by_key(|lint| (lint.line, lint.column));
+        lints
+    }
+}
+
+impl Linter {
+    /**
+    Lint the statements of a file or block. Static instructions apply to the statement after them, and code after
+    a `break` or `continue` is reported once.
+     */
+    fn lint_stmts(&mut self, stmts: &[Stmt]) {
+        let mut allow = None;
+        // the statement that makes the rest of the block unreachable, and whether that was reported already
+        let mut jump: Option<(&str, bool)> = None;
+        for stmt in stmts {
+            if let StmtKind::Static { name, args } = &stmt.kind {
+                let inst = CompilingVisitor::static_inst(name, args);
+                // malformed instructions are reported by the compiler
+                match inst.name.as_str() {
+                    "allow" => allow = Vec::<Rule>::parse(&inst.args).ok(),
+                    "strict" => {
+                        if let Ok(flags) = StrictFlags::parse(&inst.args) {
+                            self.strict_import = flags.import;
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(rules) = allow.take() {
+                self.allowed.push((rules, stmt.span.start.0, stmt.span.end.0));
+            }
+            if let Some((keyword, false)) = jump {
+                let msg = format!("unreachable code after `{}`", keyword);
+                self.report(Rule::UnreachableCode, stmt.span.start, msg);
+                jump = Some((keyword, true));
+            }
+            self.visit_stmt(stmt);
+            if jump.is_none() {
+                match stmt.kind {
+                    StmtKind::Break => jump = Some(("break", false)),
+                    StmtKind::Continue => jump = Some(("continue", false)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn lint_funcdef(&mut self, span: Span, func: &FuncDef) {
+        if func.export {
+            self.check_export(span.start);
+        }
+        // declared before the body, so the function can call itself
+        self.declare(func.name.name.clone(), VarKind::Function, span.line());
+        self.push(true);
+        for param in &func.params {
+            if let Some(default) = &param.default {
+                self.visit_expr(default);
+            }
+            self.declare(param.name.name.clone(), VarKind::Param, param.span.line());
+        }
+        self.lint_stmts(&func.body.body);
+        self.pop();
+    }
+
+    fn lint_assignment(&mut self, target: &Expr, op: AssignOp, value: &Expr) {
+        self.visit_expr(value);
+        let ExprKind::Ident(name) = &target.kind else {
+            self.visit_expr(target);
+            return;
+        };
+        let pos = target.span.start;
+        // compound assignments like `+=` read the variable as well
+        let reads = matches!(op, AssignOp::Compound(_));
+        match self.lookup(name) {
+            Some((scope, i)) => {
+                let var = &mut self.scopes[scope].vars[i];
+                var.used |= reads;
+                if var.kind == VarKind::Const {
+                    let msg = format!("`{}` is a constant declared on line {}", name, var.line);
+                    self.report(Rule::ConstAssign, pos, msg);
+                }
+            }
+            None => {
+                let msg = format!(
+                    "`{}` is assigned without being declared: it is the global `{}` if there is one, a new local otherwise",
+                    name, name
+                );
+                self.report(Rule::ImplicitGlobal, pos, msg);
+                self.declare(name.clone(), VarKind::Implicit, pos.0);
+                if reads {
+                    self.use_name(name);
+                }
+            }
+        }
+    }
+
+    fn lint_unloc(&mut self, span: Span, name: &str) {
+        match self.lookup(name) {
+            Some((scope, i)) if self.scopes[scope].vars[i].kind == VarKind::Global => {
+                let msg = format!("`unloc {}` of a global variable", name);
+                self.report(Rule::UnlocNonLocal, span.start, msg);
+            }
+            // the value is used, and the variable is no longer declared
+            Some((scope, i)) => {
+                self.scopes[scope].vars.remove(i);
+            }
+            None => {
+                let msg = format!("`unloc {}` of a variable that isn't declared as a local", name);
+                self.report(Rule::UnlocNonLocal, span.start, msg);
+            }
+        }
+    }
+
+    fn lint_import(&mut self, span: Span, import: &Import) {
+        self.visit_expr(&import.source);
+        let names: Vec<String> = match &import.kind {
+            ImportKind::Module(_) => import.module_name().into_iter().collect(),
+            ImportKind::From(selectors) => selectors
+                .iter()
+                .filter_map(|selector| selector.binding())
+                .map(|name| name.name.clone())
+                .collect(),
+        };
+        for name in names {
+            self.declare(name, VarKind::Import, span.line());
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::For { pattern, iter, body } => {
+                self.visit_expr(iter);
+                // like in the compiler, the loop variables live in a scope around the loop body
+                self.push(false);
+                for name in pattern.names() {
+                    self.declare_local(name.name.clone(), VarKind::Local, pattern.span.start);
+                }
+                self.visit_stmt(body);
+                self.pop();
+            }
+            StmtKind::Var(decl) => {
+                if let Some(value) = &decl.value {
+                    self.visit_expr(value);
+                }
+                let kind = if decl.export {
+                    self.check_export(stmt.span.start);
+                    VarKind::Exported
+                } else if decl.constant {
+                    VarKind::Const
+                } else {
+                    VarKind::Local
+                };
+                for name in decl.pattern.names() {
+                    self.declare_local(name.name.clone(), kind, stmt.span.start);
+                }
+            }
+            StmtKind::Arg { name, default, .. } => {
+                if let Some(default) = default {
+                    self.visit_expr(default);
+                }
+                self.declare(name.name.clone(), VarKind::Param, stmt.span.line());
+            }
+            StmtKind::Export(block) => {
+                self.check_export(stmt.span.start);
+                self.visit_block(block);
+            }
+            StmtKind::Func(func) => self.lint_funcdef(stmt.span, func),
+            StmtKind::Assign { target, op, value } => self.lint_assignment(target, *op, value),
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.push(false);
+        self.lint_stmts(&block.body);
+        self.pop();
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Ident(name) => self.use_name(name),
+            ExprKind::Global(name) => self.declare(name.name.clone(), VarKind::Global, expr.span.line()),
+            ExprKind::Unloc(name) => self.lint_unloc(expr.span, &name.name),
+            ExprKind::Import(import) => self.lint_import(expr.span, import),
+            ExprKind::Dict(entries) => {
+                for entry in entries {
+                    match entry {
+                        // `{a}` is short for `{a: a}`
+                        DictEntry::Shorthand(name) => self.use_name(&name.name),
+                        DictEntry::Pair { key, value } => {
+                            // identifiers as keys are used as strings
+                            if !matches!(key.kind, ExprKind::Ident(_)) {
+                                self.visit_expr(key);
+                            }
+                            self.visit_expr(value);
+                        }
+                        DictEntry::Spread(value) => self.visit_expr(value),
+                    }
+                }
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/**
+Check a script for common pitfalls, like assignments that may capture a global variable. Findings can be
+suppressed for a statement by an `#!allow` with their rule IDs on the line before it. Scripts with syntax errors
+are not linted.
+ */
+pub fn lint_source(source: &str) -> Result<Vec<Lint>, Vec<SyntaxError>> {
+    let file = parse(source)?;
+    let mut linter = Linter {
+        scopes: Vec::new(),
+        lints: Vec::new(),
+        strict_import: false,
+        allowed: Vec::new(),
+    };
+    linter.push(false);
+    linter.lint_stmts(&file.body);
+    Ok(linter.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(source: &str) -> Vec<(Rule, isize)> {
+        lint_source(source).unwrap().iter().map(|lint| (lint.rule, lint.line)).collect()
+    }
+
+    #[test]
+    fn finds_pitfalls() {
+        let source = "import stdout()\n\
+            import unused = ./lib()\n\
+            const answer = 42\n\
+            local a = 1\n\
+            PATH = a\n\
+            answer = 2\n\
+            {\n\
+            local a = 2\n\
+            stdout.println(a)\n\
+            }\n\
+            unloc nope\n\
+            for x in [1, 2] {\n\
+            break\n\
+            stdout.println(x)\n\
+            }\n\
+            local _ignored = 1\n";
+        assert_eq!(
+            rules(source),
+            vec![
+                (Rule::UnusedImport, 2),
+                (Rule::UnusedLocal, 3),
+                (Rule::ImplicitGlobal, 5),
+                (Rule::ConstAssign, 6),
+                (Rule::ShadowedLocal, 8),
+                (Rule::UnlocNonLocal, 11),
+                (Rule::UnreachableCode, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_bindings() {
+        // an alias is the name that has to be used, both for a module and for the names imported from one
+        let source = "import lib = ./lib()\n\
+            import a, b = c from ./other()\n\
+            lib.greet(a, c)\n";
+        assert_eq!(rules(source), vec![(Rule::UnusedImport, 2)]);
+    }
+
+    #[test]
+    fn allow_and_strict_import() {
+        let source = "#!strict import\n\
+            func f() -> none {\n\
+            #!allow unused_local\n\
+            local x = 1\n\
+            export local y = 2\n\
+            }\n\
+            #!allow implicit_global\n\
+            PATH = 1\n\
+            f()\n";
+        assert_eq!(rules(source), vec![(Rule::ExportInFunction, 5)]);
+    }
+}
@@ -0,0 +1,176 @@
+use lazy_static::lazy_static;
+use std::{
+    fmt::{self, Display},
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// How much the interpreter reports, from nothing at all to every instruction it executes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    /// scope changes, declarations, exports and imports
+    Debug,
+    /// every executed instruction
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(LogLevel::Off),
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!("unknown log level `{}`", s)),
+        }
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warning",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        })
+    }
+}
+
+/**
+Where log messages end up. Embedders can implement this to log into their own environment,
+eg. a file in the virtual file system of the game.
+ */
+pub trait LogSink: Send + Sync {
+    fn write(&self, level: LogLevel, msg: &str);
+}
+
+/// Prints messages to stderr, like `warning: ...`.
+pub struct StderrSink;
+impl LogSink for StderrSink {
+    fn write(&self, level: LogLevel, msg: &str) {
+        eprintln!("{}: {}", level, msg);
+    }
+}
+
+/**
+Appends messages to a file, as set by `#!log <level> <path>`. The file is opened once and written through a buffer,
+which is flushed when the sink is dropped, ie. once no script logs to it anymore.
+ */
+pub struct FileSink {
+    path: PathBuf,
+    file: Mutex<BufWriter<File>>,
+}
+impl FileSink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink { path: path.to_owned(), file: Mutex::new(BufWriter::new(file)) })
+    }
+}
+impl LogSink for FileSink {
+    fn write(&self, level: LogLevel, msg: &str) {
+        let result = writeln!(self.file.lock().unwrap(), "{}: {}", level, msg);
+        // logging must not make a script fail, so the message goes to stderr instead
+        if let Err(e) = result {
+            eprintln!("{}: {} (could not write to {}: {})", level, msg, self.path.display(), e);
+        }
+    }
+}
+
+/// Keeps the messages in memory, formatted like `StderrSink` does.
+#[derive(Default)]
+pub struct MemorySink {
+    lines: Mutex<Vec<String>>,
+}
+impl MemorySink {
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+}
+impl LogSink for MemorySink {
+    fn write(&self, level: LogLevel, msg: &str) {
+        self.lines.lock().unwrap().push(format!("{}: {}", level, msg));
+    }
+}
+
+/// A log level together with the sink that receives the messages at or above it.
+#[derive(Clone)]
+pub struct Logger {
+    level: LogLevel,
+    sink: Arc<dyn LogSink>,
+}
+
+impl Logger {
+    pub fn new(level: LogLevel, sink: Arc<dyn LogSink>) -> Self {
+        Logger { level, sink }
+    }
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+    pub fn with_level(&self, level: LogLevel) -> Self {
+        Logger { level, sink: self.sink.clone() }
+    }
+    pub fn with_sink(&self, sink: Arc<dyn LogSink>) -> Self {
+        Logger { level: self.level, sink }
+    }
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level != LogLevel::Off && level <= self.level
+    }
+    /// Log a message, which is only formatted if its level is enabled.
+    pub fn log(&self, level: LogLevel, msg: impl FnOnce() -> String) {
+        if self.enabled(level) {
+            self.sink.write(level, &msg());
+        }
+    }
+    pub fn warn(&self, msg: &str) {
+        self.log(LogLevel::Warn, || msg.to_owned());
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT_LOGGER: RwLock<Logger> = RwLock::new(Logger::new(LogLevel::Warn, Arc::new(StderrSink)));
+}
+
+/// The logger scripts start out with, before any `#!log` instruction. It shows warnings on stderr unless changed.
+pub fn default_logger() -> Logger {
+    DEFAULT_LOGGER.read().unwrap().clone()
+}
+
+/// Replace the logger scripts start out with, eg. to show tracing output for all of them.
+pub fn set_default_logger(logger: Logger) {
+    *DEFAULT_LOGGER.write().unwrap() = logger;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn file_sink() {
+        let path = std::env::temp_dir().join(format!("msh-log-test-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let sink: Arc<dyn LogSink> = Arc::new(FileSink::open(&path).unwrap());
+        let logger = Logger::new(LogLevel::Info, sink);
+        logger.warn("first");
+        logger.log(LogLevel::Debug, || "hidden".to_owned());
+        logger.log(LogLevel::Info, || "second".to_owned());
+        // the messages are written once the last logger using the sink is gone
+        drop(logger);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "warning: first\ninfo: second\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
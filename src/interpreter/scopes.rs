@@ -1,10 +1,10 @@
 use std::{
     collections::HashMap,
     mem,
-    sync::{Arc, RwLock}, num::NonZeroIsize,
+    sync::{Arc, RwLock},
 };
 
-use super::types::{MObjectRef, MFuncResult, string::MStringImpl};
+use super::types::{object::MObjectRef, MFuncResult, string::MStringImpl};
 
 pub type MFieldResult = Result<Option<MObjectRef>, MObjectRef>;
 
@@ -18,9 +18,10 @@ can be used as a placeholder for *lazy evaluation*. This allows builtin support
 object member variable assignments, complete with logic guarding them. It's definitely gonna be useful
 for destructuring assignments, if I decide to go down that route.
  */
-pub trait Field {
+pub trait Field: Send + Sync {
     fn name(&self) -> String;
     fn docstring(&self) -> Option<String>;
+    fn set_docstring(&mut self, docstring: Option<String>);
     fn can_read(&self) -> bool;
     fn can_write(&self) -> bool;
     fn get(&self) -> MFieldResult;
@@ -32,18 +33,18 @@ pub type FieldRef = Arc<RwLock<dyn Field>>;
 pub struct DynamicField {
     name: String,
     docstring: Option<String>,
-    get: Option<dyn Fn() -> MFuncResult>,
-    set: Option<dyn Fn(MObjectRef) -> MFuncResult>,
-    del: Option<dyn Fn() -> MFuncResult>,
+    get: Option<Box<dyn Fn() -> MFuncResult + Send + Sync>>,
+    set: Option<Box<dyn Fn(MObjectRef) -> MFuncResult + Send + Sync>>,
+    del: Option<Box<dyn Fn() -> MFuncResult + Send + Sync>>,
 }
 impl DynamicField {
     pub fn new(
         name: String,
         docstring: Option<String>,
-        get: Option<impl Fn() -> MFuncResult>,
-        set: Option<impl Fn(MObjectRef) -> MFuncResult>,
-        del: Option<impl Fn() -> MFuncResult>,
-    ) {
+        get: Option<Box<dyn Fn() -> MFuncResult + Send + Sync>>,
+        set: Option<Box<dyn Fn(MObjectRef) -> MFuncResult + Send + Sync>>,
+        del: Option<Box<dyn Fn() -> MFuncResult + Send + Sync>>,
+    ) -> Self {
         DynamicField {
             name,
             docstring,
@@ -60,6 +61,9 @@ impl Field for DynamicField {
     fn docstring(&self) -> Option<String> {
         self.docstring.clone()
     }
+    fn set_docstring(&mut self, docstring: Option<String>) {
+        self.docstring = docstring;
+    }
     fn can_read(&self) -> bool {
         self.get.is_some()
     }
@@ -68,20 +72,25 @@ impl Field for DynamicField {
     }
     fn get(&self) -> MFieldResult {
         match self.get {
-            Some(func) => func().map(|v| Some(v)),
-            None => None
+            Some(ref func) => func().map(Some),
+            None => Err(MStringImpl::from(format!("field `{}` is not readable", self.name)).wrap()),
         }
     }
-    fn set(&mut self, new_value: MObjectRef) -> MFieldResult {
+    fn set(&mut self, new_value: Option<MObjectRef>) -> MFieldResult {
+        // assigning nothing is the same as deleting the field
+        let new_value = match new_value {
+            Some(v) => v,
+            None => return self.del(),
+        };
         match self.set {
-            Some(func) => func(new_value).map(|v| Some(v)),
-            None => None
+            Some(ref func) => func(new_value).map(Some),
+            None => Err(MStringImpl::from(format!("field `{}` is readonly", self.name)).wrap()),
         }
     }
-    fn del(&self) -> MFieldResult {
+    fn del(&mut self) -> MFieldResult {
         match self.del {
-            Some(func) => func().map(|v| Some(v)),
-            None => None
+            Some(ref func) => func().map(Some),
+            None => Err(MStringImpl::from(format!("field `{}` cannot be deleted", self.name)).wrap()),
         }
     }
 }
@@ -97,7 +106,7 @@ TODO: Variables for general values NEED to store the docstring in the annotation
 I believe it makes sense to add those docstrings both to the annotations AND the function itself.
 */
 pub struct StaticField {
-    name: String,
+    name: Option<String>,
     docstring: Option<String>,
     value: Option<MObjectRef>,
     readonly: bool
@@ -119,11 +128,14 @@ impl StaticField {
 }
 impl Field for StaticField {
     fn name(&self) -> String {
-        self.name.as_ref().map(|o| o.to_owned())
+        self.name.clone().unwrap_or_default()
     }
     fn docstring(&self) -> Option<String> {
         self.docstring.as_ref().map(|o| o.to_owned())
     }
+    fn set_docstring(&mut self, docstring: Option<String>) {
+        self.docstring = docstring;
+    }
 
     fn can_read(&self) -> bool {
         true
@@ -133,30 +145,38 @@ impl Field for StaticField {
     }
 
     fn get(&self) -> MFieldResult {
+        // unlike a mutable variable, a constant has no meaningful value before it's assigned
+        if self.readonly && self.value.is_none() {
+            return Err(MStringImpl::from(format!(
+                "readonly field `{}` was read before being initialized",
+                self.name()
+            )).wrap());
+        }
         Ok(self.value.clone())
     }
     fn set(
         &mut self,
-        value: MObjectRef,
+        value: Option<MObjectRef>,
     ) -> MFieldResult {
         // if the field is readonly, we'll allow setting it for the first time and never again after that.
         if self.readonly && self.value.is_some() {
-            return Err(MStringImpl::from("readonly fields can only be assigned once").wrap());
+            return Err(MStringImpl::from(format!(
+                "readonly field `{}` can only be assigned once",
+                self.name()
+            )).wrap());
         }
         Ok(mem::replace(&mut self.value, value))
     }
-    fn del(&mut self) {
+    fn del(&mut self) -> MFieldResult {
         if self.readonly {
-            return Err(MStringImpl::from("readonly fields cannot be deleted").wrap());
+            return Err(MStringImpl::from(format!(
+                "readonly field `{}` cannot be deleted",
+                self.name()
+            )).wrap());
         }
-        Ok(mem::replace(&mut self.value, None))
+        Ok(self.value.take())
     }
 }
-// impl StaticField {
-//     pub fn set_docstring(&mut self, docstr: Option<impl Into<String>>) {
-//         self.docstring = docstr.map(|o| o.into());
-//     }
-// }
 
 /** Describing the different ways a variable can be declared */
 pub enum VarScopeRefType {
@@ -164,6 +184,10 @@ pub enum VarScopeRefType {
     LocalValue(FieldRef),
     /// Never actually used: just a marker to signify the field being declared in `get_behavior`
     Local,
+    /// The variable is a locally declared constant, held in a readonly field that can only be assigned once.
+    ConstValue(FieldRef),
+    /// Like `Local`, a marker for declaring a constant.
+    Const,
     /// The variable explicitly defers to the global scope.
     Global,
     /// The variable isn't defined in this scope, so any access implicitly propagates.
@@ -217,6 +241,11 @@ impl VarScope {
         }
     }
 
+    /// The names of the variables declared directly in this scope, eg. to offer them for completion.
+    pub fn names(&self) -> Vec<String> {
+        self.variables.keys().cloned().collect()
+    }
+
     /**
     Get the field under the specified name in the scope. If the field isn't declared or defers to global,
     the call is propagated upwards in the scope tree.
@@ -226,8 +255,8 @@ impl VarScope {
     the scope is needed, use `get_behavior`, `is_declared` or `has_value` instead.
     */
     pub fn get(&self, id: &str) -> Option<FieldRef> {
-        return match self.variables.get(id) {
-            Some(VarScopeRefType::LocalValue(o)) => Some(o.clone()),
+        match self.variables.get(id) {
+            Some(VarScopeRefType::LocalValue(o)) | Some(VarScopeRefType::ConstValue(o)) => Some(o.clone()),
             // Propagate is never actually used, but a key not being present implicitly behaves the same way.
             Some(VarScopeRefType::Propagate) | None => self
                 .parent
@@ -237,10 +266,10 @@ impl VarScope {
                 .global
                 .as_ref()
                 .and_then(|o| o.as_ref().read().unwrap().get(id).clone()),
-            Some(VarScopeRefType::Local) => {
+            Some(VarScopeRefType::Local) | Some(VarScopeRefType::Const) => {
                 panic!("this variant shouldn't actually be used internally")
             }
-        };
+        }
     }
     /**
     Indicates how a variable's value can be found. Possible values are:
@@ -253,14 +282,15 @@ impl VarScope {
     encapsulated value is costly, we simplify that option to `Local`.
     */
     pub fn get_behavior(&self, id: &str) -> VarScopeRefType {
-        return match self.variables.get(id) {
-            Some(VarScopeRefType::LocalValue(_)) | Some(VarScopeRefType::Local) => {
-                VarScopeRefType::Local
-            }
+        match self.variables.get(id) {
+            Some(VarScopeRefType::LocalValue(_))
+            | Some(VarScopeRefType::Local)
+            | Some(VarScopeRefType::ConstValue(_))
+            | Some(VarScopeRefType::Const) => VarScopeRefType::Local,
             // Propagate is never actually used, but a key not being present implicitly behaves the same way.
             Some(VarScopeRefType::Propagate) | None => VarScopeRefType::Propagate,
             Some(VarScopeRefType::Global) => VarScopeRefType::Global,
-        };
+        }
     }
 
     /**
@@ -270,30 +300,43 @@ impl VarScope {
     However this only happens if `#!strict assign` isn't set; if it is the function simply returns `false`.
     It returns `true` iff the value was correctly assigned, which is true in every other case.
      */
+    #[allow(clippy::result_unit_err)]
     pub fn get_or_declare(&mut self, id: &str) -> Result<FieldRef, ()> {
         if self.get(id).is_none() && !self.strict_assign {
-            self.declare(id, VarScopeRefType::Local);
+            self.declare(id, VarScopeRefType::Local).map_err(|_| ())?;
         }
         self.get(id).ok_or(())
     }
 
+    /// Whether the variable is declared as a constant in this very scope.
+    pub fn is_const(&self, id: &str) -> bool {
+        matches!(self.variables.get(id), Some(VarScopeRefType::ConstValue(_)))
+    }
+
     /**
     Declare a new variable in the scope; can also redeclare existing variables,
     and undeclare variables if `Propagate` is specified. An optional type hint can be provided.
+
+    Constants are the exception: once declared, they can't be redeclared, undeclared or
+    moved to the global scope, so this fails with an error message.
      */
-    pub fn declare(&mut self, id: &str, variant: VarScopeRefType) {
+    pub fn declare(&mut self, id: &str, variant: VarScopeRefType) -> Result<(), String> {
+        if self.is_const(id) {
+            return Err(match variant {
+                VarScopeRefType::Global => format!("constant `{}` cannot be moved to the global scope", id),
+                VarScopeRefType::Propagate => format!("constant `{}` cannot be undeclared", id),
+                _ => format!("constant `{}` cannot be redeclared", id),
+            });
+        }
+        let field = |readonly| {
+            Arc::new(RwLock::new(StaticField::new(Some(id.to_owned()), None, None, readonly))) as FieldRef
+        };
         match variant {
-            VarScopeRefType::Local => self.variables.insert(
-                id.to_owned(),
-                VarScopeRefType::LocalValue(Arc::new(RwLock::new(StaticField::new(
-                    Some(id.to_owned()),
-                    None,
-                    None,
-                    false,
-                )))),
-            ),
+            VarScopeRefType::Local => self.variables.insert(id.to_owned(), VarScopeRefType::LocalValue(field(false))),
+            VarScopeRefType::Const => self.variables.insert(id.to_owned(), VarScopeRefType::ConstValue(field(true))),
             VarScopeRefType::Propagate => self.variables.remove(id),
             _ => self.variables.insert(id.to_owned(), variant),
         };
+        Ok(())
     }
 }
@@ -3,312 +3,652 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-use super::{types_dyn::{error::MshBaseError, none::MshNoneValue, BinaryOperator, MshValue, string::MshString}};
-use crate::parser::mshparser::MshParserContext;
+use super::{
+    log::{self, FileSink, LogLevel, Logger},
+    scopes::{FieldRef, StaticField, VarScope, VarScopeRefType},
+    types::{
+        boolean::MBoolImpl,
+        builtin::{help_text, BUILTINS},
+        dict::MDictImpl,
+        list::MListImpl,
+        error::MErrorImpl,
+        function::{FunctionCode, MFunctionImpl},
+        module::MModuleImpl,
+        none::MNone,
+        object::{cast, deep_clone, CloneMemo, MObject, MObjectRef},
+        path::MPathImpl,
+        tuple::MTupleImpl,
+        string::MStringImpl,
+        BinaryOperator, MFuncResult, UnaryOperator,
+    },
+};
 
+/**
+The instructions executed by the `StackMachine`. Besides the usual value stack, the machine
+holds a stack of *fields*: these are the lazily evaluated places (see `scopes::Field`) that
+assignments write to. This way an assignment target like `a.b[c]` is only evaluated once,
+even if it's read and written again (as in `a.b[c] += 1`).
+ */
 #[derive(Debug)]
 pub enum Statement {
-    LoadStatic(Arc<RwLock<dyn MshValue>>),
+    LoadStatic(MObjectRef),
     BinOperator(BinaryOperator),
+    UnOperator(UnaryOperator),
     LoadScope(String),
     LoadGlobal(String),
     StoreScope(String),
     StoreGlobal(String),
     Dot(String),
     Index,
-    Call,
-}
-
-pub enum ScopeReference {
-    /// The variable is locally declared but not yet assigned.
-    Local,
-    /// The variable is locally declared and assigned.
-    LocalValue(Arc<RwLock<dyn MshValue>>),
-    /// The variable explicitly defers to the global scope.
-    Global,
-    /// The variable isn't defined in this scope, so any access implicitly propagates.
-    /// (not used internally)
-    Propagate,
-}
-
-/// A container representation of variable scopes, for use in the interpreter AND a potential typechecker.
-pub struct VarScope {
-    /// this is only zero for the global scope itself
-    pub global: Option<Arc<RwLock<Self>>>,
-    /// any non-global scope must have a parent, this can also be the global scope
-    parent: Option<Arc<RwLock<Self>>>,
-    /// the values consist of a behavior specifier (and usually an encapsulated value),
-    /// combined with an optional type hint.
-    values: HashMap<String, (ScopeReference, Option<Arc<RwLock<dyn MshValue>>>)>,
-    /// the scope needs to be informed of the `#!strict assign` setting in the interpreter
-    pub strict_assign: bool,
-}
-
-impl VarScope {
-    pub fn find_global_scope(scope: Arc<RwLock<Self>>) -> Arc<RwLock<Self>> {
-        scope
-            .as_ref()
-            .read()
-            .unwrap()
-            .global
-            .clone()
-            .unwrap_or(scope.clone())
-    }
-
-    /// Create a new global scope as a root for the scope tree
-    pub fn new_global(strict_assign: bool) -> Self {
-        VarScope {
-            global: None,
-            parent: None,
-            values: HashMap::new(),
-            strict_assign,
-        }
-    }
     /**
-    Create a new child scope under the specified scope tree.
-
-    Each local scope holds a shortcut reference to the global root scope,
-    which is automatically obtained from the parent (i.e. reparenting a scope is not possible)
-    */
-    pub fn new_local(parent: Arc<RwLock<Self>>, strict_assign: bool) -> Self {
-        VarScope {
-            // if a global scope was specified, use that.
-            // if not, reuse the paren't global scope (if present).
-            global: Some(Self::find_global_scope(parent.clone())),
-            parent: Some(parent.clone()),
-            values: HashMap::new(),
-            strict_assign,
-        }
-    }
-
+    Pop three values `start`, `stop` and `step` (`none` where omitted) and an object,
+    and push `obj[start:stop:step]`.
+     */
+    Slice,
     /**
-    Get the value of a variable in the scope. If the variable isn't declared or defers to global,
-    the call is propagated upwards in the scope tree.
-
-    This function always returns a value according to the defined algorithm,
-    and `None` even if a variable isn't declared. if raw information about
-    the scope is needed, use `get_behavior`, `is_declared` or `has_value` instead.
-    */
-    pub fn get(&self, id: &str) -> Arc<RwLock<dyn MshValue>> {
-        return match self.values.get(id) {
-            Some((ScopeReference::LocalValue(o), _)) => o.clone(),
-            // Propagate is never actually used, but a key not being present implicitly behaves the same way.
-            Some((ScopeReference::Propagate, _)) | None => match &self.parent {
-                Some(o) => o.as_ref().read().unwrap().get(id),
-                None => MshNoneValue::get(),
-            },
-            Some((ScopeReference::Global, _)) => match &self.global {
-                Some(o) => o.as_ref().read().unwrap().get(id),
-                None => MshNoneValue::get(),
-            },
-            Some((ScopeReference::Local, _)) => MshNoneValue::get(),
-        };
-    }
+    Call a function with the given number of positional arguments, followed by keyword arguments
+    with the given names. The arguments are popped in reverse order, then the function itself.
+     */
+    Call(usize, Vec<String>),
     /**
-    Indicates how a variable's value can be found. Possible values are:
-
-    * `Local`: the variable is locally declared, assigned or not
-    * `Global`: the variable explicitly defers to the global scope
-    * `Propagate`: the variable isn't defined, so it implicitly defers up the scope tree
-
-    Note that `LocalValue` is not a possible return value: because using `clone` to return the
-    encapsulated value is costly, we simplify that option to `Local`.
-    */
-    pub fn get_behavior(&self, id: &str) -> ScopeReference {
-        return match self.values.get(id) {
-            Some((ScopeReference::LocalValue(_), _)) | Some((ScopeReference::Local, _)) => {
-                ScopeReference::Local
-            }
-            // Propagate is never actually used, but a key not being present implicitly behaves the same way.
-            Some((ScopeReference::Propagate, _)) | None => ScopeReference::Propagate,
-            Some((ScopeReference::Global, _)) => ScopeReference::Global,
-        };
-    }
+    Pop one value for each entry and build a list from them, in order.
+    Entries marked `true` are spreads (`*xs`), which are unpacked into the list.
+     */
+    BuildList(Vec<bool>),
+    /// Like `BuildList`, but builds a tuple.
+    BuildTuple(Vec<bool>),
     /**
-    Whether the variable is declared in the scope tree, according to the defined algorithm.
-    Note that this function returns `true` if the variable isn't defined here but in a parent scope,
-    and it returns `false` if the variable is declared as deferring to a nonexistent global variable.
+    Build a dict from the entries on the stack, in order. Entries marked `true` are spreads (`**d`)
+    and take one value, the others are key-value pairs and take two.
      */
-    pub fn is_declared(&self, id: &str) -> bool {
-        return match self.values.get(id) {
-            Some((ScopeReference::Local, _)) | Some((ScopeReference::LocalValue(_), _)) => true,
-            Some((ScopeReference::Global, _)) => match &self.global {
-                Some(o) => o.as_ref().read().unwrap().is_declared(id),
-                None => false,
-            },
-            Some((ScopeReference::Propagate, _)) | None => match &self.parent {
-                Some(o) => o.as_ref().read().unwrap().is_declared(id),
-                None => false,
-            },
-        };
-    }
+    BuildDict(Vec<bool>),
+    /// Declare a new local variable in the current scope, without assigning a value.
+    DeclareLocal(String),
+    /// Declare a constant in the current scope, which can only be assigned once.
+    DeclareConst(String),
     /**
-    Whether the variable has a value in the scope tree. If `is_declared()==false` this is also `false`,
-    but the converse isn't necessarily true: a variable can be initially declared without having received a value yet.
+    Make a variable refer to the global scope from the current scope on. If it's declared locally,
+    its value is moved to the global variable first. In the global scope itself this does nothing.
      */
-    pub fn has_value(&self, id: &str) -> bool {
-        return match self.values.get(id) {
-            Some((ScopeReference::Local, _)) => false,
-            Some((ScopeReference::LocalValue(_), _)) => true,
-            Some((ScopeReference::Global, _)) => match &self.global {
-                Some(o) => o.as_ref().read().unwrap().has_value(id),
-                None => false,
-            },
-            Some((ScopeReference::Propagate, _)) | None => match &self.parent {
-                Some(o) => o.as_ref().read().unwrap().has_value(id),
-                None => false,
-            },
-        };
-    }
+    DeclareGlobal(String),
+    /// Undeclare a local variable and push its value. If it's not declared locally, warn and push its value anyway.
+    Unloc(String),
+    /// Attach a docstring to a variable visible from the current scope.
+    SetDoc(String, String),
+    /// Turn `#!strict assign` on or off for the current scope and the scopes it encloses from now on.
+    StrictAssign(bool),
+    /// Change the log level and/or log file for the rest of the script, as set by `#!log`.
+    SetLog(Option<LogLevel>, Option<String>),
     /**
-    Get the type hint for a variable, if it has been declared with one.
-    TODO: can global variables be declared with a type hint that differs from their global definition?
-    It could make sense if global variables are always typeless, or if one can't rely on their type hints well...
+    Pop the topmost field and push what `help` shows for it: the field's docstring,
+    or else the docstring of its value (or `none` if neither has one).
      */
-    pub fn get_type_hint(&self, id: &str) -> Option<Arc<RwLock<dyn MshValue>>> {
-        return match self.values.get(id) {
-            Some((ScopeReference::Local, o)) | Some((ScopeReference::LocalValue(_), o)) => {
-                o.clone()
-            }
-            Some((ScopeReference::Global, o)) => o.clone().or(match &self.global {
-                Some(o) => o.as_ref().read().unwrap().get_type_hint(id),
-                None => None,
-            }),
-            Some((ScopeReference::Propagate, _)) | None => match &self.parent {
-                Some(o) => o.as_ref().read().unwrap().get_type_hint(id),
-                None => None,
-            },
-        };
-    }
-
+    Help,
+    /// Push the field for a variable onto the field stack, declaring it if necessary.
+    FieldScope(String),
+    /// Like `FieldScope`, but always refers to the global scope.
+    FieldGlobal(String),
     /**
-    Set the value of a variable in scope, deferring to the parent/global scope if necessary.
-
-    If a variable isn't declared yet, it will be declared locally with the new value.
-    However this only happens if `#!strict assign` isn't set; if it is the function simply returns `false`.
-    It returns `true` iff the value was correctly assigned, which is true in every other case.
+    Push the field for a variable onto the field stack without declaring or assigning anything, for `help(x)`.
+    An undeclared variable gives a field holding the `undeclared` sentinel, or a `NameError` under `#!strict assign`.
      */
-    pub fn set(&mut self, id: &str, val: Arc<RwLock<dyn MshValue>>) -> bool {
-        if self.is_declared(id) {
-            match self.values.get(id) {
-                Some((ScopeReference::LocalValue(_),_)) | Some((ScopeReference::Local,_)) => {
-                    let o = self.values.remove(id).unwrap();
-                    self.values.insert(id.to_owned(), (ScopeReference::LocalValue(val), o.1));
-                },
-                Some((ScopeReference::Global,_)) => match self.global {
-                    Some(ref g) => return g.as_ref().write().unwrap().set(id, val),
-                    None => panic!("the global scope isn't supposed to defer to Global")
-                },
-                Some((ScopeReference::Propagate,_)) | None => return self.parent.as_deref()
-                    .expect("if a propagating variable is declared, then the parent must hold that declaration,
-                    and in particular the parent must exist.").write().unwrap().set(id, val),
-            };
-        } else {
-            if self.strict_assign {
-                // TODO: respect #!strict assign, prevent the user from falling into this case
-                return false;
-            } else {
-                self.declare(id, ScopeReference::LocalValue(val), None)
-            }
-        };
-        return true;
-    }
+    LookupScope(String),
+    /// Like `LookupScope`, but always refers to the global scope.
+    LookupGlobal(String),
+    /// Pop an object from the value stack and push its field `obj.name` onto the field stack.
+    FieldDot(String),
+    /// Pop an index and an object from the value stack and push the field `obj[index]`.
+    FieldIndex,
+    /// Read the value of the topmost field onto the value stack, without consuming the field.
+    ReadField,
+    /// Pop the topmost field and assign it the topmost value.
+    WriteField,
+    /// Discard the topmost value, eg. after an expression statement.
+    Pop,
+    /// Duplicate the topmost value.
+    Dup,
+    /// Enter a new local scope (for blocks).
+    PushScope,
+    /// Leave the current local scope.
+    PopScope,
+    /// Continue execution at the given instruction.
+    Jump(usize),
+    /// Replace the topmost value with an iterator over it (see `MObject::iter`).
+    Iter,
     /**
-    Declare a new variable in the scope; can also redeclare existing variables,
-    and undeclare variables if `Propagate` is specified. An optional type hint can be provided.
+    Advance the iterator on top of the stack, pushing the next element on top of it.
+    Once the iterator is exhausted it is popped, and execution continues at the given instruction.
      */
-    pub fn declare(
-        &mut self,
-        id: &str,
-        value: ScopeReference,
-        type_hint: Option<Arc<RwLock<dyn MshValue>>>,
-    ) {
-        match value {
-            ScopeReference::Propagate => self.values.remove(id),
-            _ => self.values.insert(id.to_owned(), (value, type_hint)),
-        };
-    }
+    Next(usize),
+    /// Pop a value and unpack it into exactly this many values, pushed in reverse so the first one ends up on top.
+    Unpack(usize),
+    /**
+    Like `Unpack`, but for a pattern with a `*rest` capture: the value has to have at least
+    `before + after` elements, and everything in between is collected into a list at that position.
+     */
+    UnpackRest(usize, usize),
+    /// Replace the topmost value with a deep copy of it, for the by-value assignment `a <= b`.
+    CloneValue,
+    /// Convert the topmost value to the builtin type with the given name, for `value as type` (see `object::cast`).
+    Cast(String),
+    /// Continue execution at the given instruction unless the script was invoked in the given way (see `Invocation`).
+    JumpUnlessInvoked(Invocation, usize),
+    /// Add the field of a variable visible from the current scope to the exports of the script.
+    Export(String),
+    /**
+    Pop the default values of the function's parameters (in order, for those that have one) and push
+    a function with the given code, which captures the current scope and logger.
+     */
+    MakeFunction(Arc<FunctionCode>),
+    /**
+    Push the script argument for `arg name`: `kwargs[name]` if it's given, else `args[index]`.
+    If the argument was passed, continue at the given instruction, skipping the code of the default value;
+    otherwise continue with that code. Without a default value, a missing argument is an error, unless the script
+    is being imported: importing passes no arguments, so the variable is just left unassigned.
+     */
+    LoadArg(usize, String, Option<usize>),
+    /**
+    Pop the source of an import and push the module it refers to: a path is imported as a script
+    under the given name (see `MModuleImpl::import_file`), while a module is used as it is.
+    The import is reported to the current logger, which the imported script starts out with.
+     */
+    Import(String),
+    /**
+    Declare a local variable for each export of the module on top of the stack, holding the exported value,
+    for `import * from`. The module stays on the stack.
+     */
+    ImportAll,
+}
+
+/**
+How a script was invoked, which decides whether its `export { }` and `run { }` blocks are executed:
+the former only run when the script is imported, the latter only when it is called.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invocation {
+    /// The script was called, eg. from the shell or as `./script()`.
+    Run,
+    /// The script was imported, to collect its exports.
+    Import,
+}
+
+fn error(msg: &str) -> MObjectRef {
+    MStringImpl::from(msg).wrap()
 }
 
 pub struct StackMachine {}
 
 impl StackMachine {
-    pub fn exec(
-        instructions: &Vec<Statement>,
+    pub fn exec(instructions: &[Statement], scope: Arc<RwLock<VarScope>>) -> MFuncResult {
+        Self::exec_with_logger(instructions, scope, log::default_logger())
+    }
+
+    /// Like `exec`, but reports warnings and tracing output to the given logger until the script changes it.
+    pub fn exec_with_logger(instructions: &[Statement], scope: Arc<RwLock<VarScope>>, logger: Logger) -> MFuncResult {
+        Self::exec_invoked(instructions, scope, logger, Invocation::Run, None)
+    }
+
+    /**
+    Like `exec_with_logger`, but for a script invoked in the given way. The variables it exports
+    are added as fields to `exports`; without it, `export` only declares them as usual.
+     */
+    pub fn exec_invoked(
+        instructions: &[Statement],
         scope: Arc<RwLock<VarScope>>,
-    ) -> Result<Arc<RwLock<dyn MshValue>>, Arc<RwLock<dyn MshValue>>> {
-        let mut value_stack = Vec::<Arc<RwLock<dyn MshValue>>>::new();
+        logger: Logger,
+        invocation: Invocation,
+        exports: Option<&dyn MObject>,
+    ) -> MFuncResult {
+        let mut logger = logger;
+        let mut value_stack = Vec::<MObjectRef>::new();
+        let mut field_stack = Vec::<FieldRef>::new();
+        let mut scope_stack = Vec::<Arc<RwLock<VarScope>>>::new();
+        let mut scope = scope;
         let global_scope = VarScope::find_global_scope(scope.clone());
-        for inst in instructions {
+        let mut pc = 0;
+        while let Some(inst) = instructions.get(pc) {
+            logger.log(LogLevel::Trace, || format!("{:>4}: {:?}", pc, inst));
+            pc += 1;
             match inst {
                 Statement::LoadStatic(val) => value_stack.push(val.clone()),
                 Statement::BinOperator(op) => {
                     let b = value_stack.pop();
                     let a = value_stack.pop();
-                    if let None = a {
-                        Err(Arc::new(RwLock::new(MshBaseError::new(
-                            "not enough arguments for binary operator",
-                        ))) as Arc<RwLock<dyn MshValue>>)?;
-                    }
-                    let (a, b) = (a.unwrap(), b.unwrap());
-                    value_stack.push(a.read().unwrap().binop(b, *op)?);
+                    let (a, b) = match (a, b) {
+                        (Some(a), Some(b)) => (a, b),
+                        _ => Err(error("not enough arguments for binary operator"))?,
+                    };
+                    let result = match op {
+                        BinaryOperator::Eq => MBoolImpl::from(a.equals(&b)?).wrap() as MObjectRef,
+                        BinaryOperator::Neq => MBoolImpl::from(!a.equals(&b)?).wrap(),
+                        BinaryOperator::In => MBoolImpl::from(b.contains(&a)?).wrap(),
+                        _ => a.binop(b, *op)?,
+                    };
+                    value_stack.push(result);
+                }
+                Statement::UnOperator(op) => {
+                    let a = value_stack.pop().ok_or_else(|| error("no argument for unary operator"))?;
+                    value_stack.push(a.unop(*op)?);
+                }
+                Statement::LoadScope(id) => {
+                    value_stack.push(Self::read_variable(&scope, id)?);
                 }
-                Statement::LoadScope(id) => value_stack.push(scope.read().unwrap().get(id)),
                 Statement::LoadGlobal(id) => {
-                    value_stack.push(global_scope.read().unwrap().get(id));
+                    value_stack.push(Self::read_variable(&global_scope, id)?);
                 }
                 Statement::StoreScope(id) => {
-                    scope.write().unwrap().set(
-                        id,
-                        value_stack
-                            .pop()
-                            .ok_or(Arc::new(RwLock::new(MshBaseError::new(
-                                "no value to assign",
-                            ))) as Arc<RwLock<dyn MshValue>>)?,
-                    );
+                    let value = value_stack.pop().ok_or_else(|| error("no value to assign"))?;
+                    Self::declared_field(&scope, id)?.write().unwrap().set(Some(value))?;
                 }
                 Statement::StoreGlobal(id) => {
-                    global_scope.write().unwrap().set(
-                        id,
-                        value_stack
-                            .pop()
-                            .ok_or(Arc::new(RwLock::new(MshBaseError::new(
-                                "no value to assign",
-                            ))) as Arc<RwLock<dyn MshValue>>)?,
-                    );
+                    let value = value_stack.pop().ok_or_else(|| error("no value to assign"))?;
+                    Self::declared_field(&global_scope, id)?.write().unwrap().set(Some(value))?;
                 }
                 Statement::Dot(id) => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to index into"))?;
+                    value_stack.push(Self::read_field(a.dot(id)?)?)
+                }
+                Statement::Index => {
+                    let index = value_stack.pop();
                     let a = value_stack.pop();
-                    if let None = a {
-                        Err(Arc::new(RwLock::new(MshBaseError::new(
-                            "no value to index into",
-                        ))) as Arc<RwLock<dyn MshValue>>)?;
+                    let (a, index) = match (a, index) {
+                        (Some(a), Some(index)) => (a, index),
+                        _ => Err(error("no value to index into"))?,
+                    };
+                    value_stack.push(Self::read_field(a.index(index)?)?)
+                }
+                Statement::Slice => {
+                    let mut parts = Self::pop_n(&mut value_stack, 4)?.into_iter();
+                    let obj = parts.next().unwrap();
+                    // omitted bounds are loaded as `none`
+                    let mut bound = || parts.next().filter(|b| !b.read().unwrap().as_any().is::<MNone>());
+                    let (start, stop, step) = (bound(), bound(), bound());
+                    value_stack.push(obj.slice(start, stop, step)?);
+                }
+                Statement::Call(num_args, kwnames) => {
+                    let kwvalues = Self::pop_n(&mut value_stack, kwnames.len())?;
+                    let args = Self::pop_n(&mut value_stack, *num_args)?;
+                    let func = value_stack.pop().ok_or_else(|| error("no function to call"))?;
+                    let kwargs: HashMap<String, MObjectRef> = kwnames.iter().cloned().zip(kwvalues).collect();
+                    value_stack.push(func.call(args, kwargs)?);
+                }
+                Statement::BuildList(spreads) => {
+                    let items = Self::unpack_entries(&mut value_stack, spreads)?;
+                    value_stack.push(MListImpl::new(items).wrap());
+                }
+                Statement::BuildTuple(spreads) => {
+                    let items = Self::unpack_entries(&mut value_stack, spreads)?;
+                    value_stack.push(MTupleImpl::new(items).wrap());
+                }
+                Statement::BuildDict(spreads) => {
+                    let count = spreads.iter().map(|&spread| if spread { 1 } else { 2 }).sum();
+                    let mut values = Self::pop_n(&mut value_stack, count)?.into_iter();
+                    let dict = MDictImpl::new();
+                    for &spread in spreads {
+                        let value = values.next().unwrap();
+                        if spread {
+                            let entries = MDictImpl::entries_of(&value).ok_or_else(|| {
+                                MStringImpl::from(format!(
+                                    "only dicts can be spread into a dict, not `{}`",
+                                    value.objtype().read().unwrap().name()
+                                ))
+                                .wrap() as MObjectRef
+                            })?;
+                            for (k, v) in entries {
+                                dict.insert(k, v)?;
+                            }
+                        } else {
+                            dict.insert(value, values.next().unwrap())?;
+                        }
+                    }
+                    value_stack.push(dict.wrap());
+                }
+                Statement::DeclareLocal(id) => {
+                    scope.write().unwrap().declare(id, VarScopeRefType::Local).map_err(|e| error(&e))?;
+                    logger.log(LogLevel::Debug, || format!("declared local `{}` at depth {}", id, scope_stack.len()));
+                }
+                Statement::DeclareConst(id) => {
+                    scope.write().unwrap().declare(id, VarScopeRefType::Const).map_err(|e| error(&e))?;
+                    logger.log(LogLevel::Debug, || format!("declared const `{}` at depth {}", id, scope_stack.len()));
+                }
+                Statement::DeclareGlobal(id) => {
+                    logger.log(LogLevel::Debug, || format!("declared global `{}` at depth {}", id, scope_stack.len()));
+                    if !Arc::ptr_eq(&scope, &global_scope) {
+                        let local = Self::local_field(&scope, id);
+                        scope.write().unwrap().declare(id, VarScopeRefType::Global).map_err(|e| error(&e))?;
+                        let value = match local {
+                            Some(field) => field.read().unwrap().get()?,
+                            None => None,
+                        };
+                        if let Some(value) = value {
+                            Self::declared_field(&global_scope, id)?.write().unwrap().set(Some(value))?;
+                        }
                     }
-                    value_stack.push(a.unwrap().read().unwrap().dot(id)?)
                 }
-                Statement::Index => todo!(),
-                Statement::Call => todo!(),
+                Statement::Unloc(id) => match Self::local_field(&scope, id) {
+                    Some(field) => {
+                        let value = field.read().unwrap().get()?;
+                        scope.write().unwrap().declare(id, VarScopeRefType::Propagate).map_err(|e| error(&e))?;
+                        value_stack.push(value.unwrap_or(MNone::unassigned() as MObjectRef));
+                    }
+                    None => {
+                        logger.warn(&format!("`unloc {}` has no effect, since `{}` isn't declared locally", id, id));
+                        value_stack.push(Self::read_variable(&scope, id)?);
+                    }
+                },
+                Statement::SetDoc(id, docstring) => {
+                    let field = scope.read().unwrap().get(id);
+                    let field = field.ok_or_else(|| error(&format!("cannot document undeclared variable `{}`", id)))?;
+                    field.write().unwrap().set_docstring(Some(docstring.clone()));
+                }
+                Statement::StrictAssign(strict) => scope.write().unwrap().strict_assign = *strict,
+                Statement::SetLog(level, file) => {
+                    if let Some(level) = level {
+                        logger = logger.with_level(*level);
+                    }
+                    if let Some(file) = file {
+                        match FileSink::open(file) {
+                            Ok(sink) => logger = logger.with_sink(Arc::new(sink)),
+                            // like failing writes, this shouldn't stop the script; the messages go where they went so far
+                            Err(e) => logger.warn(&format!("could not open log file {}: {}", file, e)),
+                        }
+                    }
+                }
+                Statement::Help => {
+                    let field = field_stack.pop().ok_or_else(|| error("no field to show help for"))?;
+                    let docstring = field.read().unwrap().docstring();
+                    value_stack.push(help_text(docstring, &Self::read_field(field)?));
+                }
+                Statement::FieldScope(id) => field_stack.push(Self::declared_field(&scope, id)?),
+                Statement::FieldGlobal(id) => field_stack.push(Self::declared_field(&global_scope, id)?),
+                Statement::LookupScope(id) => field_stack.push(Self::lookup_field(&scope, id)?),
+                Statement::LookupGlobal(id) => field_stack.push(Self::lookup_field(&global_scope, id)?),
+                Statement::FieldDot(id) => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to index into"))?;
+                    field_stack.push(a.dot(id)?);
+                }
+                Statement::FieldIndex => {
+                    let index = value_stack.pop();
+                    let a = value_stack.pop();
+                    let (a, index) = match (a, index) {
+                        (Some(a), Some(index)) => (a, index),
+                        _ => Err(error("no value to index into"))?,
+                    };
+                    field_stack.push(a.index(index)?);
+                }
+                Statement::ReadField => {
+                    let field = field_stack.last().ok_or_else(|| error("no field to read"))?;
+                    value_stack.push(Self::read_field(field.clone())?);
+                }
+                Statement::WriteField => {
+                    let field = field_stack.pop().ok_or_else(|| error("no field to assign to"))?;
+                    let value = value_stack.pop().ok_or_else(|| error("no value to assign"))?;
+                    field.write().unwrap().set(Some(value))?;
+                }
+                Statement::Pop => {
+                    value_stack.pop().ok_or_else(|| error("no value to discard"))?;
+                }
+                Statement::Dup => {
+                    let a = value_stack.last().ok_or_else(|| error("no value to duplicate"))?;
+                    value_stack.push(a.clone());
+                }
+                Statement::CloneValue => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to clone"))?;
+                    value_stack.push(deep_clone(&a, &mut CloneMemo::new())?);
+                }
+                Statement::Cast(name) => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to cast"))?;
+                    let target = BUILTINS.find_type(name).ok_or_else(|| {
+                        MErrorImpl::name_error(format!("cannot cast to `{}`: there is no such type", name)).wrap()
+                            as MObjectRef
+                    })?;
+                    value_stack.push(cast(&a, &target)?);
+                }
+                Statement::JumpUnlessInvoked(expected, target) => {
+                    if *expected != invocation {
+                        pc = *target;
+                    }
+                }
+                Statement::Export(id) => {
+                    let field = scope.read().unwrap().get(id);
+                    let field = field.ok_or_else(|| error(&format!("cannot export undeclared variable `{}`", id)))?;
+                    if let Some(exports) = exports {
+                        exports.insert_field(field);
+                        logger.log(LogLevel::Debug, || format!("exported `{}`", id));
+                    }
+                }
+                Statement::MakeFunction(code) => {
+                    let num_defaults = code.params.iter().filter(|(_, has_default)| *has_default).count();
+                    let defaults = Self::pop_n(&mut value_stack, num_defaults)?;
+                    let func = MFunctionImpl::new(code.clone(), defaults, scope.clone(), logger.clone());
+                    value_stack.push(func.wrap());
+                }
+                Statement::LoadArg(index, name, skip_default) => match Self::script_arg(&scope, *index, name)? {
+                    Some(value) => {
+                        value_stack.push(value);
+                        if let Some(target) = skip_default {
+                            pc = *target;
+                        }
+                    }
+                    None if skip_default.is_some() => {}
+                    None if invocation == Invocation::Import => value_stack.push(MNone::unassigned() as MObjectRef),
+                    None => Err(error(&format!("missing argument `{}`", name)))?,
+                },
+                Statement::Import(name) => {
+                    let source = value_stack.pop().ok_or_else(|| error("nothing to import"))?;
+                    value_stack.push(Self::import(source, name, &logger)?);
+                }
+                Statement::ImportAll => {
+                    let module = value_stack.last().ok_or_else(|| error("no module to import from"))?;
+                    let exports = match module.read().unwrap().as_any().downcast_ref::<MModuleImpl>() {
+                        Some(module) => module.exports(),
+                        None => Err(error("`import * from` needs a module"))?,
+                    };
+                    for (id, field) in exports {
+                        scope.write().unwrap().declare(&id, VarScopeRefType::Local).map_err(|e| error(&e))?;
+                        let value = field.read().unwrap().get()?;
+                        Self::declared_field(&scope, &id)?.write().unwrap().set(value)?;
+                        logger.log(LogLevel::Debug, || format!("imported `{}`", id));
+                    }
+                }
+                Statement::PushScope => {
+                    let strict_assign = scope.read().unwrap().strict_assign;
+                    let inner = Arc::new(RwLock::new(VarScope::new_local(scope.clone(), strict_assign)));
+                    scope_stack.push(std::mem::replace(&mut scope, inner));
+                    logger.log(LogLevel::Debug, || format!("entered scope at depth {}", scope_stack.len()));
+                }
+                Statement::PopScope => {
+                    scope = scope_stack.pop().ok_or_else(|| error("no scope to leave"))?;
+                    logger.log(LogLevel::Debug, || format!("left scope, back at depth {}", scope_stack.len()));
+                }
+                Statement::Jump(target) => pc = *target,
+                Statement::Iter => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to iterate over"))?;
+                    value_stack.push(a.iter()?);
+                }
+                Statement::Next(end) => {
+                    let iterator = value_stack.last().ok_or_else(|| error("no iterator to advance"))?;
+                    match iterator.next()? {
+                        Some(item) => value_stack.push(item),
+                        None => {
+                            value_stack.pop();
+                            pc = *end;
+                        }
+                    }
+                }
+                Statement::Unpack(n) => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to unpack"))?;
+                    let items = a.unpack()?;
+                    if items.len() != *n {
+                        Err(MStringImpl::from(format!(
+                            "cannot unpack {} values into {} targets",
+                            items.len(),
+                            n
+                        ))
+                        .wrap() as MObjectRef)?;
+                    }
+                    value_stack.extend(items.into_iter().rev());
+                }
+                Statement::UnpackRest(before, after) => {
+                    let a = value_stack.pop().ok_or_else(|| error("no value to unpack"))?;
+                    let mut items = a.unpack()?;
+                    if items.len() < before + after {
+                        Err(MStringImpl::from(format!(
+                            "cannot unpack {} values into at least {} targets",
+                            items.len(),
+                            before + after
+                        ))
+                        .wrap() as MObjectRef)?;
+                    }
+                    let tail = items.split_off(items.len() - after);
+                    let rest = items.split_off(*before);
+                    value_stack.extend(tail.into_iter().rev());
+                    value_stack.push(MListImpl::new(rest).wrap());
+                    value_stack.extend(items.into_iter().rev());
+                }
             }
         }
         if value_stack.len() > 1 {
-            Err(Arc::new(RwLock::new(MshBaseError::new(
-                "too many return values",
-            ))) as Arc<RwLock<dyn MshValue>>)?
+            Err(error("too many return values"))
         } else {
-            Ok(value_stack.pop().unwrap_or(MshNoneValue::get()))
+            Ok(value_stack.pop().unwrap_or(MNone::refer() as MObjectRef))
+        }
+    }
+
+    /// Resolve the source of an import to a module, importing the script a path refers to.
+    fn import(source: MObjectRef, name: &str, logger: &Logger) -> MFuncResult {
+        if source.read().unwrap().as_any().is::<MModuleImpl>() {
+            return Ok(source);
+        }
+        let paths = match source.read().unwrap().as_any().downcast_ref::<MPathImpl>() {
+            Some(path) => path.expand()?,
+            None => {
+                let type_name = source.objtype().read().unwrap().name();
+                return Err(error(&format!("cannot import `{}`: only paths and modules can be imported", type_name)));
+            }
+        };
+        match &paths[..] {
+            [path] => Ok(MModuleImpl::import_file_with_logger(name, path, logger.clone())?),
+            _ => Err(error(&format!("cannot import {} paths at once", paths.len()))),
+        }
+    }
+
+    /**
+    Find the argument at `index` or named `name` in the `args` and `kwargs` a script was called with.
+    A script that wasn't called with arguments (eg. when it's imported) doesn't have them declared.
+     */
+    fn script_arg(scope: &Arc<RwLock<VarScope>>, index: usize, name: &str) -> Result<Option<MObjectRef>, MObjectRef> {
+        let variable = |id: &str| match scope.read().unwrap().get(id) {
+            Some(field) => field.read().unwrap().get(),
+            None => Ok(None),
+        };
+        if let Some(entries) = variable("kwargs")?.as_ref().and_then(MDictImpl::entries_of) {
+            let key = MStringImpl::from(name).wrap() as MObjectRef;
+            for (k, v) in entries {
+                if k.equals(&key)? {
+                    return Ok(Some(v));
+                }
+            }
+        }
+        match variable("args")? {
+            Some(args) if args.read().unwrap().as_any().is::<MListImpl>() => Ok(args.unpack()?.into_iter().nth(index)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Pop the topmost `n` values, returning them in the order they were pushed.
+    fn pop_n(value_stack: &mut Vec<MObjectRef>, n: usize) -> Result<Vec<MObjectRef>, MObjectRef> {
+        if value_stack.len() < n {
+            return Err(error("not enough values on the stack"));
+        }
+        Ok(value_stack.split_off(value_stack.len() - n))
+    }
+
+    /// Pop the entries of a list or tuple literal, unpacking the spread ones.
+    fn unpack_entries(value_stack: &mut Vec<MObjectRef>, spreads: &[bool]) -> Result<Vec<MObjectRef>, MObjectRef> {
+        let mut items = Vec::new();
+        for (value, &spread) in Self::pop_n(value_stack, spreads.len())?.into_iter().zip(spreads) {
+            if spread {
+                items.extend(value.unpack()?);
+            } else {
+                items.push(value);
+            }
         }
+        Ok(items)
+    }
+
+    /// Read a field, giving the `unassigned` sentinel if it has no value.
+    fn read_field(field: FieldRef) -> MFuncResult {
+        Ok(field.read().unwrap().get()?.unwrap_or(MNone::unassigned() as MObjectRef))
+    }
+
+    /**
+    Read a variable. An undeclared variable gives the `undeclared` sentinel, or a `NameError`
+    under `#!strict assign`, where variables have to be declared before they are used.
+     */
+    fn read_variable(scope: &Arc<RwLock<VarScope>>, id: &str) -> MFuncResult {
+        let scope = scope.read().unwrap();
+        match scope.get(id) {
+            Some(field) => Self::read_field(field),
+            None if scope.strict_assign => {
+                Err(MErrorImpl::name_error(format!("variable `{}` is not declared", id)).wrap())
+            }
+            None => Ok(MNone::undeclared() as MObjectRef),
+        }
+    }
+
+    /// Find the field for a variable without declaring it. Undeclared variables behave as in `read_variable`.
+    fn lookup_field(scope: &Arc<RwLock<VarScope>>, id: &str) -> Result<FieldRef, MObjectRef> {
+        let scope = scope.read().unwrap();
+        match scope.get(id) {
+            Some(field) => Ok(field),
+            None if scope.strict_assign => {
+                Err(MErrorImpl::name_error(format!("variable `{}` is not declared", id)).wrap())
+            }
+            None => {
+                let undeclared = Some(MNone::undeclared() as MObjectRef);
+                Ok(Arc::new(RwLock::new(StaticField::new(Some(id.to_owned()), None, undeclared, true))))
+            }
+        }
+    }
+
+    /// The field of a variable declared in this very scope, if any.
+    fn local_field(scope: &Arc<RwLock<VarScope>>, id: &str) -> Option<FieldRef> {
+        let scope = scope.read().unwrap();
+        match scope.get_behavior(id) {
+            VarScopeRefType::Local => scope.get(id),
+            _ => None,
+        }
+    }
+
+    /// Find the field for a variable, implicitly declaring it unless `#!strict assign` forbids that.
+    fn declared_field(scope: &Arc<RwLock<VarScope>>, id: &str) -> Result<FieldRef, MObjectRef> {
+        scope.write().unwrap().get_or_declare(id).map_err(|_| {
+            MStringImpl::from(format!(
+                "variable `{}` was assigned without being declared (#!strict assign)",
+                id
+            ))
+            .wrap() as MObjectRef
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::interpreter::types_dyn::int::MshInt;
-
     use super::*;
+    use crate::interpreter::{
+        compiler::compile,
+        log::MemorySink,
+        types::{builtin::declare_builtins, int::MIntImpl, object::MObjectImpl},
+    };
     use std::vec;
 
     static STRICT_ASSIGN: bool = false;
 
+    fn string(value: &str) -> MObjectRef {
+        MStringImpl::from(value).wrap()
+    }
+
     #[test]
     fn create_scopes() {
         let global_scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
@@ -321,15 +661,15 @@ mod tests {
             STRICT_ASSIGN,
         )));
 
-        assert!(!scope2.as_ref().read().unwrap().is_declared("test"));
-        scope1.as_ref().write().unwrap().declare(
-            "test",
-            ScopeReference::LocalValue(MshNoneValue::get()),
-            None,
-        );
-        assert!(scope2.as_ref().read().unwrap().is_declared("test"));
-        // assert_eq!(scope2.as_ref().read().unwrap().get("test"), MshNoneValue::get());
-        assert!(!global_scope.as_ref().read().unwrap().is_declared("test"));
+        assert!(scope2.as_ref().read().unwrap().get("test").is_none());
+        scope1
+            .as_ref()
+            .write()
+            .unwrap()
+            .declare("test", VarScopeRefType::Local)
+            .unwrap();
+        assert!(scope2.as_ref().read().unwrap().get("test").is_some());
+        assert!(global_scope.as_ref().read().unwrap().get("test").is_none());
     }
 
     #[test]
@@ -338,15 +678,446 @@ mod tests {
         let local_scope = Arc::new(RwLock::new(VarScope::new_local(global_scope.clone(), STRICT_ASSIGN)));
 
         let instructions = vec![
-            Statement::LoadStatic(Arc::new(RwLock::new(MshInt::new(42)))),
+            Statement::LoadStatic(MIntImpl::from(42).wrap()),
             Statement::StoreGlobal("test".to_owned()),
             Statement::LoadScope("test".to_owned()),
         ];
         let res = StackMachine::exec(&instructions, local_scope.clone());
 
-        let local_scope = local_scope.read().unwrap();
-        assert!(local_scope.is_declared("test"));
-        // assert_eq!(local_scope.get("test").read().unwrap(), MshInt::new(42));
-        // assert_eq!(res.unwrap_or_else(|| panic!()).read().unwrap(), MshInt::new(42));
+        assert!(local_scope.read().unwrap().get("test").is_some());
+        assert_eq!(res.ok().unwrap().to_ext_string(0, false).ok(), Some("42".to_owned()));
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let global_scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
+
+        // x = 'a'; x += 'b'; x
+        let instructions = vec![
+            Statement::FieldScope("x".to_owned()),
+            Statement::LoadStatic(string("a")),
+            Statement::WriteField,
+            Statement::FieldScope("x".to_owned()),
+            Statement::ReadField,
+            Statement::LoadStatic(string("b")),
+            Statement::BinOperator(BinaryOperator::Plus),
+            Statement::WriteField,
+            Statement::LoadScope("x".to_owned()),
+        ];
+        let res = StackMachine::exec(&instructions, global_scope.clone());
+        assert_eq!(res.ok().unwrap().to_ext_string(0, false).ok(), Some("ab".to_owned()));
+    }
+
+    #[test]
+    fn containers() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "xs = [1, 2]\n\
+            xs.append(3)\n\
+            ys = [0, *xs][1:]\n\
+            d = {a: xs, **{b: len(ys)}}\n\
+            t = ('x',)\n\
+            result = (d['a'] == ys, 2 in xs, 'b' in d, t * 2, ys[::-1])\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let result = StackMachine::exec(&[Statement::LoadScope("result".to_owned())], global_scope).ok().unwrap();
+        assert_eq!(
+            result.to_ext_string(0, false).ok(),
+            Some("(true, true, true, ('x', 'x'), [3, 2, 1])".to_owned())
+        );
+    }
+
+    #[test]
+    fn for_loops() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "pairs = []\n\
+            for (k, v) in {a: 1, b: 2, c: 3}.items() {\n\
+              pairs.append(k + v)\n\
+            }\n\
+            first = 0\n\
+            for x in [4, 5, 6] { first = x; break }\n\
+            total = 0\n\
+            for i in range(10) {\n\
+              for c in 'xy' { total += 1 }\n\
+              total += i\n\
+            }\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let load = |id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+        assert_eq!(load("pairs"), Some("['a1', 'b2', 'c3']".to_owned()));
+        assert_eq!(load("total"), Some("65".to_owned()));
+        assert_eq!(load("first"), Some("4".to_owned()));
+        // loop variables don't leak out of the loop
+        assert_eq!(load("i"), Some("none".to_owned()));
+    }
+
+    #[test]
+    fn by_value_assignment() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "local a = {xs: [1, 2], t: (3, [4])}\n\
+            local b <= a\n\
+            local c = a\n\
+            b['xs'].append(5)\n\
+            b['t'][1].append(6)\n\
+            c['xs'].append(7)\n\
+            d = 0\n\
+            d <= c['xs']\n\
+            d.append(8)\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let load = |id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+        assert_eq!(load("a"), Some("{'xs': [1, 2, 7], 't': (3, [4])}".to_owned()));
+        assert_eq!(load("b"), Some("{'xs': [1, 2, 5], 't': (3, [4, 6])}".to_owned()));
+        assert_eq!(load("d"), Some("[1, 2, 7, 8]".to_owned()));
+    }
+
+    #[test]
+    fn casts() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "ints = ('42' as int, '-0x1f' as int, 2.9 as int, -2.9 as int, true as int, 7 as int)\n\
+            floats = (3 as float, ' 1.5 ' as float, false as float)\n\
+            strs = (12 as str, [1, 2] as str, 'x' as str)\n\
+            bools = (0 as bool, 0.5 as bool, 'x' as bool, [] as bool, none as bool, {k: 1} as bool)\n\
+            containers = ('a/b' as path, 'ab' as list, {k: 1} as tuple, (1, 2) as list)\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let load = |id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+        assert_eq!(load("ints"), Some("(42, -31, 2, -2, 1, 7)".to_owned()));
+        assert_eq!(load("floats"), Some("(3.0, 1.5, 0.0)".to_owned()));
+        assert_eq!(load("strs"), Some("('12', '[1, 2]', 'x')".to_owned()));
+        assert_eq!(load("bools"), Some("(false, true, true, false, false, true)".to_owned()));
+        assert_eq!(load("containers"), Some("(a/b, ['a', 'b'], ('k',), [1, 2])".to_owned()));
+
+        let error = |source: &str| {
+            let instructions = compile(source).ok().unwrap();
+            StackMachine::exec(&instructions, global_scope.clone()).err().unwrap()
+        };
+        // impossible casts are a `TypeError`, bad values fail in the conversion itself
+        assert_eq!(MErrorImpl::kind_of(&error("[1] as int\n")), Some("TypeError".to_owned()));
+        assert_eq!(MErrorImpl::kind_of(&error("len as bool\n")), Some("TypeError".to_owned()));
+        assert_eq!(error("'abc' as int\n").to_ext_string(0, false).ok(), Some("invalid int format: `abc`".to_owned()));
+        assert_eq!(MErrorImpl::kind_of(&error("1 as nothing\n")), Some("NameError".to_owned()));
+    }
+
+    #[test]
+    fn invocation_blocks() {
+        let global_scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
+        let source = "mode = 'top'\n\
+            export {\n  mode = mode + ' import'\n}\n\
+            run {\n  mode = mode + ' run'\n}\n\
+            export answer = 42\n";
+        let instructions = compile(source).ok().unwrap();
+        let load = |id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+
+        // a script that is simply executed counts as a run; its exports are declared, but go nowhere
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        assert_eq!(load("mode"), Some("top run".to_owned()));
+        assert_eq!(load("answer"), Some("42".to_owned()));
+
+        let exports = MObjectImpl::new(BUILTINS.get_type("obj"));
+        let logger = log::default_logger();
+        StackMachine::exec_invoked(&instructions, global_scope.clone(), logger, Invocation::Import, Some(&exports))
+            .ok()
+            .unwrap();
+        assert_eq!(load("mode"), Some("top import".to_owned()));
+        assert!(exports.get_field("answer").is_some() && exports.get_field("mode").is_none());
+    }
+
+    #[test]
+    fn global_and_unloc() {
+        let global_scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
+        let local_scope = Arc::new(RwLock::new(VarScope::new_local(global_scope.clone(), STRICT_ASSIGN)));
+
+        let source = "local a = 1\n\
+            local b = 2\n\
+            global a\n\
+            a += 10\n\
+            local c = unloc b\n\
+            global d = c\n\
+            local d = 'local'\n\
+            global d = global d + 1\n\
+            local e = unloc missing\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, local_scope.clone()).ok().unwrap();
+        let load = |scope: &Arc<RwLock<VarScope>>, id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+        // `a` was moved to the global scope, and later uses refer to it there
+        assert_eq!(load(&global_scope, "a"), Some("11".to_owned()));
+        assert!(matches!(local_scope.read().unwrap().get_behavior("a"), VarScopeRefType::Global));
+        // `b` is gone, its value lives on in `c`
+        assert!(matches!(local_scope.read().unwrap().get_behavior("b"), VarScopeRefType::Propagate));
+        assert_eq!(load(&local_scope, "c"), Some("2".to_owned()));
+        // inline `global` doesn't touch the local variable of the same name
+        assert_eq!(load(&global_scope, "d"), Some("3".to_owned()));
+        assert_eq!(load(&local_scope, "d"), Some("local".to_owned()));
+        assert_eq!(load(&local_scope, "e"), Some("none".to_owned()));
+    }
+
+    #[test]
+    fn functions() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "calls = []\n\
+            greeting = 'hi '\n\
+            func greet(name, punct = '!') -> none {\n\
+              local text = greeting + name + punct\n\
+              calls.append(text)\n\
+            }\n\
+            greeting = 'hello '\n\
+            greet('a')\n\
+            greet('b', '?')\n\
+            greet(punct = '.', name = 'c')\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let run = |source: &str| {
+            let instructions = compile(source).ok().unwrap();
+            StackMachine::exec(&instructions, global_scope.clone())
+                .map(|v| v.to_ext_string(0, false).ok().unwrap())
+                .map_err(|e| e.to_ext_string(0, false).ok().unwrap())
+        };
+        let calls = StackMachine::exec(&[Statement::LoadScope("calls".to_owned())], global_scope.clone()).ok().unwrap();
+        // the body sees the variables of the scope it was defined in as they are when it's called
+        assert_eq!(calls.to_ext_string(0, false).ok(), Some("['hello a!', 'hello b?', 'hello c.']".to_owned()));
+        // its locals stay in the call's scope
+        assert!(global_scope.read().unwrap().get("text").is_none());
+        assert_eq!(run("greet()\n"), Err("`greet` is missing the argument `name`".to_owned()));
+        assert_eq!(run("greet('a', '!', 1)\n"), Err("`greet` takes 2 arguments, but 3 were given".to_owned()));
+        assert_eq!(run("greet('a', name = 'b')\n"), Err("`greet` got multiple values for `name`".to_owned()));
+        assert_eq!(run("greet('a', x = 1)\n"), Err("`greet` got an unexpected keyword argument `x`".to_owned()));
+    }
+
+    #[test]
+    fn logging() {
+        let sink = Arc::new(MemorySink::default());
+        let instructions = compile("local x = 1\n{ local y }\nunloc z\n#!log off\nunloc z\n").ok().unwrap();
+        let scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
+        StackMachine::exec_with_logger(&instructions, scope, Logger::new(LogLevel::Debug, sink.clone()))
+            .ok()
+            .unwrap();
+        assert_eq!(
+            sink.lines(),
+            vec![
+                "debug: declared local `x` at depth 0",
+                "debug: entered scope at depth 1",
+                "debug: declared local `y` at depth 1",
+                "debug: left scope, back at depth 0",
+                "warning: `unloc z` has no effect, since `z` isn't declared locally",
+            ]
+        );
+
+        // tracing shows every instruction before it's executed
+        let sink = Arc::new(MemorySink::default());
+        let instructions = compile("local x\n").ok().unwrap();
+        let scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
+        StackMachine::exec_with_logger(&instructions, scope, Logger::new(LogLevel::Trace, sink.clone()))
+            .ok()
+            .unwrap();
+        assert_eq!(sink.lines()[0], "trace:    0: DeclareLocal(\"x\")");
+    }
+
+    #[test]
+    fn help() {
+        let mut global_scope = VarScope::new_global(false);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "## The number of the day.\n\
+            local x = 1\n\
+            x = 2\n\
+            docs = [help(x), help(len), help(2)]\n\
+            ## Says hello.\n\
+            func greet() -> none {}\n\
+            alias = greet\n\
+            func_docs = [help(greet), help(alias)]\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let load = |id: &str| {
+            let value = StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone()).ok().unwrap();
+            value.to_ext_string(0, true).ok().unwrap()
+        };
+        let docs = load("docs");
+        // the docstring stays with the variable when it is reassigned
+        assert!(docs.starts_with("['The number of the day.', '"));
+        assert!(docs.ends_with("', none]"));
+        // a function keeps its docstring wherever it's stored
+        assert_eq!(load("func_docs"), "['Says hello.', 'Says hello.']");
+    }
+
+    #[test]
+    fn help_only_looks_up() {
+        let mut global_scope = VarScope::new_global(false);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "## Never changes.\n\
+            const A = 1\n\
+            docs = [help(A), help(missing), missing]\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let docs = StackMachine::exec(&[Statement::LoadScope("docs".to_owned())], global_scope).ok().unwrap();
+        // `missing` is still undeclared afterwards
+        assert_eq!(docs.to_ext_string(0, true).ok(), Some("['Never changes.', none, none (undeclared)]".to_owned()));
+
+        // with `#!strict assign`, it's an error like reading the variable
+        let instructions = compile("#!strict assign\nhelp(missing)\n").ok().unwrap();
+        let error =
+            StackMachine::exec(&instructions, Arc::new(RwLock::new(VarScope::new_global(false)))).err().unwrap();
+        assert!(error.to_ext_string(0, false).ok().unwrap().contains("is not declared"));
+    }
+
+    #[test]
+    fn missing_variables() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "local declared\n\
+            local assigned = none\n\
+            results = [defined(undeclared), defined(declared), defined(assigned), undeclared == none]\n\
+            sentinels = [undeclared, declared, assigned]\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let load = |id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+        assert_eq!(load("results"), Some("[false, false, true, true]".to_owned()));
+        assert_eq!(
+            load("sentinels"),
+            Some("[none (undeclared), none (unassigned), none]".to_owned())
+        );
+
+        // `#!strict assign` switches the check on from within the script
+        let instructions = compile("#!strict assign\nx = 1\n").ok().unwrap();
+        let error = StackMachine::exec(&instructions, Arc::new(RwLock::new(VarScope::new_global(false))))
+            .err()
+            .unwrap();
+        assert!(error.to_ext_string(0, false).ok().unwrap().contains("#!strict assign"));
+
+        // with `#!strict assign`, variables have to be declared before they are read
+        let strict_scope = Arc::new(RwLock::new(VarScope::new_global(true)));
+        let instructions = compile("local x = y\n").ok().unwrap();
+        let error = StackMachine::exec(&instructions, strict_scope).err().unwrap();
+        assert_eq!(MErrorImpl::kind_of(&error), Some("NameError".to_owned()));
+        assert_eq!(
+            error.to_ext_string(0, false).ok(),
+            Some("NameError: variable `y` is not declared".to_owned())
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let global_scope = Arc::new(RwLock::new(VarScope::new_global(STRICT_ASSIGN)));
+        let run = |source: &str| {
+            let instructions = compile(source).ok().unwrap();
+            StackMachine::exec(&instructions, global_scope.clone())
+                .map(|v| v.to_ext_string(0, false).ok().unwrap())
+                .map_err(|e| e.to_ext_string(0, false).ok().unwrap())
+        };
+        assert!(run("const a = 21\nconst b\nb = a * 2\n").is_ok());
+        let b = StackMachine::exec(&[Statement::LoadScope("b".to_owned())], global_scope.clone());
+        assert_eq!(b.ok().unwrap().to_ext_string(0, false).ok(), Some("42".to_owned()));
+        assert_eq!(
+            run("const c\nfor x in [1, 2] { c = x }\n"),
+            Err("readonly field `c` can only be assigned once".to_owned())
+        );
+        assert_eq!(
+            run("const d\nd + 1\n"),
+            Err("readonly field `d` was read before being initialized".to_owned())
+        );
+        assert_eq!(run("local a = 1\n"), Err("constant `a` cannot be redeclared".to_owned()));
+        assert_eq!(
+            global_scope.write().unwrap().declare("a", VarScopeRefType::Global),
+            Err("constant `a` cannot be moved to the global scope".to_owned())
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        let mut global_scope = VarScope::new_global(STRICT_ASSIGN);
+        declare_builtins(&mut global_scope);
+        let global_scope = Arc::new(RwLock::new(global_scope));
+
+        let source = "local [first, *middle, (x, y)] = [1, 2, 3, (4, 5)]\n\
+            local {name, size: (w, h)} = {name: 'box', size: (2, 3)}\n\
+            p = {}\n\
+            (p['a'], p['b']) = ('A', 'B')\n\
+            (x, y) = (y, x)\n";
+        let instructions = compile(source).ok().unwrap();
+        StackMachine::exec(&instructions, global_scope.clone()).ok().unwrap();
+        let load = |id: &str| {
+            StackMachine::exec(&[Statement::LoadScope(id.to_owned())], global_scope.clone())
+                .ok()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+        };
+        assert_eq!(load("first"), Some("1".to_owned()));
+        assert_eq!(load("middle"), Some("[2, 3]".to_owned()));
+        assert_eq!(load("x"), Some("5".to_owned()));
+        assert_eq!(load("y"), Some("4".to_owned()));
+        assert_eq!(load("name"), Some("box".to_owned()));
+        assert_eq!(load("h"), Some("3".to_owned()));
+        assert_eq!(load("p"), Some("{'a': 'A', 'b': 'B'}".to_owned()));
+
+        let mismatch = |source: &str| {
+            let instructions = compile(source).ok().unwrap();
+            StackMachine::exec(&instructions, global_scope.clone())
+                .err()
+                .unwrap()
+                .to_ext_string(0, false)
+                .ok()
+                .unwrap()
+        };
+        assert_eq!(mismatch("local (a, b) = [1, 2, 3]\n"), "cannot unpack 3 values into 2 targets");
+        assert_eq!(
+            mismatch("local [a, *b, c] = [1]\n"),
+            "cannot unpack 1 values into at least 2 targets"
+        );
     }
 }
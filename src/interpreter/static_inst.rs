@@ -0,0 +1,251 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::{lint::Rule, log::LogLevel, stackmachine::Statement};
+
+/**
+A static instruction like `#!tab 2` as it appears in the source. The arguments are the
+tokens following the instruction's name, since the lexer doesn't treat them specially.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticInst {
+    pub name: String,
+    pub args: Vec<String>,
+    pub line: isize,
+}
+
+/// The `#!strict` toggles; see `doc/examples/hello_world.m`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StrictFlags {
+    /// imports have to be explicit, no values leak from the shell
+    pub import: bool,
+    /// variables have to be declared before they are used
+    pub assign: bool,
+    /// `$` in plain string literals doesn't start an interpolation
+    pub dolstr: bool,
+}
+
+/// The `#!log` overrides of a script: a level like `debug`, and/or a file to log to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogSettings {
+    pub level: Option<LogLevel>,
+    pub file: Option<String>,
+}
+
+/// The interpreter settings a script makes with static instructions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    pub strict: StrictFlags,
+    pub log: LogSettings,
+    /// the interpreter given by `#!exec`
+    pub exec: Option<String>,
+}
+
+/// A static instruction that only an editor cares about, like `#!sep` or `#!fold`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub name: String,
+    pub args: Vec<String>,
+    pub line: isize,
+}
+
+/**
+What the handler of a static instruction can affect: the settings of the interpreter,
+the instructions the compiler emits at the point of the static instruction, and the editor metadata.
+ */
+#[derive(Default)]
+pub struct StaticContext {
+    pub settings: Settings,
+    pub metadata: Vec<Metadata>,
+    /// the instructions to emit where the static instruction is, drained by the compiler after each handler
+    pub instructions: Vec<Statement>,
+}
+
+/// The arguments of a static instruction, parsed from its tokens.
+pub trait StaticArgs: Sized {
+    fn parse(args: &[String]) -> Result<Self, String>;
+}
+
+/// Instructions like `#!sep` take no arguments.
+impl StaticArgs for () {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        match args {
+            [] => Ok(()),
+            _ => Err("expected no arguments".to_owned()),
+        }
+    }
+}
+
+impl StaticArgs for usize {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        match args {
+            [arg] => arg.parse().map_err(|_| format!("expected a number, got `{}`", arg)),
+            _ => Err("expected a single number".to_owned()),
+        }
+    }
+}
+
+/// The whole argument text, which can't be empty.
+impl StaticArgs for String {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        match args {
+            [] => Err("expected an argument".to_owned()),
+            _ => Ok(args.join(" ")),
+        }
+    }
+}
+
+/// At most one word, like the name in `#!fold neura`.
+impl StaticArgs for Option<String> {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        match args {
+            [] => Ok(None),
+            [arg] => Ok(Some(arg.clone())),
+            _ => Err("expected at most one argument".to_owned()),
+        }
+    }
+}
+
+impl StaticArgs for Vec<String> {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        Ok(args.to_vec())
+    }
+}
+
+/// `none` and `all` switch every toggle, the other words enable single ones. No words is the same as `none`.
+impl StaticArgs for StrictFlags {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut flags = StrictFlags::default();
+        for arg in args {
+            match arg.as_str() {
+                "none" => flags = StrictFlags::default(),
+                "all" => {
+                    flags = StrictFlags {
+                        import: true,
+                        assign: true,
+                        dolstr: true,
+                    }
+                }
+                "import" => flags.import = true,
+                "assign" => flags.assign = true,
+                "dolstr" => flags.dolstr = true,
+                _ => return Err(format!("unknown strict option `{}`", arg)),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+/// A log level and a path in any order, like `#!log trace ./debug.log`; paths start with `/` or `.`.
+impl StaticArgs for LogSettings {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut log = LogSettings::default();
+        for arg in args {
+            if arg.starts_with('/') || arg.starts_with('.') {
+                if log.file.replace(arg.clone()).is_some() {
+                    return Err("expected at most one file".to_owned());
+                }
+            } else if log.level.replace(arg.parse()?).is_some() {
+                return Err("expected at most one log level".to_owned());
+            }
+        }
+        if log == LogSettings::default() {
+            return Err("expected a log level or a file".to_owned());
+        }
+        Ok(log)
+    }
+}
+
+type StaticHandler = Arc<dyn Fn(&StaticInst, &mut StaticContext) -> Result<(), String> + Send + Sync>;
+
+/**
+The static instructions the compiler understands. Each one parses its arguments into a `StaticArgs` type
+and hands them to its handler. Embedders can register their own instructions (or replace the builtin ones)
+before compiling; instructions that aren't registered only cause a warning.
+ */
+#[derive(Clone)]
+pub struct StaticRegistry {
+    handlers: HashMap<String, StaticHandler>,
+}
+
+impl StaticRegistry {
+    /// A registry without any instructions, not even the builtin ones.
+    pub fn empty() -> Self {
+        StaticRegistry { handlers: HashMap::new() }
+    }
+
+    /// Register a static instruction, replacing any previous one with the same name.
+    pub fn register<A: StaticArgs + 'static>(
+        &mut self,
+        name: &str,
+        handler: impl Fn(A, &StaticInst, &mut StaticContext) -> Result<(), String> + Send + Sync + 'static,
+    ) {
+        self.handlers.insert(
+            name.to_owned(),
+            Arc::new(move |inst: &StaticInst, ctx: &mut StaticContext| handler(A::parse(&inst.args)?, inst, ctx)),
+        );
+    }
+
+    /// Register an instruction that an editor can use, and the compiler only checks and records.
+    pub fn register_metadata<A: StaticArgs + 'static>(&mut self, name: &str) {
+        self.register(name, |_: A, inst, ctx| {
+            ctx.metadata.push(Metadata {
+                name: inst.name.clone(),
+                args: inst.args.clone(),
+                line: inst.line,
+            });
+            Ok(())
+        });
+    }
+
+    /// The names of the registered instructions, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.handlers.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    /// Run the handler of a static instruction, failing with a message if it's unknown or its arguments are wrong.
+    pub fn apply(&self, inst: &StaticInst, ctx: &mut StaticContext) -> Result<(), String> {
+        let handler = self
+            .handlers
+            .get(&inst.name)
+            .ok_or_else(|| format!("unknown static instruction `#!{}`", inst.name))?;
+        handler(inst, ctx).map_err(|e| format!("`#!{}`: {}", inst.name, e))
+    }
+}
+
+/// The builtin static instructions.
+impl Default for StaticRegistry {
+    fn default() -> Self {
+        let mut registry = StaticRegistry::empty();
+        registry.register("exec", |exec: String, _, ctx| {
+            ctx.settings.exec = Some(exec);
+            Ok(())
+        });
+        registry.register("strict", |strict: StrictFlags, _, ctx| {
+            // the other toggles concern the lexer and imports, only `assign` is checked while running
+            if strict.assign != ctx.settings.strict.assign {
+                ctx.instructions.push(Statement::StrictAssign(strict.assign));
+            }
+            ctx.settings.strict = strict;
+            Ok(())
+        });
+        registry.register("log", |log: LogSettings, _, ctx| {
+            ctx.instructions.push(Statement::SetLog(log.level, log.file.clone()));
+            ctx.settings.log = log;
+            Ok(())
+        });
+        // suppresses lints for the next statement; the compiler only checks the rule IDs
+        registry.register("allow", |_: Vec<Rule>, _, _| Ok(()));
+        registry.register_metadata::<usize>("tab");
+        registry.register_metadata::<()>("sep");
+        registry.register_metadata::<Option<String>>("fold");
+        registry.register_metadata::<Option<String>>("endfold");
+        registry.register_metadata::<Vec<String>>("bfold");
+        registry
+    }
+}
@@ -3,23 +3,46 @@ pub mod none;
 pub mod string;
 pub mod builtin;
 pub mod object;
+pub mod int;
+pub mod float;
+pub mod boolean;
+pub mod function;
+pub mod list;
+pub mod tuple;
+pub mod dict;
+pub mod iterator;
+pub mod range;
+pub mod path;
+pub mod module;
 
 use std::{
+    any::Any,
     collections::HashMap,
     fmt::Debug,
     sync::{Arc, RwLock},
 };
 
-use self::{builtin::BUILTINS, object::{MObjectRef, MObject}};
+use self::{builtin::BUILTINS, object::{call_method, CloneMemo, MObjectRef, MObject}};
 
-use super::scopes::DynamicField;
+use super::scopes::FieldRef;
 
 pub type MFuncResult = Result<MObjectRef, MObjectRef>;
 
 pub trait MType: MObject {
     fn name(&self) -> String;
     fn supertypes(&self) -> &Vec<MTypeRef>;
+    /**
+    Construct a value of this type from another value for `value as type`, or return `None` if this type
+    doesn't know how (see `object::cast`). By default this calls the type's `$from` method with the value.
+     */
+    #[allow(clippy::wrong_self_convention)]
+    fn from_value(&self, value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+        call_method(self, "$from", vec![value.clone()]).transpose()
+    }
 }
+/// The native `$from` of a builtin type, see `MType::from_value`.
+pub type Converter = fn(&MObjectRef) -> Result<Option<MObjectRef>, MObjectRef>;
+
 pub type MTypeRef = Arc<RwLock<dyn MType>>;
 pub struct MTypeImpl {
     name: String,
@@ -27,15 +50,31 @@ pub struct MTypeImpl {
     supertypes: Vec<MTypeRef>,
     /// when a variable is not defined for a specific instance of an object,
     /// the object's type's `proto_dict` is consulted.
-    proto_dict: HashMap<String, DynamicField>,
+    #[allow(dead_code)]
+    proto_dict: HashMap<String, FieldRef>,
     /// unfortunately, because the type hierarchy is a real mess at the top, the implementation of
     /// object functionality needs to be redone here.
-    inst_dict: HashMap<String, DynamicField>,
+    inst_dict: RwLock<HashMap<String, FieldRef>>,
+    /// the builtin conversions to this type, tried before a `$from` method
+    from: Option<Converter>,
 }
 pub type MTypeImplRef = Arc<RwLock<MTypeImpl>>;
 impl MObject for MTypeImpl {
     fn objtype(&self) -> MTypeRef {
-        self.objtype.unwrap_or(BUILTINS.get_type("type"))
+        self.objtype.clone().unwrap_or_else(|| BUILTINS.get_type("type"))
+    }
+    fn get_field(&self, name: &str) -> Option<FieldRef> {
+        self.inst_dict.read().unwrap().get(name).cloned()
+    }
+    fn insert_field(&self, field: FieldRef) {
+        let name = field.read().unwrap().name();
+        self.inst_dict.write().unwrap().insert(name, field);
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 impl MType for MTypeImpl {
@@ -45,6 +84,14 @@ impl MType for MTypeImpl {
     fn supertypes(&self) -> &Vec<MTypeRef> {
         &self.supertypes
     }
+    fn from_value(&self, value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+        if let Some(from) = self.from {
+            if let Some(converted) = from(value)? {
+                return Ok(Some(converted));
+            }
+        }
+        call_method(self, "$from", vec![value.clone()]).transpose()
+    }
 }
 impl From<MTypeImpl> for MTypeRef {
     fn from(o: MTypeImpl) -> Self {
@@ -59,7 +106,19 @@ impl From<MTypeImpl> for MTypeImplRef {
 
 impl MTypeImpl {
     pub fn new(name: &str, objtype: Option<MTypeRef>, supertypes: Vec<MTypeRef>) -> MTypeImpl {
-        MTypeImpl { name, objtype, supertypes, proto_dict: HashMap::new(), inst_dict: HashMap::new() }
+        MTypeImpl {
+            name: name.to_owned(),
+            objtype,
+            supertypes,
+            proto_dict: HashMap::new(),
+            inst_dict: RwLock::new(HashMap::new()),
+            from: None,
+        }
+    }
+    /// Give the type builtin conversions from other types, see `MType::from_value`.
+    pub fn with_from(mut self, from: Converter) -> Self {
+        self.from = Some(from);
+        self
     }
     pub fn wrap(self) -> Arc<RwLock<MTypeImpl>> {
         Arc::new(RwLock::new(self))
@@ -89,4 +148,10 @@ pub enum BinaryOperator {
     Mod,
     Plus,
     Minus,
+    /// `==`, see `MObject::equals`
+    Eq,
+    /// `!=`, the negation of `Eq`
+    Neq,
+    /// `item in container`, see `MObject::contains`. Note that the container is the *right* operand.
+    In,
 }
\ No newline at end of file
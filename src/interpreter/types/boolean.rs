@@ -0,0 +1,127 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::scopes::FieldRef;
+
+use super::{
+    builtin::BUILTINS,
+    float::MFloatImpl,
+    int::MIntImpl,
+    none::MNone,
+    object::{binop_unsupported, unop_unsupported, CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    BinaryOperator, MFuncResult, MTypeImpl, MTypeRef, UnaryOperator,
+};
+use delegate::delegate;
+
+pub trait MBool: MObject {
+    fn value(&self) -> bool;
+}
+pub type MBoolRef = Arc<RwLock<dyn MBool>>;
+
+pub struct MBoolImpl {
+    mobject: MObjectImpl,
+    value: bool,
+}
+pub type MBoolImplRef = Arc<RwLock<MBoolImpl>>;
+impl MObject for MBoolImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("bool")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(format!("{}", self.value)).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(format!("{}", self.value))
+    }
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        let rhs = match MBoolImpl::value_of(&other) {
+            Some(rhs) => rhs,
+            None => return Err(binop_unsupported(self, &other, op)),
+        };
+        let result = match op {
+            BinaryOperator::And | BinaryOperator::BitAnd => self.value && rhs,
+            BinaryOperator::Or | BinaryOperator::BitOr => self.value || rhs,
+            BinaryOperator::Xor => self.value ^ rhs,
+            _ => return Err(binop_unsupported(self, &other, op)),
+        };
+        Ok(MBoolImpl::new(result).wrap())
+    }
+    fn unop(&self, op: UnaryOperator) -> MFuncResult {
+        match op {
+            UnaryOperator::Not => Ok(MBoolImpl::new(!self.value).wrap()),
+            _ => Err(unop_unsupported(self, op)),
+        }
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(MBoolImpl::value_of(other) == Some(self.value))
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl MBool for MBoolImpl {
+    fn value(&self) -> bool {
+        self.value
+    }
+}
+
+impl MBoolImpl {
+    pub fn new(value: bool) -> Self {
+        MBoolImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("bool")),
+            value,
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// The value of an object, if it is a `bool`.
+    pub fn value_of(obj: &MObjectRef) -> Option<bool> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MBoolImpl>()
+            .map(|o| o.value)
+    }
+}
+impl From<bool> for MBoolImpl {
+    fn from(value: bool) -> Self {
+        Self::new(value)
+    }
+}
+
+/**
+The builtin conversions to `bool`: numbers are `true` unless they are zero, `none` is `false`,
+and strings and containers are `true` unless they are empty.
+ */
+fn bool_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    let truth = if let Some(value) = MIntImpl::value_of(value) {
+        !value.is_zero()
+    } else if let Some(value) = MFloatImpl::value_of(value) {
+        value != 0.0
+    } else if value.read().unwrap().as_any().is::<MNone>() {
+        false
+    } else {
+        match value.len() {
+            Ok(len) => len > 0,
+            Err(_) => return Ok(None),
+        }
+    };
+    Ok(Some(MBoolImpl::from(truth).wrap()))
+}
+
+pub(super) fn create_bool_type() -> MTypeRef {
+    MTypeImpl::new("bool", None, vec![BUILTINS.get_type("obj")]).with_from(bool_from).wrap()
+}
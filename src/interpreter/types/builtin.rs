@@ -1,36 +1,140 @@
 use lazy_static::lazy_static;
 
-use std::collections::HashMap;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{Arc, Once, RwLock},
+};
 
-use super::{MTypeImpl, MTypeRef, none, object};
+use crate::interpreter::scopes::{StaticField, VarScope, VarScopeRefType};
+
+use super::{
+    boolean::{self, MBoolImpl},
+    dict, error, float,
+    function::{self, check_args, MNativeFunctionImpl},
+    int::{self, MIntImpl},
+    iterator::{self, MStopIteration},
+    list, module,
+    none::{self, MNone},
+    path,
+    object::{self, MObject, MObjectRef},
+    range,
+    string::{self, MStringImpl},
+    tuple, MTypeImpl, MTypeRef,
+};
 
 lazy_static! {
     pub static ref BUILTINS: Builtins = Builtins::singleton();
 }
 
-struct Builtins {
-    types: HashMap<String, MTypeRef>,
+thread_local! {
+    /// set while this thread runs `init_type_system`, whose types already look each other up in `BUILTINS`
+    static INITIALIZING: Cell<bool> = const { Cell::new(false) };
+}
+
+pub struct Builtins {
+    types: RwLock<HashMap<String, MTypeRef>>,
+    init: Once,
 }
 impl Builtins {
     fn singleton() -> Self {
-        Self;
+        Self { types: RwLock::new(HashMap::new()), init: Once::new() }
     }
-    pub fn create_type(&mut self, _type: MTypeRef) {
-        self.types
-            .insert(_type.read().unwrap().name(), _type.clone())
+    /// Make sure the builtin types exist. The first lookup creates them, other threads wait for it to finish.
+    fn ensure_init(&self) {
+        if INITIALIZING.with(Cell::get) {
+            return;
+        }
+        self.init.call_once(|| {
+            INITIALIZING.with(|initializing| initializing.set(true));
+            init_type_system(self);
+            INITIALIZING.with(|initializing| initializing.set(false));
+        });
+    }
+    fn create_type(&self, _type: MTypeRef) {
+        let name = _type.read().unwrap().name();
+        self.types.write().unwrap().insert(name, _type);
     }
     pub fn get_type(&self, name: &str) -> MTypeRef {
-        self.types.get(name).clone()
+        self.find_type(name).unwrap_or_else(|| panic!("no builtin type {name}"))
+    }
+    /// The builtin type with the given name, if there is one, eg. the target of `x as int`.
+    pub fn find_type(&self, name: &str) -> Option<MTypeRef> {
+        self.ensure_init();
+        self.types.read().unwrap().get(name).cloned()
     }
 }
 
 fn create_type_type() -> MTypeRef {
-    let type_type = MTypeImpl::new("type", None, vec![BUILTINS.get_type("obj")]).wrap();
-    type_type
+    MTypeImpl::new("type", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
+
+fn init_type_system(builtins: &Builtins) {
+    builtins.create_type(object::create_object_type());
+    builtins.create_type(create_type_type());
+    builtins.create_type(none::create_none_type());
+    builtins.create_type(string::create_str_type());
+    builtins.create_type(int::create_int_type());
+    builtins.create_type(float::create_float_type());
+    builtins.create_type(boolean::create_bool_type());
+    builtins.create_type(function::create_func_type());
+    builtins.create_type(list::create_list_type());
+    builtins.create_type(tuple::create_tuple_type());
+    builtins.create_type(dict::create_dict_type());
+    builtins.create_type(iterator::create_iterator_type());
+    builtins.create_type(range::create_range_type());
+    builtins.create_type(path::create_path_type());
+    builtins.create_type(module::create_module_type());
+    builtins.create_type(error::create_error_type());
+}
+
+/// What `help` shows: the documentation of a variable or field if there is any, or else that of its value.
+pub fn help_text(docstring: Option<String>, value: &MObjectRef) -> MObjectRef {
+    match docstring.or_else(|| value.docstring()) {
+        Some(docstring) => MStringImpl::from(docstring).wrap(),
+        None => MNone::refer(),
+    }
 }
 
-fn init_type_system() {
-    BUILTINS.create_type(object::create_object_type());
-    BUILTINS.create_type(create_type_type());
-    BUILTINS.create_type(none::create_none_type());
+/// Declare the builtin functions and values as readonly variables, usually in the global scope.
+pub fn declare_builtins(scope: &mut VarScope) {
+    let values: Vec<(&str, MObjectRef)> = vec![("none", MNone::refer()), ("stop_iteration", MStopIteration::refer())];
+    for (name, value) in values {
+        scope
+            .declare(
+                name,
+                VarScopeRefType::LocalValue(Arc::new(RwLock::new(StaticField::new(
+                    Some(name.to_owned()),
+                    None,
+                    Some(value),
+                    true,
+                )))),
+            )
+            .expect("builtins are declared before any constants");
+    }
+    let functions = vec![
+        MNativeFunctionImpl::new("len", |args, kwargs| {
+            check_args("len", &args, &kwargs, 1, 1)?;
+            Ok(MIntImpl::from(args[0].len()? as i64).wrap())
+        })
+        .with_docstring("The number of elements of a container, or of characters in a string."),
+        range::range_function(),
+        MNativeFunctionImpl::new("defined", |args, kwargs| {
+            check_args("defined", &args, &kwargs, 1, 1)?;
+            Ok(MBoolImpl::from(!MNone::is_missing(&args[0])).wrap())
+        })
+        .with_docstring("Whether a variable was declared and assigned a value (even if that value is `none`)."),
+        MNativeFunctionImpl::new("help", |args, kwargs| {
+            check_args("help", &args, &kwargs, 1, 1)?;
+            Ok(help_text(None, &args[0]))
+        })
+        .with_docstring("The documentation of a value. Called on a variable or field, it shows the documentation given there."),
+    ];
+    for func in functions {
+        let field = func.into_field();
+        let name = field.read().unwrap().name();
+        scope
+            .declare(&name, VarScopeRefType::LocalValue(field))
+            .expect("builtins are declared before any constants");
+    }
 }
@@ -0,0 +1,388 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use indexmap::IndexMap;
+
+use crate::interpreter::scopes::{DynamicField, FieldRef};
+
+use super::{
+    boolean::{MBool, MBoolImpl},
+    builtin::BUILTINS,
+    float::{MFloat, MFloatImpl},
+    function::{check_args, MNativeFunctionImpl},
+    int::{IntValue, MInt, MIntImpl},
+    iterator::MIteratorImpl,
+    list::MListImpl,
+    none::MNone,
+    object::{deep_clone, same_object, CloneMemo, MObject, MObjectImpl, MObjectRef, MAX_EXTSTR_DEPTH},
+    string::MStringImpl,
+    tuple::{MTuple, MTupleImpl},
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+/**
+The hashable identity of a dict key. Only immutable builtin values can be keys; numbers are
+compared by value, so `d[1]` and `d[1.0]` refer to the same entry.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DictKey {
+    None,
+    Bool(bool),
+    Int(IntValue),
+    /// floats without an integer value, by their bit pattern
+    Float(u64),
+    Str(String),
+    Tuple(Vec<DictKey>),
+}
+
+impl DictKey {
+    pub fn of(obj: &MObjectRef) -> Result<Self, MObjectRef> {
+        let obj = obj.read().unwrap();
+        let any = obj.as_any();
+        if any.is::<MNone>() {
+            Ok(DictKey::None)
+        } else if let Some(b) = any.downcast_ref::<MBoolImpl>() {
+            Ok(DictKey::Bool(b.value()))
+        } else if let Some(i) = any.downcast_ref::<MIntImpl>() {
+            Ok(DictKey::Int(i.value().clone()))
+        } else if let Some(f) = any.downcast_ref::<MFloatImpl>() {
+            let value = f.value();
+            Ok(match IntValue::from_f64(value) {
+                Some(i) if value.fract() == 0.0 => DictKey::Int(i),
+                _ => DictKey::Float(value.to_bits()),
+            })
+        } else if let Some(s) = any.downcast_ref::<MStringImpl>() {
+            Ok(DictKey::Str(s.value().to_owned()))
+        } else if let Some(t) = any.downcast_ref::<MTupleImpl>() {
+            Ok(DictKey::Tuple(t.items().iter().map(Self::of).collect::<Result<_, _>>()?))
+        } else {
+            Err(MStringImpl::from(format!(
+                "unhashable type `{}` can't be used as a dict key",
+                obj.objtype().read().unwrap().name()
+            ))
+            .wrap())
+        }
+    }
+}
+
+/// The entries of a dict in insertion order, remembering the original key objects.
+type DictEntries = IndexMap<DictKey, (MObjectRef, MObjectRef)>;
+
+pub trait MDict: MObject {
+    fn entries(&self) -> Vec<(MObjectRef, MObjectRef)>;
+}
+pub type MDictRef = Arc<RwLock<dyn MDict>>;
+
+/// The builtin mapping type. Like `list`, the entries live behind their own lock so fields and methods can share them.
+pub struct MDictImpl {
+    mobject: MObjectImpl,
+    entries: Arc<RwLock<DictEntries>>,
+}
+pub type MDictImplRef = Arc<RwLock<MDictImpl>>;
+impl MObject for MDictImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("dict")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.to_ext_string(0, true)?).wrap())
+    }
+    fn to_ext_string(&self, depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        if depth > MAX_EXTSTR_DEPTH {
+            return Ok("{...}".to_owned());
+        }
+        let entries = self
+            .entries()
+            .iter()
+            .map(|(k, v)| Ok(format!("{}: {}", k.to_ext_string(depth + 1, true)?, v.to_ext_string(depth + 1, true)?)))
+            .collect::<Result<Vec<_>, MObjectRef>>()?;
+        Ok(format!("{{{}}}", entries.join(", ")))
+    }
+    fn dot(&self, name: &str) -> Result<FieldRef, MObjectRef> {
+        match dict_method(&self.entries, name) {
+            Some(method) => Ok(method.into_field()),
+            None => self.mobject.dot(name),
+        }
+    }
+    fn index(&self, index: MObjectRef) -> Result<FieldRef, MObjectRef> {
+        let key = DictKey::of(&index)?;
+        let name = format!("[{}]", index.to_ext_string(0, true)?);
+        let (get_entries, set_entries, del_entries) = (self.entries.clone(), self.entries.clone(), self.entries.clone());
+        let (get_key, set_key, del_key) = (key.clone(), key.clone(), key);
+        let missing = {
+            let name = name.clone();
+            move || MStringImpl::from(format!("key {} not found in dict", name)).wrap() as MObjectRef
+        };
+        let missing_del = missing.clone();
+        Ok(Arc::new(RwLock::new(DynamicField::new(
+            name,
+            None,
+            Some(Box::new(move || {
+                get_entries.read().unwrap().get(&get_key).map(|(_, v)| v.clone()).ok_or_else(&missing)
+            })),
+            Some(Box::new(move |value| {
+                let mut entries = set_entries.write().unwrap();
+                // an existing entry keeps its original key object and position
+                let entry = entries.entry(set_key.clone()).or_insert_with(|| (index.clone(), value.clone()));
+                Ok(std::mem::replace(&mut entry.1, value))
+            })),
+            Some(Box::new(move || {
+                del_entries.write().unwrap().shift_remove(&del_key).map(|(_, v)| v).ok_or_else(&missing_del)
+            })),
+        ))))
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        if same_object(self, other) {
+            return Ok(true);
+        }
+        let other = match MDictImpl::entries_of(other) {
+            Some(other) => other,
+            None => return Ok(false),
+        };
+        let entries = self.entries.read().unwrap();
+        if entries.len() != other.len() {
+            return Ok(false);
+        }
+        for (key, value) in other {
+            match entries.get(&DictKey::of(&key)?) {
+                Some((_, v)) if v.equals(&value)? => (),
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+    fn contains(&self, item: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(self.entries.read().unwrap().contains_key(&DictKey::of(item)?))
+    }
+    fn len(&self) -> Result<usize, MObjectRef> {
+        Ok(self.entries.read().unwrap().len())
+    }
+    /// Unpacking a dict yields its keys.
+    fn unpack(&self) -> Result<Vec<MObjectRef>, MObjectRef> {
+        Ok(self.entries().into_iter().map(|(k, _)| k).collect())
+    }
+    /// Like unpacking, iteration yields the keys. It works on a snapshot, so the dict can be modified in the loop.
+    fn iter(&self) -> MFuncResult {
+        Ok(MIteratorImpl::from_vec(self.unpack()?).wrap())
+    }
+    fn clone_value(&self, memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        clone_entries(&self.entries, memo).map(Some)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl MDict for MDictImpl {
+    fn entries(&self) -> Vec<(MObjectRef, MObjectRef)> {
+        self.entries.read().unwrap().values().cloned().collect()
+    }
+}
+
+impl MDictImpl {
+    pub fn new() -> Self {
+        MDictImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("dict")),
+            entries: Arc::new(RwLock::new(IndexMap::new())),
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// Build a dict from key-value pairs; later pairs overwrite earlier ones with the same key.
+    pub fn from_pairs(pairs: Vec<(MObjectRef, MObjectRef)>) -> Result<Self, MObjectRef> {
+        let dict = Self::new();
+        for (key, value) in pairs {
+            dict.insert(key, value)?;
+        }
+        Ok(dict)
+    }
+    pub fn insert(&self, key: MObjectRef, value: MObjectRef) -> Result<(), MObjectRef> {
+        let hashed = DictKey::of(&key)?;
+        let mut entries = self.entries.write().unwrap();
+        match entries.get_mut(&hashed) {
+            Some(entry) => entry.1 = value,
+            None => {
+                entries.insert(hashed, (key, value));
+            }
+        }
+        Ok(())
+    }
+    /// A snapshot of the entries of an object, if it is a `dict`.
+    pub fn entries_of(obj: &MObjectRef) -> Option<Vec<(MObjectRef, MObjectRef)>> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MDictImpl>()
+            .map(|o| o.entries())
+    }
+}
+impl Default for MDictImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Deep-copy the values of a dict into a new one, like `list` does. Keys are immutable, so they are shared.
+fn clone_entries(entries: &Arc<RwLock<DictEntries>>, memo: &mut CloneMemo) -> MFuncResult {
+    let key = Arc::as_ptr(entries) as usize;
+    if let Some(copy) = memo.get(&key) {
+        return Ok(copy.clone());
+    }
+    let copy = MDictImpl::new();
+    let copy_entries = copy.entries.clone();
+    let copy: MObjectRef = copy.wrap();
+    memo.insert(key, copy.clone());
+    let originals = entries.read().unwrap().clone();
+    let mut cloned = DictEntries::with_capacity(originals.len());
+    for (hashed, (k, v)) in originals {
+        cloned.insert(hashed, (k, deep_clone(&v, memo)?));
+    }
+    *copy_entries.write().unwrap() = cloned;
+    Ok(copy)
+}
+
+/// The methods of `dict`, bound to the entries of a specific dict.
+fn dict_method(entries: &Arc<RwLock<DictEntries>>, name: &str) -> Option<MNativeFunctionImpl> {
+    let entries = entries.clone();
+    let method = match name {
+        "keys" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("keys", &args, &kwargs, 0, 0)?;
+            let keys = entries.read().unwrap().values().map(|(k, _)| k.clone()).collect();
+            Ok(MListImpl::new(keys).wrap())
+        }),
+        "values" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("values", &args, &kwargs, 0, 0)?;
+            let values = entries.read().unwrap().values().map(|(_, v)| v.clone()).collect();
+            Ok(MListImpl::new(values).wrap())
+        }),
+        "items" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("items", &args, &kwargs, 0, 0)?;
+            let items = entries
+                .read()
+                .unwrap()
+                .values()
+                .map(|(k, v)| MTupleImpl::new(vec![k.clone(), v.clone()]).wrap() as MObjectRef)
+                .collect();
+            Ok(MListImpl::new(items).wrap())
+        }),
+        "get" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("get", &args, &kwargs, 1, 2)?;
+            let key = DictKey::of(&args[0])?;
+            Ok(match entries.read().unwrap().get(&key) {
+                Some((_, v)) => v.clone(),
+                None => args.get(1).cloned().unwrap_or(MNone::refer() as MObjectRef),
+            })
+        }),
+        "pop" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("pop", &args, &kwargs, 1, 2)?;
+            let key = DictKey::of(&args[0])?;
+            let removed = entries.write().unwrap().shift_remove(&key);
+            match (removed, args.get(1)) {
+                (Some((_, v)), _) => Ok(v),
+                (None, Some(default)) => Ok(default.clone()),
+                (None, None) => Err(MStringImpl::from(format!(
+                    "key {} not found in dict",
+                    args[0].to_ext_string(0, true)?
+                ))
+                .wrap()),
+            }
+        }),
+        "update" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            // `d.update(other)` and `d.update(key=value)` both work
+            check_args("update", &args, &Default::default(), 0, 1)?;
+            let mut pairs = match args.first() {
+                Some(other) => MDictImpl::entries_of(other).ok_or_else(|| {
+                    MStringImpl::from("dict.update expects a dict").wrap() as MObjectRef
+                })?,
+                None => vec![],
+            };
+            pairs.extend(kwargs.into_iter().map(|(k, v)| (MStringImpl::from(k).wrap() as MObjectRef, v)));
+            let mut entries = entries.write().unwrap();
+            for (key, value) in pairs {
+                let hashed = DictKey::of(&key)?;
+                match entries.get_mut(&hashed) {
+                    Some(entry) => entry.1 = value,
+                    None => {
+                        entries.insert(hashed, (key, value));
+                    }
+                }
+            }
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "clear" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("clear", &args, &kwargs, 0, 0)?;
+            entries.write().unwrap().clear();
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "copy" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("copy", &args, &kwargs, 0, 0)?;
+            let copy = MDictImpl::new();
+            *copy.entries.write().unwrap() = entries.read().unwrap().clone();
+            Ok(copy.wrap())
+        }),
+        "$clone" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("$clone", &args, &kwargs, 0, 0)?;
+            clone_entries(&entries, &mut CloneMemo::new())
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
+
+pub(super) fn create_dict_type() -> MTypeRef {
+    MTypeImpl::new("dict", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn int(value: i64) -> MObjectRef {
+        MIntImpl::from(value).wrap()
+    }
+    fn string(value: &str) -> MObjectRef {
+        MStringImpl::from(value).wrap()
+    }
+
+    #[test]
+    fn numeric_keys() {
+        let dict: MObjectRef = MDictImpl::from_pairs(vec![(int(1), string("a"))]).ok().unwrap().wrap();
+        assert!(dict.contains(&(MFloatImpl::new(1.0).wrap() as MObjectRef)).ok().unwrap());
+        assert!(!dict.contains(&(MFloatImpl::new(1.5).wrap() as MObjectRef)).ok().unwrap());
+        assert!(dict.contains(&(MListImpl::new(vec![]).wrap() as MObjectRef)).is_err());
+    }
+
+    #[test]
+    fn index_fields() {
+        let dict: MObjectRef = MDictImpl::from_pairs(vec![(string("a"), int(1)), (string("b"), int(2))])
+            .ok()
+            .unwrap()
+            .wrap();
+        dict.index(string("a")).ok().unwrap().write().unwrap().set(Some(int(3))).ok().unwrap();
+        dict.index(string("c")).ok().unwrap().write().unwrap().set(Some(int(4))).ok().unwrap();
+        dict.index(string("b")).ok().unwrap().write().unwrap().del().ok().unwrap();
+        assert_eq!(dict.to_ext_string(0, false).ok(), Some("{'a': 3, 'c': 4}".to_owned()));
+        assert!(dict.index(string("b")).ok().unwrap().read().unwrap().get().is_err());
+    }
+
+    #[test]
+    fn update() {
+        let dict: MObjectRef =
+            MDictImpl::from_pairs(vec![(string("a"), int(1)), (string("b"), int(2))]).ok().unwrap().wrap();
+        let other: MObjectRef =
+            MDictImpl::from_pairs(vec![(string("b"), int(3)), (int(4), int(5))]).ok().unwrap().wrap();
+        // entries of the dict come first, then the keyword arguments
+        let update = dict.dot("update").ok().unwrap().read().unwrap().get().ok().unwrap().unwrap();
+        update.call(vec![other], HashMap::from([("a".to_owned(), int(6)), ("c".to_owned(), int(7))])).ok().unwrap();
+        assert_eq!(dict.to_ext_string(0, false).ok(), Some("{'a': 6, 'b': 3, 4: 5, 'c': 7}".to_owned()));
+    }
+}
@@ -1,49 +1,87 @@
 use std::{
-    error::Error,
-    fmt::Display,
+    any::Any,
     sync::{Arc, RwLock},
 };
 
-pub trait MshError: MshValue + Error {}
+use crate::interpreter::scopes::FieldRef;
 
-#[derive(Debug)]
-pub struct MshBaseError {
+use super::{
+    builtin::BUILTINS,
+    object::{CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+/**
+An error with a kind that code can tell apart from other errors, like `NameError`.
+Most errors are still thrown as plain strings; this is for the ones that need to be recognized.
+ */
+pub struct MErrorImpl {
+    mobject: MObjectImpl,
+    kind: String,
     msg: String,
 }
-impl MshBaseError {
-    pub fn new(msg: &str) -> Self {
-        Self {
-            msg: msg.to_owned(),
+pub type MErrorImplRef = Arc<RwLock<MErrorImpl>>;
+impl MObject for MErrorImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
         }
     }
-}
-impl From<MshBaseError> for MshReference {
-    fn from(o: MshBaseError) -> Self {
-        msh_value_wrap(o)
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("error")
+    }
+    fn str_nice(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.to_ext_string(0, false)?).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(format!("{}: {}", self.kind, self.msg))
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
-impl Display for MshBaseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.msg)
+impl MErrorImpl {
+    pub fn new(kind: impl Into<String>, msg: impl Into<String>) -> Self {
+        MErrorImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("error")),
+            kind: kind.into(),
+            msg: msg.into(),
+        }
     }
-}
-impl Error for MshBaseError {}
-impl MshValue for MshBaseError {
-    fn objtype(&self) -> MshReference {
-        Arc::new(RwLock::new(MshString::from("Error")))
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
     }
-
-    fn str_nice(&self) -> Result<MshReference, MshReference> {
-        Ok(MshString::from(format!(
-            "{}: {}",
-            self.objtype().to_string_nice(0)?,
-            self.msg
-        )).into())
+    /// The error thrown when reading a variable that was never declared, under `#!strict assign`.
+    pub fn name_error(msg: impl Into<String>) -> Self {
+        Self::new("NameError", msg)
     }
-
-    fn dot(&self, identifier: &str) -> Result<Option<Arc<RwLock<dyn crate::interpreter::scopes::AnnotatedField>>>, MshReference> {
-        todo!()
+    /// The error thrown for a conversion that isn't possible, like `[1] as int`.
+    pub fn type_error(msg: impl Into<String>) -> Self {
+        Self::new("TypeError", msg)
+    }
+    pub fn kind(&self) -> &str {
+        &self.kind
     }
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+    /// The kind of an object, if it is an `error`.
+    pub fn kind_of(obj: &MObjectRef) -> Option<String> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MErrorImpl>()
+            .map(|o| o.kind.clone())
+    }
+}
+
+pub(super) fn create_error_type() -> MTypeRef {
+    MTypeImpl::new("error", None, vec![BUILTINS.get_type("obj")]).wrap()
 }
-impl MshError for MshBaseError {}
@@ -0,0 +1,351 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::interpreter::scopes::FieldRef;
+
+use super::{
+    boolean::MBoolImpl,
+    builtin::BUILTINS,
+    int::{IntValue, MIntImpl},
+    object::{binop_unsupported, unop_unsupported, CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    BinaryOperator, MFuncResult, MTypeImpl, MTypeRef, UnaryOperator,
+};
+use delegate::delegate;
+
+pub trait MFloat: MObject {
+    fn value(&self) -> f64;
+}
+pub type MFloatRef = Arc<RwLock<dyn MFloat>>;
+
+pub struct MFloatImpl {
+    mobject: MObjectImpl,
+    value: f64,
+}
+pub type MFloatImplRef = Arc<RwLock<MFloatImpl>>;
+impl MObject for MFloatImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("float")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(format!("{:?}", self.value)).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(format!("{:?}", self.value))
+    }
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        // ints are implicitly widened
+        let rhs = match MFloatImpl::value_of(&other) {
+            Some(rhs) => rhs,
+            None => match MIntImpl::value_of(&other) {
+                Some(rhs) => rhs.to_f64(),
+                None => return Err(binop_unsupported(self, &other, op)),
+            },
+        };
+        // dividing by zero is an error like for ints, rather than giving an infinity or `NaN`
+        if matches!(op, BinaryOperator::Div | BinaryOperator::Mod) && rhs == 0.0 {
+            let what = if op == BinaryOperator::Div { "division" } else { "modulo" };
+            return Err(MStringImpl::from(format!("{} by zero", what)).wrap());
+        }
+        let result = match op {
+            BinaryOperator::Plus => self.value + rhs,
+            BinaryOperator::Minus => self.value - rhs,
+            BinaryOperator::Mul => self.value * rhs,
+            BinaryOperator::Div => self.value / rhs,
+            // flooring modulo, consistent with ints
+            BinaryOperator::Mod => self.value - rhs * (self.value / rhs).floor(),
+            BinaryOperator::Pow => self.value.powf(rhs),
+            _ => return Err(binop_unsupported(self, &other, op)),
+        };
+        Ok(MFloatImpl::new(result).wrap())
+    }
+    fn unop(&self, op: UnaryOperator) -> MFuncResult {
+        match op {
+            UnaryOperator::Inc => Ok(MFloatImpl::new(self.value + 1.0).wrap()),
+            UnaryOperator::Dec => Ok(MFloatImpl::new(self.value - 1.0).wrap()),
+            _ => Err(unop_unsupported(self, op)),
+        }
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        let rhs = MFloatImpl::value_of(other).or_else(|| MIntImpl::value_of(other).map(|i| i.to_f64()));
+        Ok(rhs == Some(self.value))
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl MFloat for MFloatImpl {
+    fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+impl MFloatImpl {
+    pub fn new(value: f64) -> Self {
+        MFloatImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("float")),
+            value,
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// The value of an object, if it is a `float`.
+    pub fn value_of(obj: &MObjectRef) -> Option<f64> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MFloatImpl>()
+            .map(|o| o.value)
+    }
+    /// Parse a string as a `float`, accepting the same syntax as float literals.
+    pub fn parse(value: &str) -> Result<Self, MObjectRef> {
+        parse_float_literal(value.trim()).map(Self::new).ok_or_else(|| {
+            MStringImpl::from(format!("invalid float format: `{}`", value)).wrap() as MObjectRef
+        })
+    }
+    /// Truncate towards zero, failing for infinities and `NaN`.
+    pub fn to_int(&self) -> Result<MIntImpl, MObjectRef> {
+        MIntImpl::from_float(self.value)
+    }
+}
+impl From<f64> for MFloatImpl {
+    fn from(value: f64) -> Self {
+        Self::new(value)
+    }
+}
+
+/**
+Parse a float literal as accepted by the lexer (`DEC_FLOAT`, `HEX_FLOAT` or `BIN_FLOAT`):
+an optional sign, an optional `0x`/`0b` prefix for the mantissa, `_` separators,
+and an optional exponent which can itself be written in any base.
+An `e` exponent scales by powers of 10, a `p` exponent by powers of 2.
+
+The value is computed exactly and then rounded to the nearest `f64` (ties to even),
+so hex and binary literals can describe any float bit pattern precisely.
+ */
+pub fn parse_float_literal(text: &str) -> Option<f64> {
+    let text = text.replace('_', "").to_lowercase();
+    let (negative, rest) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, &text[..]),
+    };
+    let (base, rest) = if let Some(r) = rest.strip_prefix("0x") {
+        (16u32, r)
+    } else if let Some(r) = rest.strip_prefix("0b") {
+        (2, r)
+    } else {
+        (10, rest)
+    };
+
+    // `e` is a digit in hex mantissas, so only `p` can start their exponent
+    let markers: &[char] = if base == 16 { &['p'] } else { &['e', 'p'] };
+    let (mantissa, exp10, exp2) = match rest.find(markers) {
+        Some(pos) => {
+            let exponent = match IntValue::parse_literal(&rest[pos + 1..])? {
+                IntValue::Small(e) => e,
+                // this is far outside of the float range either way
+                IntValue::Big(e) => if e.sign() == num_bigint::Sign::Minus { i64::MIN } else { i64::MAX },
+            };
+            if rest[pos..].starts_with('e') {
+                (&rest[..pos], exponent, 0)
+            } else {
+                (&rest[..pos], 0, exponent)
+            }
+        }
+        None => (rest, 0, 0),
+    };
+
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let digits = format!("{}{}", int_part, frac_part);
+    let mantissa = BigUint::parse_bytes(digits.as_bytes(), base)?;
+
+    // value = mantissa * base^-frac_len * 10^exp10 * 2^exp2
+    let value = if mantissa.is_zero() {
+        0.0
+    } else {
+        let frac_len = frac_part.len() as i64;
+        let (mut num, mut den, mut exp2) = (mantissa, BigUint::from(1u32), exp2);
+        if base == 10 {
+            let exp10 = exp10.saturating_sub(frac_len);
+            // the magnitude is roughly 10^(exp10 + significant digits); cut off before the powers get absurdly large
+            let significant = digits.trim_start_matches('0').len();
+            let magnitude = exp10.saturating_add(significant as i64);
+            if magnitude > 310 {
+                return Some(if negative { f64::NEG_INFINITY } else { f64::INFINITY });
+            } else if magnitude < -330 {
+                return Some(if negative { -0.0 } else { 0.0 });
+            }
+            if exp10 >= 0 {
+                num *= BigUint::from(10u32).pow(exp10 as u32);
+            } else {
+                den = BigUint::from(10u32).pow((-exp10) as u32);
+            }
+        } else {
+            // binary and hex digits shift the binary exponent. Binary mantissas may still have a decimal exponent.
+            let bits_per_digit = if base == 16 { 4 } else { 1 };
+            exp2 = exp2.saturating_sub(frac_len * bits_per_digit);
+            let magnitude = exp10.saturating_abs();
+            if magnitude > 400 {
+                let huge = exp10 > 0;
+                return Some(match (huge, negative) {
+                    (true, false) => f64::INFINITY,
+                    (true, true) => f64::NEG_INFINITY,
+                    (false, false) => 0.0,
+                    (false, true) => -0.0,
+                });
+            }
+            if exp10 >= 0 {
+                num *= BigUint::from(10u32).pow(exp10 as u32);
+            } else {
+                den = BigUint::from(10u32).pow((-exp10) as u32);
+            }
+        }
+        ratio_to_f64(num, den, exp2)
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Round `num / den * 2^exp2` to the nearest `f64`, ties to even. `num` must be nonzero.
+fn ratio_to_f64(num: BigUint, den: BigUint, exp2: i64) -> f64 {
+    // the mantissa has 53 bits; we compute one more as the rounding bit, and remember if anything is left (sticky)
+    const PRECISION: i64 = 54;
+    // avoid overflowing the exponent arithmetic below; these are way outside of the float range anyway
+    if exp2 > 1 << 40 {
+        return f64::INFINITY;
+    } else if exp2 < -(1 << 40) {
+        return 0.0;
+    }
+    let shift = PRECISION + 1 - (num.bits() as i64 - den.bits() as i64);
+    let (num, den) = if shift >= 0 {
+        (num << shift as usize, den)
+    } else {
+        (num, den << (-shift) as usize)
+    };
+    let (mut q, r) = num.div_rem(&den);
+    let mut sticky = !r.is_zero();
+    // the value is now q * 2^e, with q having PRECISION+1 or PRECISION+2 bits
+    let mut e = exp2 - shift;
+    while (q.bits() as i64) > PRECISION {
+        sticky |= q.bit(0);
+        q >>= 1;
+        e += 1;
+    }
+    // the lowest mantissa bit has weight 2^(e+1); subnormals can't go below 2^-1074
+    if e + 1 < -1074 {
+        let extra = -1074 - (e + 1);
+        if extra > PRECISION {
+            return 0.0;
+        }
+        let dropped = &q & ((BigUint::from(1u32) << extra as usize) - 1u32);
+        sticky |= !dropped.is_zero();
+        q >>= extra as usize;
+        e += extra;
+    }
+    let round = q.bit(0);
+    let mut mantissa = (q >> 1usize).to_u64().unwrap();
+    let mut lsb_exp = e + 1;
+    if round && (sticky || mantissa & 1 == 1) {
+        mantissa += 1;
+        if mantissa == 1 << 53 {
+            mantissa >>= 1;
+            lsb_exp += 1;
+        }
+    }
+    if mantissa < 1 << 52 {
+        // subnormal (or zero after rounding): the exponent field is zero
+        return f64::from_bits(mantissa);
+    }
+    let biased = lsb_exp + 52 + 1023;
+    if biased >= 0x7ff {
+        return f64::INFINITY;
+    }
+    f64::from_bits(((biased as u64) << 52) | (mantissa & ((1 << 52) - 1)))
+}
+
+/// The builtin conversions to `float`: from ints, bools (`0.0` or `1.0`) and strings in the syntax of float literals.
+fn float_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    let float = if let Some(value) = MIntImpl::value_of(value) {
+        MFloatImpl::new(value.to_f64())
+    } else if let Some(value) = MBoolImpl::value_of(value) {
+        MFloatImpl::new(if value { 1.0 } else { 0.0 })
+    } else if let Some(value) = MStringImpl::value_of(value) {
+        MFloatImpl::parse(&value)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(float.wrap()))
+}
+
+pub(super) fn create_float_type() -> MTypeRef {
+    MTypeImpl::new("float", None, vec![BUILTINS.get_type("obj")]).with_from(float_from).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_and_binary_literals() {
+        assert_eq!(parse_float_literal("0x1.8p3"), Some(12.0));
+        assert_eq!(parse_float_literal("-0x.8"), Some(-0.5));
+        assert_eq!(parse_float_literal("0b1.1"), Some(1.5));
+        assert_eq!(parse_float_literal("0b1.1e2"), Some(150.0));
+        assert_eq!(parse_float_literal("0x1p-1074"), Some(f64::from_bits(1)));
+        assert_eq!(parse_float_literal("0x1.fffffffffffffp1023"), Some(f64::MAX));
+        assert_eq!(parse_float_literal("0x1p1024"), Some(f64::INFINITY));
+        // exponents can have any base as well
+        assert_eq!(parse_float_literal("1.5p0x4"), Some(24.0));
+        assert_eq!(parse_float_literal("1e-0b11"), Some(0.001));
+    }
+
+    #[test]
+    fn rounding() {
+        // exactly halfway between the two nearest floats, so this rounds to even
+        assert_eq!(parse_float_literal("0x1.fffffffffffff8p0"), Some(2.0));
+        assert_eq!(parse_float_literal("0x1.00000000000008p0"), Some(1.0));
+        assert_eq!(parse_float_literal("9_007_199_254_740_993"), Some(9007199254740992.0));
+        for text in ["0.1", "123.456e-7", "-4.32e36", "2.2250738585072011e-308", "4.9e-324", "1.7976931348623157e308"] {
+            assert_eq!(parse_float_literal(text), text.parse::<f64>().ok(), "{}", text);
+        }
+        assert_eq!(parse_float_literal("1e-400"), Some(0.0));
+        assert_eq!(parse_float_literal("1.8e308"), Some(f64::INFINITY));
+        // leading zeros don't count towards the magnitude
+        assert_eq!(parse_float_literal("0.0000000001e311"), Some(1e301));
+        assert_eq!(parse_float_literal("00000000000001e300"), Some(1e300));
+        assert_eq!(parse_float_literal("0_000_000_000_001e300"), Some(1e300));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let one: MObjectRef = MFloatImpl::new(1.0).wrap();
+        for zero in [MFloatImpl::new(-0.0).wrap() as MObjectRef, MIntImpl::from(0).wrap()] {
+            for op in [BinaryOperator::Div, BinaryOperator::Mod] {
+                assert!(one.binop(zero.clone(), op).is_err());
+                assert!(MIntImpl::from(1).binop(zero.clone(), op).is_err());
+            }
+        }
+        // infinities are still fine
+        let inf = MFloatImpl::new(f64::INFINITY).wrap();
+        assert_eq!(MFloatImpl::value_of(&one.binop(inf, BinaryOperator::Div).ok().unwrap()), Some(0.0));
+    }
+}
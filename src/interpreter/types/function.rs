@@ -1,29 +1,239 @@
-use std::{collections::HashMap, sync::{Arc, RwLock}};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
 
-use super::{MshValue, string::MshString};
+use crate::interpreter::{
+    log::Logger,
+    scopes::{FieldRef, StaticField, VarScope, VarScopeRefType},
+    stackmachine::{Invocation, StackMachine, Statement},
+};
 
-type ArgType = Arc<RwLock<dyn MshValue>>;
+use super::{
+    builtin::BUILTINS,
+    object::{CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
 
-#[derive(Debug)]
-pub struct MshFunction {
-  pub name: String,
-  pub doc: String,
-  pub args: Vec<Option<ArgType>>,
-  pub kwargs: HashMap<String, Option<ArgType>>,
-  pub ret: Option<ArgType>,
-  pub instructions: Vec<Statement>
+/// The Rust signature of a function that can be called from mscript: positional and keyword arguments.
+pub type NativeFn = dyn Fn(Vec<MObjectRef>, HashMap<String, MObjectRef>) -> MFuncResult + Send + Sync;
+
+/**
+A function implemented in Rust. This is how builtin functions (like `len`) and the methods
+of builtin types are exposed: methods are created on access, with the receiver captured in the closure.
+ */
+pub struct MNativeFunctionImpl {
+    mobject: MObjectImpl,
+    name: String,
+    docstring: Option<String>,
+    func: Box<NativeFn>,
+}
+pub type MNativeFunctionImplRef = Arc<RwLock<MNativeFunctionImpl>>;
+impl MObject for MNativeFunctionImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("func")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(format!("<builtin func {}>", self.name)).wrap())
+    }
+    fn call(&self, args: Vec<MObjectRef>, kwargs: HashMap<String, MObjectRef>) -> MFuncResult {
+        (self.func)(args, kwargs)
+    }
+    fn docstring(&self) -> Option<String> {
+        self.docstring.clone()
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl MNativeFunctionImpl {
+    pub fn new(
+        name: &str,
+        func: impl Fn(Vec<MObjectRef>, HashMap<String, MObjectRef>) -> MFuncResult + Send + Sync + 'static,
+    ) -> Self {
+        MNativeFunctionImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("func")),
+            name: name.to_owned(),
+            docstring: None,
+            func: Box::new(func),
+        }
+    }
+    pub fn with_docstring(mut self, docstring: &str) -> Self {
+        self.docstring = Some(docstring.to_owned());
+        self
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// Wrap the function in a readonly field, as returned by `dot` for the methods of builtin types.
+    pub fn into_field(self) -> FieldRef {
+        let (name, docstring) = (self.name.clone(), self.docstring.clone());
+        Arc::new(RwLock::new(StaticField::new(Some(name), docstring, Some(self.wrap()), true)))
+    }
+}
+
+/// The compiled code of a function defined in mscript, as created by `Statement::MakeFunction`.
+pub struct FunctionCode {
+    pub name: String,
+    /// the names of the parameters, and whether they have a default value
+    pub params: Vec<(String, bool)>,
+    /// the doc comment of the definition
+    pub docstring: Option<String>,
+    pub instructions: Vec<Statement>,
+}
+/// Only the signature, so that tracing `MakeFunction` doesn't dump the whole body.
+impl fmt::Debug for FunctionCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<_> = self.params.iter().map(|(name, _)| name.as_str()).collect();
+        write!(f, "func {}({})", self.name, params.join(", "))
+    }
 }
-impl MshValue for MshFunction {
-    fn objtype(&self) -> Arc<RwLock<dyn MshValue>> {
-        Arc::new(RwLock::new(MshString::from("func")))
+
+/**
+A function defined in mscript. Default values are evaluated once, when the function is defined.
+Calling the function runs its body in a new scope inside the scope it was defined in, where
+the parameters are declared as local variables.
+ */
+pub struct MFunctionImpl {
+    mobject: MObjectImpl,
+    code: Arc<FunctionCode>,
+    /// the default values of the parameters that have one, in order
+    defaults: Vec<MObjectRef>,
+    /// the scope the function was defined in
+    scope: Arc<RwLock<VarScope>>,
+    /// the logger in effect where the function was defined
+    logger: Logger,
+}
+pub type MFunctionImplRef = Arc<RwLock<MFunctionImpl>>;
+impl MObject for MFunctionImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("func")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(format!("<func {}>", self.code.name)).wrap())
+    }
+    fn call(&self, args: Vec<MObjectRef>, kwargs: HashMap<String, MObjectRef>) -> MFuncResult {
+        let scope = self.bind_args(args, kwargs)?;
+        StackMachine::exec_invoked(&self.code.instructions, scope, self.logger.clone(), Invocation::Run, None)
     }
+    fn docstring(&self) -> Option<String> {
+        self.code.docstring.clone()
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 
-    fn call(
-            &self,
-            args: Vec<Arc<RwLock<dyn MshValue>>>,
-            kwargs: HashMap<String, Arc<RwLock<dyn MshValue>>>,
-            scope: Arc<RwLock<VarScope>>,
-        ) -> Result<Arc<RwLock<dyn MshValue>>, Arc<RwLock<dyn MshValue>>> {
-        StackMachine::exec(&self.instructions, Arc::new(RwLock::new(VarScope::new_local(scope.clone(), false))))
+impl MFunctionImpl {
+    pub fn new(
+        code: Arc<FunctionCode>,
+        defaults: Vec<MObjectRef>,
+        scope: Arc<RwLock<VarScope>>,
+        logger: Logger,
+    ) -> Self {
+        MFunctionImpl { mobject: MObjectImpl::new(BUILTINS.get_type("func")), code, defaults, scope, logger }
+    }
+    pub fn wrap(self) -> MFunctionImplRef {
+        Arc::new(RwLock::new(self))
     }
-}
\ No newline at end of file
+
+    /**
+    Create the scope for a call, with a local variable for each parameter. Positional arguments are bound first,
+    then keyword arguments by name; parameters that got neither take their default value.
+     */
+    fn bind_args(
+        &self,
+        args: Vec<MObjectRef>,
+        mut kwargs: HashMap<String, MObjectRef>,
+    ) -> Result<Arc<RwLock<VarScope>>, MObjectRef> {
+        let name = &self.code.name;
+        let error = |msg: String| MStringImpl::from(msg).wrap() as MObjectRef;
+        if args.len() > self.code.params.len() {
+            return Err(error(format!(
+                "`{}` takes {} arguments, but {} were given",
+                name,
+                self.code.params.len(),
+                args.len()
+            )));
+        }
+        let strict_assign = self.scope.read().unwrap().strict_assign;
+        let mut scope = VarScope::new_local(self.scope.clone(), strict_assign);
+        let mut args = args.into_iter();
+        let mut defaults = self.defaults.iter();
+        for (param, has_default) in &self.code.params {
+            let default = if *has_default { defaults.next().cloned() } else { None };
+            let value = match (args.next(), kwargs.remove(param)) {
+                (Some(_), Some(_)) => return Err(error(format!("`{}` got multiple values for `{}`", name, param))),
+                (Some(value), None) | (None, Some(value)) => value,
+                (None, None) => match default {
+                    Some(default) => default,
+                    None => return Err(error(format!("`{}` is missing the argument `{}`", name, param))),
+                },
+            };
+            let field = Arc::new(RwLock::new(StaticField::new(Some(param.clone()), None, Some(value), false)));
+            scope.declare(param, VarScopeRefType::LocalValue(field)).map_err(error)?;
+        }
+        if let Some(key) = kwargs.keys().next() {
+            return Err(error(format!("`{}` got an unexpected keyword argument `{}`", name, key)));
+        }
+        Ok(Arc::new(RwLock::new(scope)))
+    }
+}
+
+/**
+Check the arguments passed to a native function which only takes positional arguments,
+between `min` and `max` of them.
+ */
+pub fn check_args(
+    name: &str,
+    args: &[MObjectRef],
+    kwargs: &HashMap<String, MObjectRef>,
+    min: usize,
+    max: usize,
+) -> Result<(), MObjectRef> {
+    if let Some(key) = kwargs.keys().next() {
+        return Err(MStringImpl::from(format!("`{}` got an unexpected keyword argument `{}`", name, key)).wrap());
+    }
+    if args.len() < min || args.len() > max {
+        let expected = if min == max {
+            format!("{}", min)
+        } else {
+            format!("{} to {}", min, max)
+        };
+        return Err(MStringImpl::from(format!(
+            "`{}` takes {} arguments, but {} were given",
+            name,
+            expected,
+            args.len()
+        ))
+        .wrap());
+    }
+    Ok(())
+}
+
+pub(super) fn create_func_type() -> MTypeRef {
+    MTypeImpl::new("func", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
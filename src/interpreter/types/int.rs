@@ -0,0 +1,418 @@
+use std::{
+    any::Any,
+    fmt::Display,
+    sync::{Arc, RwLock},
+};
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+
+use crate::interpreter::scopes::FieldRef;
+
+use super::{
+    boolean::MBoolImpl,
+    builtin::BUILTINS,
+    float::MFloatImpl,
+    object::{binop_unsupported, unop_unsupported, CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    BinaryOperator, MFuncResult, MTypeImpl, MTypeRef, UnaryOperator,
+};
+use delegate::delegate;
+
+/// The size limit of `**` on ints in bits, give or take a factor of two: 64 Mbit, i.e. 8 MiB.
+/// Larger powers are errors, instead of taking minutes and all memory to compute.
+pub const MAX_POW_BITS: u64 = 1 << 26;
+
+/**
+The value of an mscript `int`. Integers have arbitrary precision: values are stored as a machine integer
+as long as they fit, and transparently promoted to a `BigInt` whenever an operation would overflow.
+Results are always normalized, so the same number can't have two different representations.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IntValue {
+    Small(i64),
+    Big(BigInt),
+}
+
+impl IntValue {
+    /// demote a `BigInt` to a machine integer if it fits
+    pub fn normalize(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(i) => IntValue::Small(i),
+            None => IntValue::Big(value),
+        }
+    }
+
+    pub fn to_bigint(&self) -> BigInt {
+        match self {
+            IntValue::Small(i) => BigInt::from(*i),
+            IntValue::Big(b) => b.clone(),
+        }
+    }
+
+    /// The closest float to this integer, which may be infinite for very large numbers.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            IntValue::Small(i) => *i as f64,
+            IntValue::Big(b) => b.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// Convert a float to an integer, truncating towards zero. Fails for infinities and `NaN`.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        BigInt::from_f64(value.trunc()).map(Self::normalize)
+    }
+
+    /**
+    Parse an integer literal as accepted by the lexer (`DEC_INT`, `HEX_INT` or `BIN_INT`):
+    an optional sign, an optional `0x`/`0b` prefix and digits with optional `_` separators.
+    The literal can be of any length.
+     */
+    pub fn parse_literal(text: &str) -> Option<Self> {
+        let text = text.replace('_', "");
+        let (negative, digits) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, &text[..]),
+        };
+        let (radix, digits) = if let Some(d) = digits.strip_prefix("0x") {
+            (16, d)
+        } else if let Some(d) = digits.strip_prefix("0b") {
+            (2, d)
+        } else {
+            (10, digits)
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let value = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+        Some(Self::normalize(if negative { -value } else { value }))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            IntValue::Small(i) => *i == 0,
+            IntValue::Big(b) => b.is_zero(),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            IntValue::Small(i) => *i < 0,
+            IntValue::Big(b) => b.is_negative(),
+        }
+    }
+
+    /// The number of bits of the magnitude, i.e. zero for zero.
+    fn bits(&self) -> u64 {
+        match self {
+            IntValue::Small(i) => u64::from(64 - i.unsigned_abs().leading_zeros()),
+            IntValue::Big(b) => b.bits(),
+        }
+    }
+
+    /// try the operation on machine integers, and fall back to `BigInt`s if it overflows.
+    fn promoting(
+        &self,
+        other: &Self,
+        small: impl FnOnce(i64, i64) -> Option<i64>,
+        big: impl FnOnce(&BigInt, &BigInt) -> BigInt,
+    ) -> Self {
+        if let (IntValue::Small(a), IntValue::Small(b)) = (self, other) {
+            if let Some(result) = small(*a, *b) {
+                return IntValue::Small(result);
+            }
+        }
+        Self::normalize(big(&self.to_bigint(), &other.to_bigint()))
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        self.promoting(other, i64::checked_add, |a, b| a + b)
+    }
+    pub fn sub(&self, other: &Self) -> Self {
+        self.promoting(other, i64::checked_sub, |a, b| a - b)
+    }
+    pub fn mul(&self, other: &Self) -> Self {
+        self.promoting(other, i64::checked_mul, |a, b| a * b)
+    }
+    /// The remainder of a flooring division, i.e. it has the sign of the divisor. Fails for a zero divisor.
+    pub fn modulo(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        Some(self.promoting(
+            other,
+            |a, b| {
+                a.checked_rem(b)
+                    .map(|r| if r != 0 && (r < 0) != (b < 0) { r + b } else { r })
+            },
+            |a, b| a.mod_floor(b),
+        ))
+    }
+    /**
+    Raise to a non-negative power. Fails if the result is too large (see `MAX_POW_BITS`), which is only possible
+    for bases other than `0`, `1` and `-1`.
+     */
+    pub fn pow(&self, exponent: &Self) -> Option<Self> {
+        debug_assert!(!exponent.is_negative());
+        match self {
+            IntValue::Small(0 | 1) if exponent.is_zero() => return Some(IntValue::Small(1)),
+            IntValue::Small(0 | 1) => return Some(self.clone()),
+            IntValue::Small(-1) => {
+                let odd = match exponent {
+                    IntValue::Small(e) => e.is_odd(),
+                    IntValue::Big(e) => e.is_odd(),
+                };
+                return Some(IntValue::Small(if odd { -1 } else { 1 }));
+            }
+            _ => {}
+        }
+        let exponent = match exponent {
+            IntValue::Small(e) if (self.bits() - 1).saturating_mul(*e as u64) < MAX_POW_BITS => *e as u32,
+            _ => return None,
+        };
+        Some(match self {
+            IntValue::Small(a) => match a.checked_pow(exponent) {
+                Some(result) => IntValue::Small(result),
+                None => Self::normalize(BigInt::from(*a).pow(exponent)),
+            },
+            IntValue::Big(a) => Self::normalize(a.pow(exponent)),
+        })
+    }
+    pub fn bitand(&self, other: &Self) -> Self {
+        self.promoting(other, |a, b| Some(a & b), |a, b| a & b)
+    }
+    pub fn bitor(&self, other: &Self) -> Self {
+        self.promoting(other, |a, b| Some(a | b), |a, b| a | b)
+    }
+    pub fn bitxor(&self, other: &Self) -> Self {
+        self.promoting(other, |a, b| Some(a ^ b), |a, b| a ^ b)
+    }
+    /// Bitwise negation in two's complement, i.e. `-x - 1`
+    pub fn bitnot(&self) -> Self {
+        match self {
+            IntValue::Small(a) => IntValue::Small(!a),
+            IntValue::Big(a) => Self::normalize(!a),
+        }
+    }
+}
+
+impl Display for IntValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntValue::Small(i) => write!(f, "{}", i),
+            IntValue::Big(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+pub trait MInt: MObject {
+    fn value(&self) -> &IntValue;
+}
+pub type MIntRef = Arc<RwLock<dyn MInt>>;
+
+pub struct MIntImpl {
+    mobject: MObjectImpl,
+    value: IntValue,
+}
+pub type MIntImplRef = Arc<RwLock<MIntImpl>>;
+impl MObject for MIntImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("int")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.value.to_string()).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(self.value.to_string())
+    }
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        // mixed arithmetic is done in floating point
+        if MFloatImpl::value_of(&other).is_some() {
+            return MFloatImpl::new(self.value.to_f64()).binop(other, op);
+        }
+        let rhs = match MIntImpl::value_of(&other) {
+            Some(rhs) => rhs,
+            None => return Err(binop_unsupported(self, &other, op)),
+        };
+        let result = match op {
+            BinaryOperator::Plus => self.value.add(&rhs),
+            BinaryOperator::Minus => self.value.sub(&rhs),
+            BinaryOperator::Mul => self.value.mul(&rhs),
+            // true division always results in a float, like in Python. Dividing by zero is an error, as for floats.
+            BinaryOperator::Div => {
+                if rhs.is_zero() {
+                    return Err(MStringImpl::from("division by zero").wrap());
+                }
+                return Ok(MFloatImpl::new(self.value.to_f64() / rhs.to_f64()).wrap());
+            }
+            BinaryOperator::Mod => match self.value.modulo(&rhs) {
+                Some(result) => result,
+                None => return Err(MStringImpl::from("modulo by zero").wrap()),
+            },
+            // negative exponents leave the integers
+            BinaryOperator::Pow if rhs.is_negative() => {
+                return Ok(MFloatImpl::new(self.value.to_f64().powf(rhs.to_f64())).wrap());
+            }
+            BinaryOperator::Pow => match self.value.pow(&rhs) {
+                Some(result) => result,
+                None => return Err(MStringImpl::from(format!("`{} ** {}` is too large", self.value, rhs)).wrap()),
+            },
+            BinaryOperator::BitAnd => self.value.bitand(&rhs),
+            BinaryOperator::BitOr => self.value.bitor(&rhs),
+            BinaryOperator::Xor => self.value.bitxor(&rhs),
+            _ => return Err(binop_unsupported(self, &other, op)),
+        };
+        Ok(MIntImpl::from(result).wrap())
+    }
+    fn unop(&self, op: UnaryOperator) -> MFuncResult {
+        let result = match op {
+            UnaryOperator::Inc => self.value.add(&IntValue::Small(1)),
+            UnaryOperator::Dec => self.value.sub(&IntValue::Small(1)),
+            UnaryOperator::Bitnot => self.value.bitnot(),
+            _ => return Err(unop_unsupported(self, op)),
+        };
+        Ok(MIntImpl::from(result).wrap())
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(match MIntImpl::value_of(other) {
+            Some(rhs) => rhs == self.value,
+            None => MFloatImpl::value_of(other) == Some(self.value.to_f64()),
+        })
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl MInt for MIntImpl {
+    fn value(&self) -> &IntValue {
+        &self.value
+    }
+}
+
+impl MIntImpl {
+    pub fn new(value: IntValue) -> Self {
+        MIntImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("int")),
+            value,
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// The integer value of an object, if it is an `int`.
+    pub fn value_of(obj: &MObjectRef) -> Option<IntValue> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MIntImpl>()
+            .map(|o| o.value.clone())
+    }
+    /// Convert a float to an `int`, truncating towards zero.
+    pub fn from_float(value: f64) -> Result<Self, MObjectRef> {
+        IntValue::from_f64(value).map(Self::new).ok_or_else(|| {
+            MStringImpl::from(format!("cannot convert float `{}` to int", value)).wrap() as MObjectRef
+        })
+    }
+    /// Parse a string as an `int`, accepting the same syntax as integer literals.
+    pub fn parse(value: &str) -> Result<Self, MObjectRef> {
+        IntValue::parse_literal(value.trim()).map(Self::new).ok_or_else(|| {
+            MStringImpl::from(format!("invalid int format: `{}`", value)).wrap() as MObjectRef
+        })
+    }
+    pub fn to_float(&self) -> MFloatImpl {
+        MFloatImpl::new(self.value.to_f64())
+    }
+}
+impl From<IntValue> for MIntImpl {
+    fn from(value: IntValue) -> Self {
+        Self::new(value)
+    }
+}
+impl From<i64> for MIntImpl {
+    fn from(value: i64) -> Self {
+        Self::new(IntValue::Small(value))
+    }
+}
+
+/// The builtin conversions to `int`: floats are truncated towards zero, bools are `0` or `1`, and strings are parsed
+/// like integer literals.
+fn int_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    let int = if let Some(value) = MFloatImpl::value_of(value) {
+        MIntImpl::from_float(value)?
+    } else if let Some(value) = MBoolImpl::value_of(value) {
+        MIntImpl::from(value as i64)
+    } else if let Some(value) = MStringImpl::value_of(value) {
+        MIntImpl::parse(&value)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(int.wrap()))
+}
+
+pub(super) fn create_int_type() -> MTypeRef {
+    MTypeImpl::new("int", None, vec![BUILTINS.get_type("obj")]).with_from(int_from).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_literals() {
+        assert_eq!(IntValue::parse_literal("1_000"), Some(IntValue::Small(1000)));
+        assert_eq!(IntValue::parse_literal("-0x1F"), Some(IntValue::Small(-31)));
+        assert_eq!(IntValue::parse_literal("+0b1010_1010"), Some(IntValue::Small(170)));
+        assert_eq!(
+            IntValue::parse_literal("0x1_0000_0000_0000_0000"),
+            Some(IntValue::Big(BigInt::from(u64::MAX) + 1))
+        );
+        assert_eq!(IntValue::parse_literal("0x"), None);
+    }
+
+    #[test]
+    fn promote_on_overflow() {
+        let max = IntValue::Small(i64::MAX);
+        let one = IntValue::Small(1);
+        let promoted = max.add(&one);
+        assert!(matches!(promoted, IntValue::Big(_)));
+        // and results that fit again are demoted
+        assert_eq!(promoted.sub(&one), max);
+        assert_eq!(IntValue::Small(2).pow(&IntValue::Small(64)), Some(IntValue::Big(BigInt::from(u64::MAX) + 1)));
+        assert_eq!(IntValue::Small(i64::MIN).modulo(&IntValue::Small(-1)), Some(IntValue::Small(0)));
+    }
+
+    #[test]
+    fn floor_modulo() {
+        assert_eq!(IntValue::Small(-7).modulo(&IntValue::Small(3)), Some(IntValue::Small(2)));
+        assert_eq!(IntValue::Small(7).modulo(&IntValue::Small(-3)), Some(IntValue::Small(-2)));
+        assert_eq!(IntValue::Small(7).modulo(&IntValue::Small(0)), None);
+    }
+
+    #[test]
+    fn pow_limits() {
+        let big = IntValue::Small(5_000_000_000);
+        assert_eq!(IntValue::Small(-1).pow(&big), Some(IntValue::Small(1)));
+        assert_eq!(IntValue::Small(0).pow(&big), Some(IntValue::Small(0)));
+        assert_eq!(IntValue::Small(0).pow(&IntValue::Small(0)), Some(IntValue::Small(1)));
+        assert_eq!(IntValue::Small(2).pow(&big), None);
+        assert_eq!(IntValue::Small(2).pow(&IntValue::Small(u32::MAX as i64 - 1)), None);
+        assert!(IntValue::Small(2).pow(&IntValue::Small(100_000)).is_some());
+
+        let two: MObjectRef = MIntImpl::from(2).wrap();
+        let error = two.binop(MIntImpl::from(5_000_000_000i64).wrap(), BinaryOperator::Pow).err().unwrap();
+        assert_eq!(error.to_ext_string(0, false).ok(), Some("`2 ** 5000000000` is too large".to_owned()));
+        let half = two.binop(MIntImpl::from(-1).wrap(), BinaryOperator::Pow).ok().unwrap();
+        assert_eq!(MFloatImpl::value_of(&half), Some(0.5));
+    }
+}
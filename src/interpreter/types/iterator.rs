@@ -0,0 +1,105 @@
+use lazy_static::lazy_static;
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::scopes::FieldRef;
+
+use super::{
+    builtin::BUILTINS,
+    object::{CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+/// Produces the next element of an iteration, or `None` when it's exhausted.
+pub type NextFn = dyn FnMut() -> Result<Option<MObjectRef>, MObjectRef> + Send + Sync;
+
+/**
+The iterator returned by the builtin types' `iter`. It's just a closure holding the iteration state;
+builtin containers use it to walk their elements without going through `$next`.
+ */
+pub struct MIteratorImpl {
+    mobject: MObjectImpl,
+    next: RwLock<Box<NextFn>>,
+}
+pub type MIteratorImplRef = Arc<RwLock<MIteratorImpl>>;
+impl MObject for MIteratorImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("iterator")
+    }
+    fn next(&self) -> Result<Option<MObjectRef>, MObjectRef> {
+        (self.next.write().unwrap())()
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl MIteratorImpl {
+    pub fn new(next: impl FnMut() -> Result<Option<MObjectRef>, MObjectRef> + Send + Sync + 'static) -> Self {
+        MIteratorImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("iterator")),
+            next: RwLock::new(Box::new(next)),
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// Iterate over a snapshot of elements.
+    pub fn from_vec(items: Vec<MObjectRef>) -> Self {
+        let mut items = items.into_iter();
+        Self::new(move || Ok(items.next()))
+    }
+}
+
+lazy_static! {
+    // like `none`, the end of iteration marker is a single shared value.
+    static ref MSH_STOP_ITERATION: MStopIterationRef = Arc::new(RwLock::new(MStopIteration));
+}
+
+/// The value returned by a user type's `$next` method to signal that the iteration is over.
+pub struct MStopIteration;
+pub type MStopIterationRef = Arc<RwLock<MStopIteration>>;
+impl MObject for MStopIteration {
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("iterator")
+    }
+    fn str_nice(&self) -> MFuncResult {
+        Ok(MStringImpl::from("stop_iteration").wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok("stop_iteration".to_owned())
+    }
+    fn get_field(&self, _name: &str) -> Option<FieldRef> {
+        None
+    }
+    fn insert_field(&self, _field: FieldRef) {}
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl MStopIteration {
+    pub fn refer() -> MStopIterationRef {
+        MSH_STOP_ITERATION.clone()
+    }
+    pub fn is(obj: &MObjectRef) -> bool {
+        obj.read().unwrap().as_any().is::<MStopIteration>()
+    }
+}
+
+pub(super) fn create_iterator_type() -> MTypeRef {
+    MTypeImpl::new("iterator", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
@@ -0,0 +1,476 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::scopes::{DynamicField, FieldRef};
+
+use super::{
+    builtin::BUILTINS,
+    function::{check_args, MNativeFunctionImpl},
+    int::{IntValue, MIntImpl},
+    iterator::MIteratorImpl,
+    none::MNone,
+    object::{binop_unsupported, deep_clone, same_object, CloneMemo, MObject, MObjectImpl, MObjectRef, MAX_EXTSTR_DEPTH},
+    string::MStringImpl,
+    BinaryOperator, MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+pub trait MList: MObject {
+    fn items(&self) -> Vec<MObjectRef>;
+}
+pub type MListRef = Arc<RwLock<dyn MList>>;
+
+/**
+The builtin mutable sequence type. The elements live behind their own lock, so that fields
+and bound methods handed out by the list (`xs[0]`, `xs.append`) can keep modifying it
+without needing a reference to the list object itself.
+ */
+pub struct MListImpl {
+    mobject: MObjectImpl,
+    items: Arc<RwLock<Vec<MObjectRef>>>,
+}
+pub type MListImplRef = Arc<RwLock<MListImpl>>;
+impl MObject for MListImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("list")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.to_ext_string(0, true)?).wrap())
+    }
+    fn to_ext_string(&self, depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(format!("[{}]", join_repr(&self.items.read().unwrap(), depth)?))
+    }
+    fn dot(&self, name: &str) -> Result<FieldRef, MObjectRef> {
+        match list_method(&self.items, name) {
+            Some(method) => Ok(method.into_field()),
+            None => self.mobject.dot(name),
+        }
+    }
+    fn index(&self, index: MObjectRef) -> Result<FieldRef, MObjectRef> {
+        let i = resolve_index(self.items.read().unwrap().len(), &index)?;
+        let (get_items, set_items) = (self.items.clone(), self.items.clone());
+        // the index is resolved once, so a field stays valid as long as the list doesn't shrink
+        let missing = move || MStringImpl::from(format!("list index {} out of range", i)).wrap() as MObjectRef;
+        Ok(Arc::new(RwLock::new(DynamicField::new(
+            format!("[{}]", i),
+            None,
+            Some(Box::new(move || get_items.read().unwrap().get(i).cloned().ok_or_else(missing))),
+            Some(Box::new(move |value| {
+                let mut items = set_items.write().unwrap();
+                match items.get_mut(i) {
+                    Some(item) => Ok(std::mem::replace(item, value)),
+                    None => Err(missing()),
+                }
+            })),
+            None,
+        ))))
+    }
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        match op {
+            // concatenation creates a new list; the right side can be anything that can be unpacked
+            BinaryOperator::Plus => {
+                let mut items = self.items();
+                items.extend(other.unpack()?);
+                Ok(MListImpl::new(items).wrap())
+            }
+            BinaryOperator::Mul => Ok(MListImpl::new(repeat(&self.items(), &other, self, op)?).wrap()),
+            _ => Err(binop_unsupported(self, &other, op)),
+        }
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        if same_object(self, other) {
+            return Ok(true);
+        }
+        match MListImpl::items_of(other) {
+            Some(rhs) => sequence_equals(&self.items(), &rhs),
+            None => Ok(false),
+        }
+    }
+    fn contains(&self, item: &MObjectRef) -> Result<bool, MObjectRef> {
+        sequence_contains(&self.items(), item)
+    }
+    fn len(&self) -> Result<usize, MObjectRef> {
+        Ok(self.items.read().unwrap().len())
+    }
+    fn slice(
+        &self,
+        start: Option<MObjectRef>,
+        stop: Option<MObjectRef>,
+        step: Option<MObjectRef>,
+    ) -> MFuncResult {
+        let items = self.items.read().unwrap();
+        let indices = slice_indices(items.len(), start, stop, step)?;
+        Ok(MListImpl::new(indices.into_iter().map(|i| items[i].clone()).collect()).wrap())
+    }
+    fn unpack(&self) -> Result<Vec<MObjectRef>, MObjectRef> {
+        Ok(self.items())
+    }
+    /// Iteration walks the live list, so elements appended during a loop are visited as well.
+    fn iter(&self) -> MFuncResult {
+        let items = self.items.clone();
+        let mut i = 0;
+        Ok(MIteratorImpl::new(move || {
+            let item = items.read().unwrap().get(i).cloned();
+            i += 1;
+            Ok(item)
+        })
+        .wrap())
+    }
+    fn clone_value(&self, memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        clone_items(&self.items, memo).map(Some)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl MList for MListImpl {
+    fn items(&self) -> Vec<MObjectRef> {
+        self.items.read().unwrap().clone()
+    }
+}
+
+impl MListImpl {
+    pub fn new(items: Vec<MObjectRef>) -> Self {
+        MListImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("list")),
+            items: Arc::new(RwLock::new(items)),
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// A snapshot of the elements of an object, if it is a `list`.
+    pub fn items_of(obj: &MObjectRef) -> Option<Vec<MObjectRef>> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MListImpl>()
+            .map(|o| o.items())
+    }
+}
+impl From<Vec<MObjectRef>> for MListImpl {
+    fn from(items: Vec<MObjectRef>) -> Self {
+        Self::new(items)
+    }
+}
+
+/// Deep-copy the elements of a list into a new one, which is put in `memo` first so that cycles lead back to it.
+fn clone_items(items: &Arc<RwLock<Vec<MObjectRef>>>, memo: &mut CloneMemo) -> MFuncResult {
+    let key = Arc::as_ptr(items) as usize;
+    if let Some(copy) = memo.get(&key) {
+        return Ok(copy.clone());
+    }
+    let copy = MListImpl::new(Vec::new());
+    let copy_items = copy.items.clone();
+    let copy: MObjectRef = copy.wrap();
+    memo.insert(key, copy.clone());
+    let originals = items.read().unwrap().clone();
+    let cloned = originals
+        .iter()
+        .map(|item| deep_clone(item, memo))
+        .collect::<Result<Vec<_>, _>>()?;
+    *copy_items.write().unwrap() = cloned;
+    Ok(copy)
+}
+
+/// The methods of `list`, bound to the elements of a specific list.
+fn list_method(items: &Arc<RwLock<Vec<MObjectRef>>>, name: &str) -> Option<MNativeFunctionImpl> {
+    let items = items.clone();
+    let method = match name {
+        "append" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("append", &args, &kwargs, 1, 1)?;
+            items.write().unwrap().extend(args);
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "extend" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("extend", &args, &kwargs, 1, 1)?;
+            // unpack before locking, in case a list is extended with itself
+            let new_items = args[0].unpack()?;
+            items.write().unwrap().extend(new_items);
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "insert" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("insert", &args, &kwargs, 2, 2)?;
+            let mut items = items.write().unwrap();
+            // like slicing, inserting clamps out of range indices instead of failing
+            let len = items.len() as i64;
+            let mut i = int_arg(&args[0], "list index")?;
+            if i < 0 {
+                i = i.saturating_add(len);
+            }
+            items.insert(i.clamp(0, len) as usize, args[1].clone());
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "pop" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("pop", &args, &kwargs, 0, 1)?;
+            let mut items = items.write().unwrap();
+            if items.is_empty() {
+                return Err(MStringImpl::from("pop from empty list").wrap());
+            }
+            let i = match args.first() {
+                Some(index) => resolve_index(items.len(), index)?,
+                None => items.len() - 1,
+            };
+            Ok(items.remove(i))
+        }),
+        "remove" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("remove", &args, &kwargs, 1, 1)?;
+            let i = position(&items.read().unwrap(), &args[0])?;
+            match i {
+                Some(i) => Ok(items.write().unwrap().remove(i)),
+                None => Err(MStringImpl::from("list.remove: value not in list").wrap()),
+            }
+        }),
+        "clear" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("clear", &args, &kwargs, 0, 0)?;
+            items.write().unwrap().clear();
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "reverse" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("reverse", &args, &kwargs, 0, 0)?;
+            items.write().unwrap().reverse();
+            Ok(MNone::refer() as MObjectRef)
+        }),
+        "index" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("index", &args, &kwargs, 1, 1)?;
+            match position(&items.read().unwrap(), &args[0])? {
+                Some(i) => Ok(MIntImpl::from(i as i64).wrap()),
+                None => Err(MStringImpl::from("list.index: value not in list").wrap()),
+            }
+        }),
+        "count" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("count", &args, &kwargs, 1, 1)?;
+            Ok(MIntImpl::from(count(&items.read().unwrap(), &args[0])? as i64).wrap())
+        }),
+        "copy" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("copy", &args, &kwargs, 0, 0)?;
+            Ok(MListImpl::new(items.read().unwrap().clone()).wrap())
+        }),
+        "$clone" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("$clone", &args, &kwargs, 0, 0)?;
+            clone_items(&items, &mut CloneMemo::new())
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Interpret an object as an index or similar integer argument, saturating for huge values.
+pub fn int_arg(obj: &MObjectRef, what: &str) -> Result<i64, MObjectRef> {
+    match MIntImpl::value_of(obj) {
+        Some(IntValue::Small(i)) => Ok(i),
+        Some(IntValue::Big(b)) => Ok(if b.sign() == num_bigint::Sign::Minus { i64::MIN } else { i64::MAX }),
+        None => Err(MStringImpl::from(format!(
+            "{} must be an int, not `{}`",
+            what,
+            obj.objtype().read().unwrap().name()
+        ))
+        .wrap()),
+    }
+}
+
+/// Resolve an index into a sequence of length `len`, where negative indices count from the end.
+pub fn resolve_index(len: usize, index: &MObjectRef) -> Result<usize, MObjectRef> {
+    let i = int_arg(index, "sequence index")?;
+    let resolved = if i < 0 { i.saturating_add(len as i64) } else { i };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(MStringImpl::from(format!("index {} out of range for length {}", i, len)).wrap());
+    }
+    Ok(resolved as usize)
+}
+
+/**
+The indices selected by `[start:stop:step]` from a sequence of length `len`, following Python:
+negative bounds count from the end, out of range bounds are clamped, and a negative step
+walks backwards (with the bounds defaulting to the end and the beginning respectively).
+ */
+pub fn slice_indices(
+    len: usize,
+    start: Option<MObjectRef>,
+    stop: Option<MObjectRef>,
+    step: Option<MObjectRef>,
+) -> Result<Vec<usize>, MObjectRef> {
+    let len = len as i64;
+    let step = match step {
+        Some(step) => int_arg(&step, "slice step")?,
+        None => 1,
+    };
+    if step == 0 {
+        return Err(MStringImpl::from("slice step cannot be zero").wrap());
+    }
+    let bound = |value: Option<MObjectRef>, default: i64| -> Result<i64, MObjectRef> {
+        match value {
+            None => Ok(default),
+            Some(value) => {
+                let mut i = int_arg(&value, "slice bound")?;
+                if i < 0 {
+                    i = i.saturating_add(len);
+                }
+                Ok(if step > 0 { i.clamp(0, len) } else { i.clamp(-1, len - 1) })
+            }
+        }
+    };
+    let (start, stop) = if step > 0 {
+        (bound(start, 0)?, bound(stop, len)?)
+    } else {
+        (bound(start, len - 1)?, bound(stop, -1)?)
+    };
+    let mut indices = Vec::new();
+    let mut i = start;
+    while (step > 0 && i < stop) || (step < 0 && i > stop) {
+        indices.push(i as usize);
+        i = match i.checked_add(step) {
+            Some(i) => i,
+            None => break,
+        };
+    }
+    Ok(indices)
+}
+
+/// Elementwise equality of two sequences.
+pub fn sequence_equals(lhs: &[MObjectRef], rhs: &[MObjectRef]) -> Result<bool, MObjectRef> {
+    if lhs.len() != rhs.len() {
+        return Ok(false);
+    }
+    for (a, b) in lhs.iter().zip(rhs) {
+        if !a.equals(b)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+pub fn sequence_contains(items: &[MObjectRef], item: &MObjectRef) -> Result<bool, MObjectRef> {
+    Ok(position(items, item)?.is_some())
+}
+
+/// The index of the first element equal to `item`.
+pub fn position(items: &[MObjectRef], item: &MObjectRef) -> Result<Option<usize>, MObjectRef> {
+    for (i, element) in items.iter().enumerate() {
+        if element.equals(item)? {
+            return Ok(Some(i));
+        }
+    }
+    Ok(None)
+}
+
+/// The number of elements equal to `item`.
+pub fn count(items: &[MObjectRef], item: &MObjectRef) -> Result<usize, MObjectRef> {
+    let mut n = 0;
+    for element in items {
+        if element.equals(item)? {
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+/// The elements of a sequence repeated `times` times, for `seq * times`.
+pub fn repeat(
+    items: &[MObjectRef],
+    times: &MObjectRef,
+    lhs: &(impl MObject + ?Sized),
+    op: BinaryOperator,
+) -> Result<Vec<MObjectRef>, MObjectRef> {
+    if MIntImpl::value_of(times).is_none() {
+        return Err(binop_unsupported(lhs, times, op));
+    }
+    let times = int_arg(times, "repetition count")?.max(0) as usize;
+    Ok(items.iter().cloned().cycle().take(items.len().saturating_mul(times)).collect())
+}
+
+/// The debug representations of `items`, separated by commas. Nesting deeper than `MAX_EXTSTR_DEPTH` is elided.
+pub fn join_repr(items: &[MObjectRef], depth: usize) -> Result<String, MObjectRef> {
+    if depth > MAX_EXTSTR_DEPTH {
+        return Ok("...".to_owned());
+    }
+    let reprs = items
+        .iter()
+        .map(|item| item.to_ext_string(depth + 1, true))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(reprs.join(", "))
+}
+
+/// The builtin conversion to `list`, from anything that can be iterated over (see `MObject::unpack`).
+fn list_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    Ok(Some(MListImpl::new(value.unpack()?).wrap()))
+}
+
+pub(super) fn create_list_type() -> MTypeRef {
+    MTypeImpl::new("list", None, vec![BUILTINS.get_type("obj")]).with_from(list_from).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(value: i64) -> MObjectRef {
+        MIntImpl::from(value).wrap()
+    }
+    fn indices(len: usize, start: Option<i64>, stop: Option<i64>, step: Option<i64>) -> Vec<usize> {
+        slice_indices(len, start.map(int), stop.map(int), step.map(int)).ok().unwrap()
+    }
+
+    #[test]
+    fn slicing() {
+        assert_eq!(indices(5, Some(1), Some(3), None), vec![1, 2]);
+        assert_eq!(indices(5, Some(-2), None, None), vec![3, 4]);
+        assert_eq!(indices(5, None, None, Some(-2)), vec![4, 2, 0]);
+        assert_eq!(indices(5, Some(10), Some(-10), Some(-1)), vec![4, 3, 2, 1, 0]);
+        assert_eq!(indices(5, Some(3), Some(1), None), Vec::<usize>::new());
+        assert!(slice_indices(5, None, None, Some(int(0))).is_err());
+    }
+
+    #[test]
+    fn methods() {
+        let list: MObjectRef = MListImpl::new(vec![int(1), int(2)]).wrap();
+        let call = |name: &str, args: Vec<MObjectRef>| {
+            let method = list.dot(name).ok().unwrap().read().unwrap().get().ok().unwrap().unwrap();
+            method.call(args, Default::default())
+        };
+        call("append", vec![int(3)]).ok().unwrap();
+        call("extend", vec![list.clone()]).ok().unwrap();
+        assert_eq!(list.to_ext_string(0, false).ok(), Some("[1, 2, 3, 1, 2, 3]".to_owned()));
+        let popped = call("pop", vec![int(-4)]).ok().unwrap();
+        assert_eq!(popped.to_ext_string(0, false).ok(), Some("3".to_owned()));
+        assert!(list.contains(&int(2)).ok().unwrap());
+        assert_eq!(list.len().ok(), Some(5));
+        assert!(call("append", vec![]).is_err());
+    }
+
+    #[test]
+    fn index_field() {
+        let list: MObjectRef = MListImpl::new(vec![int(1), int(2)]).wrap();
+        let field = list.index(int(-1)).ok().unwrap();
+        field.write().unwrap().set(Some(int(5))).ok().unwrap();
+        assert!(list.equals(&(MListImpl::new(vec![int(1), int(5)]).wrap() as MObjectRef)).ok().unwrap());
+        assert!(list.index(int(2)).is_err());
+    }
+
+    #[test]
+    fn deep_clone_cycles() {
+        let inner: MObjectRef = MListImpl::new(vec![int(1)]).wrap();
+        let list = MListImpl::new(vec![inner.clone(), inner.clone()]);
+        let items = list.items.clone();
+        let list: MObjectRef = list.wrap();
+        // the list contains itself
+        items.write().unwrap().push(list.clone());
+
+        let copy = deep_clone(&list, &mut CloneMemo::new()).ok().unwrap();
+        let copied = MListImpl::items_of(&copy).unwrap();
+        let is = |a: &MObjectRef, b: &MObjectRef| same_object(&*a.read().unwrap(), b);
+        assert!(!is(&copy, &list) && !is(&copied[0], &inner));
+        // shared and cyclic references are preserved within the copy
+        assert!(is(&copied[0], &copied[1]));
+        assert!(is(&copied[2], &copy));
+        assert_eq!(copied[0].to_ext_string(0, false).ok(), Some("[1]".to_owned()));
+    }
+}
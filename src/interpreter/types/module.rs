@@ -0,0 +1,298 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::{
+    compiler::compile,
+    log::{self, LogLevel, Logger},
+    scopes::{FieldRef, StaticField, VarScope, VarScopeRefType},
+    stackmachine::{Invocation, StackMachine, Statement},
+};
+
+use super::{
+    builtin::{declare_builtins, BUILTINS},
+    dict::MDictImpl,
+    list::MListImpl,
+    object::{CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+/**
+An imported script. Its fields are the variables it exported while being imported.
+Calling the module calls its exported `$call` if there is one; otherwise the script runs again,
+this time invoked as a run, with the arguments available as `args` and `kwargs`.
+ */
+pub struct MModuleImpl {
+    mobject: MObjectImpl,
+    name: String,
+    instructions: Arc<Vec<Statement>>,
+}
+pub type MModuleImplRef = Arc<RwLock<MModuleImpl>>;
+impl MObject for MModuleImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("module")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(format!("<module {}>", self.name)).wrap())
+    }
+    fn call(&self, args: Vec<MObjectRef>, kwargs: HashMap<String, MObjectRef>) -> MFuncResult {
+        let call = match self.get_field("$call") {
+            Some(field) => field.read().unwrap().get()?,
+            None => None,
+        };
+        if let Some(call) = call {
+            return call.call(args, kwargs);
+        }
+        let kwargs_dict = MDictImpl::new();
+        for (name, value) in kwargs {
+            kwargs_dict.insert(MStringImpl::from(name).wrap(), value)?;
+        }
+        let scope = Self::script_scope();
+        let arguments = [("args", MListImpl::new(args).wrap() as MObjectRef), ("kwargs", kwargs_dict.wrap())];
+        for (name, value) in arguments {
+            let field = Arc::new(RwLock::new(StaticField::new(Some(name.to_owned()), None, Some(value), false)));
+            scope
+                .write()
+                .unwrap()
+                .declare(name, VarScopeRefType::LocalValue(field))
+                .map_err(|e| MStringImpl::from(e).wrap() as MObjectRef)?;
+        }
+        StackMachine::exec_invoked(&self.instructions, scope, log::default_logger(), Invocation::Run, None)
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl MModuleImpl {
+    /// Import a compiled script: run it as an import in a fresh global scope, collecting what it exports.
+    pub fn import(name: &str, instructions: Vec<Statement>) -> Result<MModuleImplRef, MObjectRef> {
+        Self::import_with_logger(name, instructions, log::default_logger())
+    }
+    pub fn import_with_logger(
+        name: &str,
+        instructions: Vec<Statement>,
+        logger: Logger,
+    ) -> Result<MModuleImplRef, MObjectRef> {
+        let module = MModuleImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("module")),
+            name: name.to_owned(),
+            instructions: Arc::new(instructions),
+        };
+        logger.log(LogLevel::Debug, || format!("importing module `{}`", name));
+        let scope = Self::script_scope();
+        StackMachine::exec_invoked(&module.instructions, scope, logger.clone(), Invocation::Import, Some(&module))?;
+        logger.log(LogLevel::Debug, || format!("imported module `{}`", name));
+        Ok(module.wrap())
+    }
+    /**
+    Import the script at `path`. As in the shell, a path without a file extension also finds
+    the `.m` script of that name.
+     */
+    pub fn import_file(name: &str, path: &str) -> Result<MModuleImplRef, MObjectRef> {
+        Self::import_file_with_logger(name, path, log::default_logger())
+    }
+    pub fn import_file_with_logger(name: &str, path: &str, logger: Logger) -> Result<MModuleImplRef, MObjectRef> {
+        let error = |msg: String| MStringImpl::from(format!("cannot import {}: {}", path, msg)).wrap() as MObjectRef;
+        let mut file = PathBuf::from(path);
+        if !file.exists() && file.extension().is_none() {
+            file.set_extension("m");
+        }
+        let source = fs::read_to_string(&file).map_err(|e| error(e.to_string()))?;
+        let instructions = compile(&source).map_err(|errors| {
+            let first = &errors[0];
+            error(format!("line {}: {}", first.line, first.msg))
+        })?;
+        Self::import_with_logger(name, instructions, logger)
+    }
+    pub fn wrap(self) -> MModuleImplRef {
+        Arc::new(RwLock::new(self))
+    }
+    /// The variables the script exported, sorted by name.
+    pub fn exports(&self) -> Vec<(String, FieldRef)> {
+        self.mobject.fields()
+    }
+    /// The global scope a script runs in, with nothing but the builtins declared.
+    fn script_scope() -> Arc<RwLock<VarScope>> {
+        let mut scope = VarScope::new_global(false);
+        declare_builtins(&mut scope);
+        Arc::new(RwLock::new(scope))
+    }
+}
+
+pub(super) fn create_module_type() -> MTypeRef {
+    MTypeImpl::new("module", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{
+        log::MemorySink,
+        types::{function::MNativeFunctionImpl, none::MNone},
+    };
+
+    fn import(source: &str) -> MObjectRef {
+        MModuleImpl::import("test", compile(source).ok().unwrap()).ok().unwrap()
+    }
+
+    fn field(module: &MObjectRef, name: &str) -> Option<String> {
+        let value = module.get_field(name)?.read().unwrap().get().ok()??;
+        value.to_ext_string(0, false).ok()
+    }
+
+    #[test]
+    fn import_collects_exports() {
+        let source = "export answer = 42\n\
+            hidden = 1\n\
+            export {\n  export mode = 'import'\n}\n\
+            run {\n  export mode = 'run'\n}\n";
+        let module = import(source);
+        assert_eq!(field(&module, "answer"), Some("42".to_owned()));
+        assert_eq!(field(&module, "mode"), Some("import".to_owned()));
+        assert_eq!(field(&module, "hidden"), None);
+    }
+
+    #[test]
+    fn import_is_logged() {
+        let sink = Arc::new(MemorySink::default());
+        let instructions = compile("export answer = 42\n").ok().unwrap();
+        MModuleImpl::import_with_logger("lib", instructions, Logger::new(LogLevel::Debug, sink.clone())).ok().unwrap();
+        let lines = sink.lines();
+        assert_eq!(lines.first().map(String::as_str), Some("debug: importing module `lib`"));
+        assert!(lines.contains(&"debug: exported `answer`".to_owned()));
+        assert_eq!(lines.last().map(String::as_str), Some("debug: imported module `lib`"));
+    }
+
+    #[test]
+    fn calling_runs_the_script() {
+        // importing skips the `run` block, where `args` wouldn't be declared
+        let module = import("export {\n  imported = 1\n}\nrun {\n  args[0](kwargs['greeting'])\n}\n");
+        let seen = Arc::new(RwLock::new(Vec::new()));
+        let record = {
+            let seen = seen.clone();
+            MNativeFunctionImpl::new("record", move |args, _| {
+                seen.write().unwrap().push(args[0].to_ext_string(0, false)?);
+                Ok(MNone::refer() as MObjectRef)
+            })
+        };
+        let kwargs = HashMap::from([("greeting".to_owned(), MStringImpl::from("hi").wrap() as MObjectRef)]);
+        module.call(vec![record.wrap()], kwargs).ok().unwrap();
+        assert_eq!(*seen.read().unwrap(), vec!["hi".to_owned()]);
+    }
+
+    #[test]
+    fn calling_binds_script_arguments() {
+        // importing passes no arguments, which leaves `name` unassigned instead of failing
+        let source = "arg name\narg greeting = 'hello'\narg record = none\nrun {\n  record(greeting + ' ' + name)\n}\n";
+        let module = import(source);
+        let seen = Arc::new(RwLock::new(Vec::new()));
+        let record = {
+            let seen = seen.clone();
+            MNativeFunctionImpl::new("record", move |args, _| {
+                seen.write().unwrap().push(args[0].to_ext_string(0, false)?);
+                Ok(MNone::refer() as MObjectRef)
+            })
+            .wrap() as MObjectRef
+        };
+        let string = |value: &str| MStringImpl::from(value).wrap() as MObjectRef;
+        let kwargs = HashMap::from([("record".to_owned(), record.clone())]);
+        module.call(vec![string("you")], kwargs).ok().unwrap();
+        let kwargs = HashMap::from([
+            ("record".to_owned(), record),
+            ("name".to_owned(), string("you")),
+            ("greeting".to_owned(), string("hi")),
+        ]);
+        module.call(vec![], kwargs).ok().unwrap();
+        assert_eq!(*seen.read().unwrap(), vec!["hello you".to_owned(), "hi you".to_owned()]);
+        let error = module.call(vec![], HashMap::new()).err().unwrap();
+        assert_eq!(error.to_ext_string(0, false).ok(), Some("missing argument `name`".to_owned()));
+    }
+
+    #[test]
+    fn calling_uses_exported_call() {
+        let module = import("export {\n  export $call = len\n}\nrun {\n  'not called'\n}\n");
+        let result = module.call(vec![MStringImpl::from("four").wrap()], HashMap::new()).ok().unwrap();
+        assert_eq!(result.to_ext_string(0, false).ok(), Some("4".to_owned()));
+    }
+
+    #[test]
+    fn importing_from_a_script() {
+        let dir = std::env::temp_dir().join(format!("msh-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let lib = "func main(xs) -> none {\n  xs.append('called')\n}\n\
+            export answer = 42\n\
+            export {\n  export $call = main\n}\n";
+        fs::write(dir.join("lib.m"), lib).unwrap();
+        // the extension can be left out, and an imported module can be imported from again
+        let source = format!(
+            "calls = []\n\
+            import lib = {dir}/lib()\n\
+            lib(calls)\n\
+            import answer, n = answer from {dir}/lib.m()\n\
+            import * from lib()\n\
+            $call(calls)\n",
+            dir = dir.display()
+        );
+        let scope = MModuleImpl::script_scope();
+        let result = StackMachine::exec(&compile(&source).ok().unwrap(), scope.clone());
+        let missing = compile(&format!("import {}/missing()\n", dir.display())).ok().unwrap();
+        let missing = StackMachine::exec(&missing, scope.clone());
+        fs::remove_dir_all(&dir).unwrap();
+        result.ok().unwrap();
+        let load = |id: &str| {
+            let value = StackMachine::exec(&[Statement::LoadScope(id.to_owned())], scope.clone()).ok().unwrap();
+            value.to_ext_string(0, false).ok().unwrap()
+        };
+        assert_eq!(load("lib"), "<module lib>");
+        assert_eq!(load("calls"), "['called', 'called']");
+        assert_eq!((load("answer"), load("n")), ("42".to_owned(), "42".to_owned()));
+        let message = missing.err().unwrap().to_ext_string(0, false).ok().unwrap();
+        assert!(message.starts_with("cannot import "), "{}", message);
+        let wrong_type = StackMachine::exec(&compile("import x = 'lib'()\n").ok().unwrap(), scope.clone());
+        assert_eq!(
+            wrong_type.err().unwrap().to_ext_string(0, false).ok(),
+            Some("cannot import `str`: only paths and modules can be imported".to_owned())
+        );
+    }
+
+    #[test]
+    fn importing_from_a_script_is_logged() {
+        let dir = std::env::temp_dir().join(format!("msh-import-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lib.m"), "export answer = 42\n").unwrap();
+        // the script raises the level itself, and the import happens under its logger
+        let source = format!("#!log debug\nimport * from {}/lib()\n", dir.display());
+        let sink = Arc::new(MemorySink::default());
+        let logger = Logger::new(LogLevel::Warn, sink.clone());
+        let instructions = compile(&source).ok().unwrap();
+        let result = StackMachine::exec_with_logger(&instructions, MModuleImpl::script_scope(), logger);
+        fs::remove_dir_all(&dir).unwrap();
+        result.ok().unwrap();
+        assert_eq!(
+            sink.lines(),
+            vec![
+                "debug: importing module `lib`",
+                "debug: declared local `answer` at depth 0",
+                "debug: exported `answer`",
+                "debug: imported module `lib`",
+                "debug: imported `answer`",
+            ]
+        );
+    }
+}
@@ -1,16 +1,40 @@
 use lazy_static::lazy_static;
 use std::{
+    any::Any,
     sync::{Arc, RwLock},
 };
 
-use super::{object::{MObject, MObjectImpl}, MTypeImpl, builtin::BUILTINS, MTypeRef, string::MStringImpl, MFuncResult};
+use crate::interpreter::scopes::FieldRef;
+
+use super::{object::{CloneMemo, MObject, MObjectRef}, MTypeImpl, builtin::BUILTINS, MTypeRef, string::MStringImpl, MFuncResult};
 
 lazy_static! {
     // There is only one `none` value, its reference is shared globally.
-    static ref MSH_NONE: MNoneRef = Arc::new(RwLock::new(MNone::singleton()));
+    static ref MSH_NONE: MNoneRef = Arc::new(RwLock::new(MNone { kind: NoneKind::None }));
+    // the same goes for the sentinels standing in for missing variables.
+    static ref MSH_UNDECLARED: MNoneRef = Arc::new(RwLock::new(MNone { kind: NoneKind::Undeclared }));
+    static ref MSH_UNASSIGNED: MNoneRef = Arc::new(RwLock::new(MNone { kind: NoneKind::Unassigned }));
+}
+
+/// Where a `none` value comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoneKind {
+    /// the actual `none` value
+    None,
+    /// the value of a variable that was never declared
+    Undeclared,
+    /// the value of a variable that was declared, but never assigned
+    Unassigned,
 }
 
-struct MNone;
+/**
+The `none` value. Reading a variable that doesn't exist or has no value yet results in a
+*sentinel* instead: it behaves just like `none` (and is equal to it), but the debug representation
+and the builtin `defined` can tell it apart.
+ */
+pub struct MNone {
+    kind: NoneKind,
+}
 pub type MNoneRef = Arc<RwLock<MNone>>;
 impl MObject for MNone {
     fn objtype(&self) -> MTypeRef {
@@ -19,18 +43,57 @@ impl MObject for MNone {
     fn str_nice(&self) -> MFuncResult {
         Ok(MStringImpl::from("none").wrap())
     }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.to_ext_string(0, true)?).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(match (self.kind, use_debug) {
+            (NoneKind::Undeclared, true) => "none (undeclared)",
+            (NoneKind::Unassigned, true) => "none (unassigned)",
+            _ => "none",
+        }
+        .to_owned())
+    }
+    fn get_field(&self, _name: &str) -> Option<FieldRef> {
+        None
+    }
+    fn insert_field(&self, _field: FieldRef) {}
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(other.read().unwrap().as_any().is::<MNone>())
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 impl MNone {
-    fn singleton() -> MNone {
-        MObjectImpl::new(BUILTINS.get_type("none"))
-    }
     pub fn refer() -> MNoneRef {
         MSH_NONE.clone()
     }
+    /// The sentinel for reading a variable that was never declared.
+    pub fn undeclared() -> MNoneRef {
+        MSH_UNDECLARED.clone()
+    }
+    /// The sentinel for reading a variable that was declared, but never assigned.
+    pub fn unassigned() -> MNoneRef {
+        MSH_UNASSIGNED.clone()
+    }
+    pub fn kind(&self) -> NoneKind {
+        self.kind
+    }
+    /// Whether an object is one of the sentinels for a missing variable, as checked by `defined`.
+    pub fn is_missing(obj: &MObjectRef) -> bool {
+        let obj = obj.read().unwrap();
+        matches!(
+            obj.as_any().downcast_ref::<MNone>().map(|o| o.kind),
+            Some(NoneKind::Undeclared | NoneKind::Unassigned)
+        )
+    }
 }
 
 pub(super) fn create_none_type() -> MTypeRef {
-    let _type = MTypeImpl::new("none", None, vec![BUILTINS.get_type("obj")]).wrap();
-    _type
-}
\ No newline at end of file
+    MTypeImpl::new("none", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
@@ -1,14 +1,18 @@
-use std::{collections::HashMap, sync::{Arc, RwLock}};
+use std::{any::Any, collections::HashMap, fmt, sync::{Arc, RwLock}};
 
-use crate::{interpreter::scopes::{FieldRef, DynamicField}};
+use crate::{interpreter::scopes::FieldRef};
 
-use super::{MTypeRef, MFuncResult, string::MString, MTypeImpl};
+use super::{error::MErrorImpl, iterator::MStopIteration, none::MNone, MTypeRef, MFuncResult, string::MStringImpl, MTypeImpl, BinaryOperator, UnaryOperator};
 
 use delegate::delegate;
 
-static MAX_EXTSTR_DEPTH: usize = 8;
+pub static MAX_EXTSTR_DEPTH: usize = 8;
 
-pub trait MObject {
+/// The copies made so far during one deep clone, keyed by the address of the state they copy.
+pub type CloneMemo = HashMap<usize, MObjectRef>;
+
+#[allow(clippy::len_without_is_empty)]
+pub trait MObject: Send + Sync {
     ///Return the object's type in the Mscript type system. This type is itself an Mobject of type `type`.
     fn objtype(&self) -> MTypeRef;
     /**
@@ -24,28 +28,162 @@ pub trait MObject {
     which CAN be turned into a string. So in the end I should probably just go down the rabbit hole when extracting the value.
      */
     fn str_debug(&self) -> MFuncResult {
-        Ok(MString::from(format!(
+        Ok(MStringImpl::from(format!(
             "object {:p} of type `{}`",
             &self,
             self.objtype().read().unwrap().name())).wrap())
-        
     }
     /// convenience wrapper for `str_nice` to be used in Rust code.
     /// If the object isn't of type `string`, this method will recurse `MAX_EXTSTR_DEPTH` times
     /// before throwing an error
     fn to_ext_string(&self, depth: usize, use_debug: bool) -> Result<String, MObjectRef> {
         if depth > MAX_EXTSTR_DEPTH {
-            // Err(MshBaseError::new("Error encoding object as `$str`: maximum recursion depth exceeded").wrap())?
+            return Err(MStringImpl::from("Error encoding object as `$str`: maximum recursion depth exceeded").wrap());
         }
         if use_debug {
-            self.str_debug()?.to_string_debug(depth+1)
+            self.str_debug()?.to_ext_string(depth + 1, true)
         } else {
-            self.str_nice()?.to_string_nice(depth+1)
+            self.str_nice()?.to_ext_string(depth + 1, false)
         }
     }
     fn get_field(&self, name: &str) -> Option<FieldRef>;
     fn insert_field(&self, field: FieldRef);
 
+    /**
+    Get the field behind `obj.name`. This is lazy: the field can be read, assigned or deleted later,
+    which is what allows assignments like `obj.name += 1` to only evaluate `obj` once.
+    By default only the fields stored on the object itself are considered.
+     */
+    fn dot(&self, name: &str) -> Result<FieldRef, MObjectRef> {
+        self.get_field(name).ok_or_else(|| {
+            MStringImpl::from(format!(
+                "member `{}` not found on type `{}`",
+                name,
+                self.objtype().read().unwrap().name()
+            ))
+            .wrap() as MObjectRef
+        })
+    }
+    /// Get the field behind `obj[index]`, with the same lazy semantics as `dot`. Not supported by default.
+    fn index(&self, _index: MObjectRef) -> Result<FieldRef, MObjectRef> {
+        Err(MStringImpl::from(format!(
+            "indexing not supported for type `{}`",
+            self.objtype().read().unwrap().name()
+        ))
+        .wrap())
+    }
+    /// Apply a binary operator, with `self` as the left operand. Not supported by default.
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        Err(binop_unsupported(self, &other, op))
+    }
+    /**
+    Apply a unary operator. Note that `Inc` and `Dec` don't modify the object: they return the
+    incremented value, which the interpreter then writes back to the field it was read from.
+    Not supported by default.
+     */
+    fn unop(&self, op: UnaryOperator) -> MFuncResult {
+        Err(unop_unsupported(self, op))
+    }
+    /// Compare with another object for `==`. By default objects are only equal to themselves.
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(same_object(self, other))
+    }
+    /// Check whether `item in self`. Not supported by default.
+    fn contains(&self, _item: &MObjectRef) -> Result<bool, MObjectRef> {
+        Err(unsupported(self, "membership tests are"))
+    }
+    /// The number of elements, as returned by `len(obj)`. Not supported by default.
+    fn len(&self) -> Result<usize, MObjectRef> {
+        Err(unsupported(self, "`len` is"))
+    }
+    /**
+    Get `obj[start:stop:step]`, where omitted parts are `None`. Unlike `index` this doesn't produce
+    a field: slices are always copies. Not supported by default.
+     */
+    fn slice(
+        &self,
+        _start: Option<MObjectRef>,
+        _stop: Option<MObjectRef>,
+        _step: Option<MObjectRef>,
+    ) -> MFuncResult {
+        Err(unsupported(self, "slicing is"))
+    }
+    /**
+    The elements of the object, as spread into a literal by `[*obj]`.
+    By default the object is iterated (see `iter`); types with their elements at hand can skip that.
+     */
+    fn unpack(&self) -> Result<Vec<MObjectRef>, MObjectRef> {
+        let iterator = self.iter()?;
+        let mut items = Vec::new();
+        while let Some(item) = iterator.next()? {
+            items.push(item);
+        }
+        Ok(items)
+    }
+    /**
+    Get an iterator over the object, as used by `for` loops. Its elements are then obtained with `next`.
+    By default this calls the object's `$iter` method, so user types can take part in the protocol.
+     */
+    fn iter(&self) -> MFuncResult {
+        call_special(self, "$iter").unwrap_or_else(|| Err(unsupported(self, "iteration is")))
+    }
+    /**
+    Advance an iterator, returning `None` once it's exhausted. By default this calls the object's `$next` method,
+    which signals the end of iteration by returning the builtin `stop_iteration` value.
+     */
+    fn next(&self) -> Result<Option<MObjectRef>, MObjectRef> {
+        match call_special(self, "$next") {
+            Some(value) => {
+                let value = value?;
+                Ok(if MStopIteration::is(&value) { None } else { Some(value) })
+            }
+            None => Err(unsupported(self, "`next` is")),
+        }
+    }
+    /**
+    Make a deep copy of the object, as done by the by-value assignment `a <= b`. Immutable objects return
+    `None` to be shared instead. Containers register their copy in `memo` before copying their elements
+    (see `deep_clone`), so cyclic structures stay cyclic. By default this calls the object's `$clone` method.
+     */
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        match call_special(self, "$clone") {
+            Some(clone) => Ok(Some(clone?)),
+            None => Err(unsupported(self, "cloning is")),
+        }
+    }
+    /**
+    Convert the object to another type for `obj as target`, or return `None` to leave the conversion to the
+    target type (see `cast`). By default this calls the object's `$as` method, or else its `$cast` method,
+    with the target type; returning `none` from it declines the conversion as well.
+     */
+    fn cast(&self, target: &MTypeRef) -> Result<Option<MObjectRef>, MObjectRef> {
+        let target: MObjectRef = target.clone();
+        let result =
+            call_method(self, "$as", vec![target.clone()]).or_else(|| call_method(self, "$cast", vec![target]));
+        match result {
+            Some(value) => Ok(Some(value?).filter(|value| !value.read().unwrap().as_any().is::<MNone>())),
+            None => Ok(None),
+        }
+    }
+    /**
+    The documentation of the object itself, as shown by `help`. Functions carry their docstring around;
+    for other values the documentation lives on the variable or field holding them.
+     */
+    fn docstring(&self) -> Option<String> {
+        None
+    }
+    /// Call the object with positional and keyword arguments. Not supported by default.
+    fn call(&self, _args: Vec<MObjectRef>, _kwargs: HashMap<String, MObjectRef>) -> MFuncResult {
+        Err(unsupported(self, "calling is"))
+    }
+
+    /**
+    Access the concrete Rust type behind the object. Builtin types use this to look into
+    each other's values (eg. for `1 + 2.5`); it isn't part of the delegated interface,
+    so it has to be called through the lock: `obj.read().unwrap().as_any()`.
+     */
+    fn as_any(&self) -> &dyn Any;
+
     // TODO: add the functions that should be callable from Rust code on any object
 }
 // TODO: make this a macro
@@ -59,16 +197,141 @@ impl MObject for MObjectRef {
             fn to_ext_string(&self, depth: usize, use_debug: bool) -> Result<String, MObjectRef>;
             fn get_field(&self, name: &str) -> Option<FieldRef>;
             fn insert_field(&self, field: FieldRef);
+            fn dot(&self, name: &str) -> Result<FieldRef, MObjectRef>;
+            fn index(&self, index: MObjectRef) -> Result<FieldRef, MObjectRef>;
+            fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult;
+            fn unop(&self, op: UnaryOperator) -> MFuncResult;
+            fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef>;
+            fn contains(&self, item: &MObjectRef) -> Result<bool, MObjectRef>;
+            fn len(&self) -> Result<usize, MObjectRef>;
+            fn slice(&self, start: Option<MObjectRef>, stop: Option<MObjectRef>, step: Option<MObjectRef>) -> MFuncResult;
+            fn unpack(&self) -> Result<Vec<MObjectRef>, MObjectRef>;
+            fn iter(&self) -> MFuncResult;
+            fn next(&self) -> Result<Option<MObjectRef>, MObjectRef>;
+            fn call(&self, args: Vec<MObjectRef>, kwargs: HashMap<String, MObjectRef>) -> MFuncResult;
+            fn clone_value(&self, memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef>;
+            fn cast(&self, target: &MTypeRef) -> Result<Option<MObjectRef>, MObjectRef>;
+            fn docstring(&self) -> Option<String>;
+        }
+    }
+    /// This is the reference itself; the concrete type is behind the lock.
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+/// Objects are debug-formatted by their debug string, eg. for tracing the instructions that load them.
+impl fmt::Debug for dyn MObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_ext_string(0, true) {
+            Ok(s) => f.write_str(&s),
+            Err(_) => f.write_str("<object without debug string>"),
         }
     }
 }
 
+/// The error thrown by any type that doesn't implement an operator for the given operand.
+pub fn binop_unsupported(lhs: &(impl MObject + ?Sized), rhs: &MObjectRef, op: BinaryOperator) -> MObjectRef {
+    MStringImpl::from(format!(
+        "operator `{:?}` not supported between types `{}`,`{}`",
+        op,
+        lhs.objtype().read().unwrap().name(),
+        rhs.objtype().read().unwrap().name()
+    ))
+    .wrap()
+}
+
+/// The error thrown by any type that doesn't implement a unary operator.
+pub fn unop_unsupported(obj: &(impl MObject + ?Sized), op: UnaryOperator) -> MObjectRef {
+    MStringImpl::from(format!(
+        "operator `{:?}` not supported for type `{}`",
+        op,
+        obj.objtype().read().unwrap().name()
+    ))
+    .wrap()
+}
+
+/// The error thrown by any type that doesn't implement some functionality, eg. `unsupported(obj, "slicing is")`.
+pub fn unsupported(obj: &(impl MObject + ?Sized), what: &str) -> MObjectRef {
+    MStringImpl::from(format!(
+        "{} not supported for type `{}`",
+        what,
+        obj.objtype().read().unwrap().name()
+    ))
+    .wrap()
+}
+
+/**
+Call a method of an object without arguments, if the object has it.
+This is how builtin operations reach the `$`-methods of user types, eg. `$iter`.
+ */
+pub fn call_special(obj: &(impl MObject + ?Sized), name: &str) -> Option<MFuncResult> {
+    call_method(obj, name, vec![])
+}
+
+/// Like `call_special`, but with positional arguments, eg. the target type for `$as`.
+pub fn call_method(obj: &(impl MObject + ?Sized), name: &str, args: Vec<MObjectRef>) -> Option<MFuncResult> {
+    let field = obj.dot(name).ok()?;
+    let method = field.read().unwrap().get();
+    match method {
+        Ok(Some(method)) => Some(method.call(args, HashMap::new())),
+        Ok(None) => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
+/// Deep-copy a value for by-value assignment, sharing it if it's immutable. See `MObject::clone_value`.
+pub fn deep_clone(obj: &MObjectRef, memo: &mut CloneMemo) -> MFuncResult {
+    Ok(obj.clone_value(memo)?.unwrap_or_else(|| obj.clone()))
+}
+
+/**
+Convert a value for `value as target`. A value of the target type is returned as it is; otherwise the value
+gets the first chance to convert itself (see `MObject::cast`), then the target type (see `MType::from_value`).
+If neither knows how, this throws a `TypeError`.
+ */
+pub fn cast(value: &MObjectRef, target: &MTypeRef) -> MFuncResult {
+    let (from, to) = (value.objtype().read().unwrap().name(), target.read().unwrap().name());
+    if from == to {
+        return Ok(value.clone());
+    }
+    if let Some(result) = value.cast(target)? {
+        return Ok(result);
+    }
+    let converted = target.read().unwrap().from_value(value)?;
+    converted.ok_or_else(|| {
+        MErrorImpl::type_error(format!("cannot cast a value of type `{}` to `{}`", from, to)).wrap() as MObjectRef
+    })
+}
+
+/// Check whether `obj` and `other` are the very same object.
+pub fn same_object(obj: &(impl MObject + ?Sized), other: &MObjectRef) -> bool {
+    let other = other.read().unwrap();
+    std::ptr::eq(
+        obj.as_any() as *const dyn Any as *const u8,
+        other.as_any() as *const dyn Any as *const u8,
+    )
+}
+
 pub struct MObjectImpl {
     objtype: MTypeRef,
-    inst_dict: HashMap<String, DynamicField>,
+    inst_dict: RwLock<HashMap<String, FieldRef>>,
 }
 pub type MObjectImplRef = Arc<RwLock<MObjectImpl>>;
-impl MObject for MObjectImpl {}
+impl MObject for MObjectImpl {
+    fn objtype(&self) -> MTypeRef {
+        self.objtype.clone()
+    }
+    fn get_field(&self, name: &str) -> Option<FieldRef> {
+        self.inst_dict.read().unwrap().get(name).cloned()
+    }
+    fn insert_field(&self, field: FieldRef) {
+        let name = field.read().unwrap().name();
+        self.inst_dict.write().unwrap().insert(name, field);
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 impl From<MObjectImpl> for MObjectRef {
     fn from(o: MObjectImpl) -> Self {
         o.wrap()
@@ -80,13 +343,50 @@ impl From<MObjectImpl> for MObjectImplRef {
     }
 }
 impl MObjectImpl {
+    pub fn new(objtype: MTypeRef) -> Self {
+        MObjectImpl {
+            objtype,
+            inst_dict: RwLock::new(HashMap::new()),
+        }
+    }
     pub fn wrap(self) -> MObjectImplRef {
         Arc::new(RwLock::new(self))
     }
+    /// The fields set on this object itself, sorted by name.
+    pub fn fields(&self) -> Vec<(String, FieldRef)> {
+        let mut fields: Vec<_> = self.inst_dict.read().unwrap().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        fields
+    }
 }
 
 
 pub(super) fn create_object_type() -> MTypeRef {
-    let _type = MTypeImpl::new("obj",None,vec![]).wrap();
-    _type
-}
\ No newline at end of file
+    MTypeImpl::new("obj", None, vec![]).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::types::{builtin::BUILTINS, function::MNativeFunctionImpl, int::MIntImpl, MType};
+
+    #[test]
+    fn cast_protocol() {
+        // a user object that only knows how to become an `int`
+        let obj: MObjectRef = MObjectImpl::new(BUILTINS.get_type("obj")).wrap();
+        let method = MNativeFunctionImpl::new("$as", |args, _kwargs| {
+            let target = args[0].read().unwrap().as_any().downcast_ref::<MTypeImpl>().map(|t| t.name());
+            Ok(match target.as_deref() {
+                Some("int") => MIntImpl::from(42).wrap() as MObjectRef,
+                _ => MNone::refer(),
+            })
+        });
+        obj.insert_field(method.into_field());
+        let converted = cast(&obj, &BUILTINS.get_type("int")).ok().unwrap();
+        assert_eq!(converted.to_ext_string(0, false).ok(), Some("42".to_owned()));
+        // declining with `none` leaves the conversion to the target type, which may not know how either
+        assert!(cast(&obj, &BUILTINS.get_type("str")).is_ok());
+        let error = cast(&obj, &BUILTINS.get_type("float")).err().unwrap();
+        assert_eq!(MErrorImpl::kind_of(&error), Some("TypeError".to_owned()));
+    }
+}
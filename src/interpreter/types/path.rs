@@ -0,0 +1,129 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::scopes::FieldRef;
+
+use super::{
+    builtin::BUILTINS,
+    iterator::MIteratorImpl,
+    object::{CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+/**
+A file system path, as written in a path literal like `./doc/examples`. A path may contain glob
+patterns (eg. a last segment `*.m`), in which case it stands for all the existing paths it matches:
+iterating over it yields each match (in sorted order), while a plain path just yields itself.
+ */
+pub struct MPathImpl {
+    mobject: MObjectImpl,
+    path: String,
+}
+pub type MPathImplRef = Arc<RwLock<MPathImpl>>;
+impl MObject for MPathImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("path")
+    }
+    fn str_nice(&self) -> MFuncResult {
+        Ok(MStringImpl::from(&self.path).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(self.path.clone())
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        let other = other.read().unwrap();
+        Ok(other.as_any().downcast_ref::<MPathImpl>().map(|o| &o.path) == Some(&self.path))
+    }
+    fn iter(&self) -> MFuncResult {
+        let paths = self.expand()?;
+        let items = paths.into_iter().map(|p| MPathImpl::new(p).wrap() as MObjectRef).collect();
+        Ok(MIteratorImpl::from_vec(items).wrap())
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl MPathImpl {
+    pub fn new(path: impl Into<String>) -> Self {
+        MPathImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("path")),
+            path: path.into(),
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    pub fn is_glob(&self) -> bool {
+        self.path.contains(['*', '?', '['])
+    }
+    /// The paths this path stands for: all matches of a glob, or just the path itself.
+    pub fn expand(&self) -> Result<Vec<String>, MObjectRef> {
+        let path = expand_home(&self.path);
+        if !self.is_glob() {
+            return Ok(vec![path]);
+        }
+        let error = |e: String| MStringImpl::from(format!("invalid glob `{}`: {}", self.path, e)).wrap() as MObjectRef;
+        let mut matches = glob::glob(&path)
+            .map_err(|e| error(e.to_string()))?
+            .map(|entry| entry.map(|p| p.to_string_lossy().into_owned()).map_err(|e| error(e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        matches.sort();
+        Ok(matches)
+    }
+}
+
+/// Resolve a leading `~` to the user's home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => home + rest,
+        _ => path.to_owned(),
+    }
+}
+
+/// The builtin conversion to `path`, from a string.
+fn path_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    Ok(MStringImpl::value_of(value).map(|path| MPathImpl::new(path).wrap() as MObjectRef))
+}
+
+pub(super) fn create_path_type() -> MTypeRef {
+    MTypeImpl::new("path", None, vec![BUILTINS.get_type("obj")]).with_from(path_from).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_iteration() {
+        let dir = std::env::temp_dir().join(format!("msh-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.m", "a.m", "c.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let pattern = format!("{}/*.m", dir.display());
+        let path: MObjectRef = MPathImpl::new(pattern).wrap();
+        let matches: Vec<String> = path
+            .unpack()
+            .ok()
+            .unwrap()
+            .iter()
+            .map(|p| p.to_ext_string(0, false).ok().unwrap())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(matches, [format!("{}/a.m", dir.display()), format!("{}/b.m", dir.display())]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::scopes::{FieldRef, StaticField};
+
+use super::{
+    builtin::BUILTINS,
+    function::{check_args, MNativeFunctionImpl},
+    int::MIntImpl,
+    iterator::MIteratorImpl,
+    list::{int_arg, resolve_index},
+    object::{CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+/// An arithmetic sequence of ints as created by `range(stop)` or `range(start, stop, step)`, like in Python.
+pub struct MRangeImpl {
+    mobject: MObjectImpl,
+    start: i64,
+    stop: i64,
+    step: i64,
+}
+pub type MRangeImplRef = Arc<RwLock<MRangeImpl>>;
+impl MObject for MRangeImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("range")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.to_ext_string(0, true)?).wrap())
+    }
+    fn to_ext_string(&self, _depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        Ok(if self.step == 1 {
+            format!("range({}, {})", self.start, self.stop)
+        } else {
+            format!("range({}, {}, {})", self.start, self.stop, self.step)
+        })
+    }
+    fn index(&self, index: MObjectRef) -> Result<FieldRef, MObjectRef> {
+        let i = resolve_index(self.size(), &index)?;
+        let value = MIntImpl::from(self.nth(i)).wrap();
+        Ok(Arc::new(RwLock::new(StaticField::new(None, None, Some(value), true))))
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        let other = other.read().unwrap();
+        Ok(match other.as_any().downcast_ref::<MRangeImpl>() {
+            // ranges are equal if they produce the same sequence
+            Some(other) => {
+                let (len, other_len) = (self.size(), other.size());
+                len == other_len
+                    && (len == 0 || (self.start == other.start && (len == 1 || self.step == other.step)))
+            }
+            None => false,
+        })
+    }
+    fn contains(&self, item: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(match MIntImpl::value_of(item) {
+            Some(_) => {
+                let value = int_arg(item, "range element")? as i128;
+                let (start, stop, step) = (self.start as i128, self.stop as i128, self.step as i128);
+                let in_bounds = if step > 0 {
+                    start <= value && value < stop
+                } else {
+                    stop < value && value <= start
+                };
+                in_bounds && (value - start) % step == 0
+            }
+            None => false,
+        })
+    }
+    fn len(&self) -> Result<usize, MObjectRef> {
+        Ok(self.size())
+    }
+    fn iter(&self) -> MFuncResult {
+        let (mut next, stop, step) = (self.start, self.stop, self.step);
+        Ok(MIteratorImpl::new(move || {
+            if (step > 0 && next >= stop) || (step < 0 && next <= stop) {
+                return Ok(None);
+            }
+            let value = next;
+            // on overflow the range is simply over
+            next = next.checked_add(step).unwrap_or(stop);
+            Ok(Some(MIntImpl::from(value).wrap()))
+        })
+        .wrap())
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl MRangeImpl {
+    pub fn new(start: i64, stop: i64, step: i64) -> Result<Self, MObjectRef> {
+        if step == 0 {
+            return Err(MStringImpl::from("range step cannot be zero").wrap());
+        }
+        Ok(MRangeImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("range")),
+            start,
+            stop,
+            step,
+        })
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    fn size(&self) -> usize {
+        let (start, stop, step) = (self.start as i128, self.stop as i128, self.step as i128);
+        let len = if step > 0 {
+            (stop - start + step - 1) / step
+        } else {
+            (start - stop - step - 1) / -step
+        };
+        len.max(0) as usize
+    }
+    fn nth(&self, i: usize) -> i64 {
+        (self.start as i128 + i as i128 * self.step as i128) as i64
+    }
+}
+
+/// The builtin `range` function: `range(stop)`, `range(start, stop)` or `range(start, stop, step)`.
+pub fn range_function() -> MNativeFunctionImpl {
+    MNativeFunctionImpl::new("range", |args, kwargs| {
+        check_args("range", &args, &kwargs, 1, 3)?;
+        let bounds = args
+            .iter()
+            .map(|arg| int_arg(arg, "range bound"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let range = match bounds[..] {
+            [stop] => MRangeImpl::new(0, stop, 1),
+            [start, stop] => MRangeImpl::new(start, stop, 1),
+            [start, stop, step] => MRangeImpl::new(start, stop, step),
+            _ => unreachable!(),
+        }?;
+        Ok(range.wrap())
+    })
+    .with_docstring("The ints from `start` (0 by default) up to `stop`, counting by `step` (1 by default).")
+}
+
+pub(super) fn create_range_type() -> MTypeRef {
+    MTypeImpl::new("range", None, vec![BUILTINS.get_type("obj")]).wrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(start: i64, stop: i64, step: i64) -> Vec<String> {
+        let range: MObjectRef = MRangeImpl::new(start, stop, step).ok().unwrap().wrap();
+        let items = range.unpack().ok().unwrap();
+        assert_eq!(range.len().ok(), Some(items.len()));
+        items.iter().map(|i| i.to_ext_string(0, false).ok().unwrap()).collect()
+    }
+
+    #[test]
+    fn iteration() {
+        assert_eq!(elements(0, 4, 1), ["0", "1", "2", "3"]);
+        assert_eq!(elements(1, 8, 3), ["1", "4", "7"]);
+        assert_eq!(elements(5, 0, -2), ["5", "3", "1"]);
+        assert!(elements(3, 3, 1).is_empty());
+        assert!(elements(0, 3, -1).is_empty());
+    }
+
+    #[test]
+    fn membership() {
+        let range: MObjectRef = MRangeImpl::new(10, 0, -3).ok().unwrap().wrap();
+        let has = |i: i64| range.contains(&(MIntImpl::from(i).wrap() as MObjectRef)).ok().unwrap();
+        assert!(has(10) && has(4) && has(1));
+        assert!(!has(0) && !has(5) && !has(13));
+    }
+}
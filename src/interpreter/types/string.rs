@@ -1,8 +1,8 @@
-use std::sync::{Arc, RwLock};
+use std::{any::Any, sync::{Arc, RwLock}};
 
-use crate::interpreter::scopes::FieldRef;
+use crate::interpreter::scopes::{FieldRef, StaticField};
 
-use super::{MObjectImpl, MObject, builtin::BUILTINS, MFuncResult, MTypeRef, MObjectRef};
+use super::{object::{MObjectImpl, MObject}, builtin::BUILTINS, MFuncResult, MTypeImpl, MTypeRef, MObjectRef, BinaryOperator, object::{binop_unsupported, CloneMemo}, list::{resolve_index, slice_indices, repeat}, iterator::MIteratorImpl};
 use delegate::delegate;
 
 pub trait MString: MObject {}
@@ -24,29 +24,109 @@ impl MObject for MStringImpl {
         BUILTINS.get_type("str")
     }
     fn str_nice(&self) -> MFuncResult {
-        Ok(MStringImpl::from(self.value).wrap())
+        Ok(MStringImpl::from(&self.value).wrap())
     }
     fn str_debug(&self) -> MFuncResult{
         Ok(MStringImpl::from(format!("'{}'", self.value)).wrap())
     }
-    fn to_ext_string(&self, depth: usize, use_debug: bool) -> Result<String, MObjectRef> {
-        Ok(self.value.to_owned())
+    fn to_ext_string(&self, _depth: usize, use_debug: bool) -> Result<String, MObjectRef> {
+        if use_debug {
+            Ok(format!("'{}'", self.value))
+        } else {
+            Ok(self.value.to_owned())
+        }
+    }
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        match op {
+            // strings are immutable, so concatenation always creates a new one
+            BinaryOperator::Plus => Ok(MStringImpl::from(
+                self.value.clone() + &other.to_ext_string(0, false)?
+            ).wrap()),
+            BinaryOperator::Mul => {
+                let chars = self.chars();
+                let repeated: String = repeat(&chars, &other, self, op)?.iter()
+                    .map(|c| c.to_ext_string(0, false))
+                    .collect::<Result<_, _>>()?;
+                Ok(MStringImpl::from(repeated).wrap())
+            }
+            _ => Err(binop_unsupported(self, &other, op)),
+        }
+    }
+    fn index(&self, index: MObjectRef) -> Result<FieldRef, MObjectRef> {
+        let chars = self.chars();
+        let i = resolve_index(chars.len(), &index)?;
+        // strings are immutable, so the field is readonly from the start
+        Ok(Arc::new(RwLock::new(StaticField::new(None, None, Some(chars[i].clone()), true))))
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        Ok(MStringImpl::value_of(other).as_deref() == Some(&self.value[..]))
+    }
+    /// `sub in str` checks for substrings.
+    fn contains(&self, item: &MObjectRef) -> Result<bool, MObjectRef> {
+        match MStringImpl::value_of(item) {
+            Some(sub) => Ok(self.value.contains(&sub[..])),
+            None => Err(MStringImpl::from(format!(
+                "`in <str>` requires a str as left operand, not `{}`",
+                item.objtype().read().unwrap().name()
+            )).wrap()),
+        }
+    }
+    /// The length of a string is its number of characters, not bytes.
+    fn len(&self) -> Result<usize, MObjectRef> {
+        Ok(self.value.chars().count())
+    }
+    fn slice(&self, start: Option<MObjectRef>, stop: Option<MObjectRef>, step: Option<MObjectRef>) -> MFuncResult {
+        let chars: Vec<char> = self.value.chars().collect();
+        let indices = slice_indices(chars.len(), start, stop, step)?;
+        Ok(MStringImpl::from(indices.into_iter().map(|i| chars[i]).collect::<String>()).wrap())
+    }
+    fn unpack(&self) -> Result<Vec<MObjectRef>, MObjectRef> {
+        Ok(self.chars())
+    }
+    fn iter(&self) -> MFuncResult {
+        Ok(MIteratorImpl::from_vec(self.chars()).wrap())
+    }
+    fn clone_value(&self, _memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        Ok(None)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
     }
-            
 }
 impl MString for MStringImpl {}
 
 impl MStringImpl {
     pub fn new(value: String) -> Self {
-        MStringImpl { mobject: MObjectImpl::new(), value }
+        MStringImpl { mobject: MObjectImpl::new(BUILTINS.get_type("str")), value }
+    }
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+    /// The value of an object, if it is a `str`.
+    pub fn value_of(obj: &MObjectRef) -> Option<String> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MStringImpl>()
+            .map(|o| o.value.clone())
+    }
+    /// The characters of the string, each as a string of its own.
+    fn chars(&self) -> Vec<MObjectRef> {
+        self.value.chars().map(|c| MStringImpl::from(c.to_string()).wrap() as MObjectRef).collect()
     }
     pub fn wrap(self) -> Arc<RwLock<Self>> {
         Arc::new(RwLock::new(self))
     }
 }
 impl From<MStringImpl> for MStringImplRef {
+    fn from(o: MStringImpl) -> Self {
+        o.wrap()
+    }
 }
 impl From<MStringImpl> for MStringRef {
+    fn from(o: MStringImpl) -> Self {
+        o.wrap()
+    }
 }
 
 
@@ -79,3 +159,12 @@ impl From<&str> for MStringImpl {
         Self::from(c.to_owned())
     }
 }
+
+/// The builtin conversion to `str`, which works for any value: it gives the string that printing the value shows.
+fn str_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    Ok(Some(MStringImpl::from(value.to_ext_string(0, false)?).wrap()))
+}
+
+pub(super) fn create_str_type() -> MTypeRef {
+    MTypeImpl::new("str", None, vec![BUILTINS.get_type("obj")]).with_from(str_from).wrap()
+}
@@ -0,0 +1,184 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::interpreter::scopes::{FieldRef, StaticField};
+
+use super::{
+    builtin::BUILTINS,
+    function::{check_args, MNativeFunctionImpl},
+    int::MIntImpl,
+    iterator::MIteratorImpl,
+    list::{count, join_repr, position, repeat, resolve_index, sequence_contains, sequence_equals, slice_indices},
+    object::{binop_unsupported, deep_clone, same_object, CloneMemo, MObject, MObjectImpl, MObjectRef},
+    string::MStringImpl,
+    BinaryOperator, MFuncResult, MTypeImpl, MTypeRef,
+};
+use delegate::delegate;
+
+pub trait MTuple: MObject {
+    fn items(&self) -> &Vec<MObjectRef>;
+}
+pub type MTupleRef = Arc<RwLock<dyn MTuple>>;
+
+/// The builtin immutable sequence type, written `(a, b)` or `(a,)` for a single element.
+pub struct MTupleImpl {
+    mobject: MObjectImpl,
+    items: Vec<MObjectRef>,
+}
+pub type MTupleImplRef = Arc<RwLock<MTupleImpl>>;
+impl MObject for MTupleImpl {
+    delegate! {
+        to self.mobject {
+            fn get_field(&self, name: &str) -> Option<FieldRef>;
+            fn insert_field(&self, field: FieldRef);
+        }
+    }
+    fn objtype(&self) -> MTypeRef {
+        BUILTINS.get_type("tuple")
+    }
+    fn str_debug(&self) -> MFuncResult {
+        Ok(MStringImpl::from(self.to_ext_string(0, true)?).wrap())
+    }
+    fn to_ext_string(&self, depth: usize, _use_debug: bool) -> Result<String, MObjectRef> {
+        let inner = join_repr(&self.items, depth)?;
+        // a single element needs the trailing comma to not look like brackets
+        Ok(if self.items.len() == 1 {
+            format!("({},)", inner)
+        } else {
+            format!("({})", inner)
+        })
+    }
+    fn dot(&self, name: &str) -> Result<FieldRef, MObjectRef> {
+        match tuple_method(&self.items, name) {
+            Some(method) => Ok(method.into_field()),
+            None => self.mobject.dot(name),
+        }
+    }
+    fn index(&self, index: MObjectRef) -> Result<FieldRef, MObjectRef> {
+        let i = resolve_index(self.items.len(), &index)?;
+        // tuples are immutable, so the field is readonly from the start
+        Ok(Arc::new(RwLock::new(StaticField::new(
+            None,
+            None,
+            Some(self.items[i].clone()),
+            true,
+        ))))
+    }
+    fn binop(&self, other: MObjectRef, op: BinaryOperator) -> MFuncResult {
+        match op {
+            BinaryOperator::Plus => match MTupleImpl::items_of(&other) {
+                Some(rhs) => Ok(MTupleImpl::new([self.items.clone(), rhs].concat()).wrap()),
+                None => Err(binop_unsupported(self, &other, op)),
+            },
+            BinaryOperator::Mul => Ok(MTupleImpl::new(repeat(&self.items, &other, self, op)?).wrap()),
+            _ => Err(binop_unsupported(self, &other, op)),
+        }
+    }
+    fn equals(&self, other: &MObjectRef) -> Result<bool, MObjectRef> {
+        if same_object(self, other) {
+            return Ok(true);
+        }
+        match MTupleImpl::items_of(other) {
+            Some(rhs) => sequence_equals(&self.items, &rhs),
+            None => Ok(false),
+        }
+    }
+    fn contains(&self, item: &MObjectRef) -> Result<bool, MObjectRef> {
+        sequence_contains(&self.items, item)
+    }
+    fn len(&self) -> Result<usize, MObjectRef> {
+        Ok(self.items.len())
+    }
+    fn slice(
+        &self,
+        start: Option<MObjectRef>,
+        stop: Option<MObjectRef>,
+        step: Option<MObjectRef>,
+    ) -> MFuncResult {
+        let indices = slice_indices(self.items.len(), start, stop, step)?;
+        Ok(MTupleImpl::new(indices.into_iter().map(|i| self.items[i].clone()).collect()).wrap())
+    }
+    fn unpack(&self) -> Result<Vec<MObjectRef>, MObjectRef> {
+        Ok(self.items.clone())
+    }
+    fn iter(&self) -> MFuncResult {
+        Ok(MIteratorImpl::from_vec(self.items.clone()).wrap())
+    }
+    fn clone_value(&self, memo: &mut CloneMemo) -> Result<Option<MObjectRef>, MObjectRef> {
+        let items = self
+            .items
+            .iter()
+            .map(|item| deep_clone(item, memo))
+            .collect::<Result<Vec<_>, _>>()?;
+        // a tuple of immutable values can be shared as well
+        let unchanged = items
+            .iter()
+            .zip(&self.items)
+            .all(|(copy, item)| Arc::as_ptr(copy) as *const u8 == Arc::as_ptr(item) as *const u8);
+        Ok(if unchanged { None } else { Some(MTupleImpl::new(items).wrap()) })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl MTuple for MTupleImpl {
+    fn items(&self) -> &Vec<MObjectRef> {
+        &self.items
+    }
+}
+
+impl MTupleImpl {
+    pub fn new(items: Vec<MObjectRef>) -> Self {
+        MTupleImpl {
+            mobject: MObjectImpl::new(BUILTINS.get_type("tuple")),
+            items,
+        }
+    }
+    pub fn wrap(self) -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(self))
+    }
+    /// The elements of an object, if it is a `tuple`.
+    pub fn items_of(obj: &MObjectRef) -> Option<Vec<MObjectRef>> {
+        obj.read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<MTupleImpl>()
+            .map(|o| o.items.clone())
+    }
+}
+impl From<Vec<MObjectRef>> for MTupleImpl {
+    fn from(items: Vec<MObjectRef>) -> Self {
+        Self::new(items)
+    }
+}
+
+/// The methods of `tuple`, bound to the elements of a specific tuple.
+fn tuple_method(items: &[MObjectRef], name: &str) -> Option<MNativeFunctionImpl> {
+    let items = items.to_vec();
+    let method = match name {
+        "index" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("index", &args, &kwargs, 1, 1)?;
+            match position(&items, &args[0])? {
+                Some(i) => Ok(MIntImpl::from(i as i64).wrap()),
+                None => Err(MStringImpl::from("tuple.index: value not in tuple").wrap()),
+            }
+        }),
+        "count" => MNativeFunctionImpl::new(name, move |args, kwargs| {
+            check_args("count", &args, &kwargs, 1, 1)?;
+            Ok(MIntImpl::from(count(&items, &args[0])? as i64).wrap())
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// The builtin conversion to `tuple`, from anything that can be iterated over, like `list`.
+fn tuple_from(value: &MObjectRef) -> Result<Option<MObjectRef>, MObjectRef> {
+    Ok(Some(MTupleImpl::new(value.unpack()?).wrap()))
+}
+
+pub(super) fn create_tuple_type() -> MTypeRef {
+    MTypeImpl::new("tuple", None, vec![BUILTINS.get_type("obj")]).with_from(tuple_from).wrap()
+}
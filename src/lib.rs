@@ -1,6 +1,3 @@
-#![feature(try_blocks)]
-#![feature(let_chains)]
-#![feature(if_let_guard)]
 pub mod interpreter;
 pub mod parser;
 mod macros;
\ No newline at end of file
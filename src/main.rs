@@ -1,7 +1,161 @@
 #![feature(try_blocks)]
 
-// use mscript::interpreter;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-fn main() {
-    // interpreter::ast::main();
-}
\ No newline at end of file
+use mscript::interpreter::{docgen, formatter, lint};
+
+const USAGE: &str = "usage: msh doc <script or directory> [-o <output directory>]
+       msh fmt [--check] <scripts or directories>...
+       msh lint <scripts or directories>...";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("doc") => doc(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `msh doc`: write the API reference of a script or module tree, by default into `./api`.
+fn doc(args: &[String]) -> ExitCode {
+    let (root, out_dir) = match args {
+        [root] => (root, "api"),
+        [root, flag, out_dir] if flag == "-o" => (root, out_dir.as_str()),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match docgen::generate(Path::new(root), Path::new(out_dir)) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The scripts to work on: the given files, and the `.m` files inside the given directories.
+fn scripts(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut scripts = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            let pattern = path.join("**").join("*.m");
+            for script in glob::glob(&pattern.to_string_lossy()).map_err(|e| e.to_string())? {
+                scripts.push(script.map_err(|e| e.to_string())?);
+            }
+        } else {
+            scripts.push(path.to_owned());
+        }
+    }
+    Ok(scripts)
+}
+
+/**
+`msh fmt`: format scripts in place. With `--check`, only list the scripts that aren't formatted,
+failing if there are any.
+ */
+fn fmt(args: &[String]) -> ExitCode {
+    let check = args.first().is_some_and(|arg| arg == "--check");
+    let paths = if check { &args[1..] } else { args };
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    let scripts = match scripts(paths) {
+        Ok(scripts) => scripts,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut failed = false;
+    for script in scripts {
+        let result: Result<(), String> = try {
+            let source = fs::read_to_string(&script).map_err(|e| e.to_string())?;
+            let formatted = formatter::format_source(&source).map_err(|errors| {
+                let errors: Vec<_> = errors
+                    .iter()
+                    .map(|error| format!("{}:{}: {}", error.line, error.column, error.msg))
+                    .collect();
+                format!("syntax errors, not formatted\n  {}", errors.join("\n  "))
+            })?;
+            if formatted != source {
+                if check {
+                    println!("{}", script.display());
+                    failed = true;
+                } else {
+                    fs::write(&script, formatted).map_err(|e| e.to_string())?;
+                }
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("error: {}: {}", script.display(), e);
+            failed = true;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// `msh lint`: report common pitfalls in scripts, failing if there are any.
+fn lint(paths: &[String]) -> ExitCode {
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    let scripts = match scripts(paths) {
+        Ok(scripts) => scripts,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut failed = false;
+    for script in scripts {
+        let source = match fs::read_to_string(&script) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("error: {}: {}", script.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        match lint::lint_source(&source) {
+            Ok(lints) => {
+                for lint in lints {
+                    println!("{}:{}:{}: {}: {}", script.display(), lint.line, lint.column, lint.rule, lint.msg);
+                    failed = true;
+                }
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("error: {}:{}:{}: {}", script.display(), error.line, error.column, error.msg);
+                }
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
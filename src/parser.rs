@@ -1,7 +1,159 @@
-#[allow(unused)]
-pub(crate) mod mshvisitor;
-#[allow(unused)]
-pub(crate) mod mshlexer;
-#[allow(unused)]
-pub(crate) mod mshparser;
-mod mshlistener;
\ No newline at end of file
+use std::fmt::{self, Display};
+
+use self::ast::File;
+
+/**
+The syntax tree the rest of the interpreter works with. Its nodes are plain owned values that know their span
+of source: they can be cloned, compared, serialized and kept after the source is gone.
+ */
+pub mod ast;
+mod grammar;
+/**
+The tokens of mscript. Besides the parser, the formatter and the analysis for editors work on them, since they
+need the comments and line breaks the syntax tree doesn't have.
+ */
+pub mod lexer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxErrorKind {
+    /// characters that don't form any token, like `§`
+    InvalidToken,
+    /// a token that doesn't fit where it is, like a stray `)`
+    UnexpectedToken,
+    /// a token that should be there, but isn't, like the `}` of an unclosed block
+    MissingToken,
+}
+
+/// A syntax error, located at the line (counting from 1) and column (counting from 0) where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    pub msg: String,
+    pub line: isize,
+    pub column: isize,
+    /// the text of the offending token, if the parser found one
+    pub token: Option<String>,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.msg)
+    }
+}
+
+/**
+Parse a script, keeping what the parser recovered even if there were syntax errors: the statements it could
+make sense of. The analysis for editors uses it while the script is being edited.
+ */
+pub(crate) fn parse_recovering(source: &str) -> (File, Vec<SyntaxError>) {
+    grammar::parse(source)
+}
+
+/**
+Parse a script. The parser recovers from errors by skipping to the end of the statement, so all the syntax
+errors of the script are reported, not only the first one.
+ */
+pub fn parse(source: &str) -> Result<File, Vec<SyntaxError>> {
+    match parse_recovering(source) {
+        (file, errors) if errors.is_empty() => Ok(file),
+        (_, errors) => Err(errors),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_all_errors() {
+        let errors = parse("local = 1\nlocal ok = 2\nx = (1 + \n").unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines.first(), Some(&1));
+        assert!(lines.len() >= 2, "{:?}", errors);
+        assert!(!lines.contains(&2));
+    }
+
+    #[test]
+    fn mismatched_brackets_dont_panic() {
+        for source in ["}\n", ")\n", "]]\n", "{ f( }\n", "{ local x = [1, 2 }\nlocal y = 3\n"] {
+            let errors = parse(source).unwrap_err();
+            assert!(!errors.is_empty(), "{}", source);
+        }
+        let errors = parse("local a = §\n").unwrap_err();
+        assert_eq!(errors[0].kind, SyntaxErrorKind::InvalidToken);
+    }
+
+    #[test]
+    fn lowers_to_ast() {
+        use ast::*;
+
+        let file = parse("#!exec /bin/msh\nfor (k, *v) in pairs {\n  x += f(k, sep = v)[1:]\n}\n").unwrap();
+        assert_eq!(file.exec.as_ref().unwrap().path, "/bin/msh");
+        let [Stmt { kind: StmtKind::For { pattern, iter, body }, span }] = &file.body[..] else {
+            panic!("{:?}", file.body);
+        };
+        assert_eq!((span.start, span.end), ((2, 0), (4, 1)));
+        let names: Vec<_> = pattern.names().iter().map(|name| name.name.as_str()).collect();
+        assert_eq!(names, ["k", "v"]);
+        assert_eq!(iter.kind, ExprKind::Ident("pairs".to_owned()));
+
+        let StmtKind::Block(block) = &body.kind else { panic!("{:?}", body) };
+        let StmtKind::Assign { target, op, value } = &block.body[0].kind else { panic!("{:?}", block) };
+        assert_eq!(target.kind, ExprKind::Ident("x".to_owned()));
+        assert_eq!(*op, AssignOp::Compound(BinaryOp::Plus));
+        let ExprKind::Slice { target, start, stop: None, step: None } = &value.kind else { panic!("{:?}", value) };
+        assert_eq!(start.as_ref().unwrap().kind, ExprKind::Int("1".to_owned()));
+        let ExprKind::Call { args, kwargs, .. } = &target.kind else { panic!("{:?}", target) };
+        assert_eq!((args.len(), kwargs[0].name.name.as_str()), (1, "sep"));
+        assert_eq!((value.span.start, value.span.end), ((3, 7), (3, 24)));
+    }
+
+    /// Lines of the examples that use syntax the grammar doesn't have (yet), eg. `return`, `struct` or f-strings.
+    /// The ANTLR parser generated from `Msh.g4` rejects exactly these lines as well.
+    const UNSUPPORTED_EXAMPLE_LINES: &[(&str, &[usize])] = &[
+        ("datatypes.m", &[1, 10, 16, 19, 22, 27, 32, 38, 42, 43, 46, 48, 54, 56, 58, 59, 62, 69]),
+        ("hello_world.m", &[111, 124, 160]),
+        ("liba.m", &[41, 97, 107, 154, 162, 174]),
+    ];
+
+    /**
+    Parse the examples without their unsupported lines, and compare the result to the AST in `src/parser/snapshots`.
+    The snapshots were recorded from the ANTLR parser generated from `Msh.g4`, lowered to the same JSON as `ast::File`,
+    so this checks that the parser follows the grammar.
+     */
+    #[test]
+    fn parses_examples() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        for entry in std::fs::read_dir(root.join("doc/examples")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some("m".as_ref()) {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let unsupported = match UNSUPPORTED_EXAMPLE_LINES.iter().find(|(file, _)| *file == name) {
+                Some((_, lines)) => *lines,
+                None => &[],
+            };
+            let source = std::fs::read_to_string(&path).unwrap();
+            let lines: Vec<_> = source
+                .split('\n')
+                .enumerate()
+                .map(|(i, line)| if unsupported.contains(&(i + 1)) { "" } else { line })
+                .collect();
+            let file = match parse(&lines.join("\n")) {
+                Ok(file) => file,
+                Err(errors) => panic!("{}: {:?}", name, errors),
+            };
+            let snapshot = root.join("src/parser/snapshots").join(name).with_extension("json");
+            let expected: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(snapshot).unwrap()).unwrap();
+            assert!(serde_json::to_value(&file).unwrap() == expected, "{}: the AST differs from the snapshot", name);
+        }
+    }
+
+    #[test]
+    fn ast_roundtrips_through_json() {
+        let file = parse("import a, b = c from ./lib()\nconst {x, y: [z]} = {**a, b}\nprint(!x as str)\n").unwrap();
+        let json = serde_json::to_string(&file).unwrap();
+        assert_eq!(serde_json::from_str::<ast::File>(&json).unwrap(), file);
+    }
+}
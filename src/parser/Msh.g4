@@ -0,0 +1,299 @@
+grammar Msh;
+
+// The reference grammar of mscript: `lexer.rs` and `grammar.rs` implement it by hand, so changes to the syntax
+// start here. The actions are kept to document the behaviour of the lexer.
+
+// keep track of nesting levels to determine correct newline behavior
+@lexer::fields {
+  nesting: usize,
+  bracket_stack: Vec<usize>,
+  strict_dolstr: bool
+}
+@lexer::init { nesting: 0, bracket_stack: vec![], strict_dolstr: false}
+
+
+file: (STATIC_EXEC execLine NL)? instructions EOF;
+
+execLine: FILE_PATH;
+
+// a block or file may start with empty lines, eg. `{` on its own line
+instructions: NL* (tlstat ((SEMICOLON | SEMICOLON? NL) tlstat?)*)?;
+tlstat: staticInst
+      | argdecl
+      | exportRunBlock
+      | stat;
+
+exportRunBlock: (EXPORT | RUN) block;
+
+// `expr` comes before `assignment` so that ANTLR resolves the ambiguity of `import a = ./b` as an import alias,
+// not as assigning to `import a`
+stat: block
+    | funcdef
+    | vardecl
+    | expr
+    | assignment
+    | BREAK
+    | CONTINUE
+    | IF expr NL? THEN stat NL? (ELSE stat)?
+    | LOOP stat WHILE expr
+    | WHILE expr LOOP stat
+    | FOR pattern IN expr stat
+    ;
+
+// the line break after a static instruction separates it from the next statement, like after any other
+staticInst: STATIC_INST ID ~NL* ;
+
+// Operator precedence, from the tightest binding level to the loosest. ANTLR gives each alternative of a
+// left-recursive rule a level of its own in the order they are listed, so the operators of a level share an
+// alternative; the postfix operators are separate alternatives, but nothing binds between them.
+//   postfix         a[i]  a[i:j]  f(x)  a.b  a++  a--
+//   power           **     right-associative: `a ** b ** c` is `a ** (b ** c)`, and `!a ** b` is `!(a ** b)`
+//   unary           !  !!  ++a  --a
+//   multiplicative  *  /  %  @
+//   additive        +  -
+//   bitwise         &, then ^, then |
+//   comparison      ==  !=  in
+//   logical         &&, then ||
+//   cast            as     `a + b as int` is `(a + b) as int`
+// All the binary operators except `**` are left-associative: `a - b - c` is `(a - b) - c`.
+expr: number                                                  # num
+    | LITERAL                                                 # literal
+    | FILE_PATH                                               # pathLiteral
+    | bool                                                    # boolean
+    | ID                                                      # identifier
+    | LBRACK (listEntry (COMMA listEntry)* COMMA?)? RBRACK    # listInit
+    | LBRACE (dictEntry (COMMA dictEntry)* COMMA?)? RBRACE    # dictInit
+    | LPAREN expr RPAREN                                      # brackets
+    // a tuple needs at least one comma to be distinguished from brackets: `()`, `(a,)`, `(a, b)`
+    | LPAREN (listEntry COMMA (listEntry (COMMA listEntry)* COMMA?)?)? RPAREN  # tupleInit
+    | importStmt                                              # inlineImport
+    // as a statement of its own, `global ID` declares the variable as global in the current scope
+    | GLOBAL ID                                               # inlineGlobal
+    // undeclare a local variable, evaluating to its value
+    | UNLOC ID                                                # unloc
+// postfix
+    | expr LBRACK index=expr RBRACK                           # index
+    | expr LBRACK start=expr? COLON stop=expr? (COLON step=expr?)? RBRACK  # slice
+    | expr LPAREN funcArgs? RPAREN                            # functionCall
+    | expr DOT ID                                             # dotaccess
+    | expr op=(INC | DEC)                                     # postUpdate
+// power
+    | <assoc=right> expr TWOSTAR expr                         # pow
+// unary
+    | op=(NOT | BITNOT | INC | DEC) expr                      # prefix
+// multiplicative and additive
+    | expr op=(STAR | SLASH | MOD | ATOP) expr                # multiplicative
+    | expr op=(PLUS | MINUS) expr                             # additive
+// bitwise
+    | expr BITAND expr                                        # bitand
+    | expr XOR expr                                           # bitxor
+    | expr BITOR expr                                         # bitor
+// comparisons
+    | expr op=(EQEQ | NEQ | IN) expr                          # comparison
+// logic operators
+    | expr AND expr                                           # and
+    | expr OR expr                                            # or
+// cast
+    | expr AS typedef                                         # typecast
+    ;
+
+// `<=` assigns by value (see `assignOp`)
+vardecl: (EXPORT? (LOCAL|CONST) | EXPORT) pattern (COLON typedef)? ((EQ | LEQ) expr)?;
+argdecl: ARG ID (COLON typedef)? (EQ expr)?;
+
+// the target is checked by the compiler: only identifiers, dot access and indexing can be assigned to,
+// as well as list, tuple and dict literals of those, which destructure the value like patterns do.
+// `=` assigns objects by reference, while `a <= b` assigns a copy made by `b.$clone`.
+assignment: target=expr op=assignOp value=expr;
+assignOp: EQ | LEQ | PLUSEQ | MINUSEQ | MULEQ | POWEQ | DIVEQ | MODEQ | ATOPEQ | BITANDEQ | BITOREQ | XOREQ;
+
+funcdef: EXPORT? FUNC ID LPAREN funcFormalArgs? RPAREN (RARROW typedef) block;
+
+funcFormalArgs: funcFormalArg (COMMA funcFormalArg)* COMMA?;
+
+funcFormalArg: ID (COLON typedef)? (EQ expr)?;
+
+block: LBRACE instructions RBRACE;
+
+typedef: ID;
+
+// the targets of a destructuring declaration, eg. `(k, v)`, `[first, *rest]` or `{name, size: (w, h)}`.
+// Like in tuple literals, `(a)` is just `a`.
+pattern: ID
+       | LPAREN (patternEntry (COMMA patternEntry)* COMMA?)? RPAREN
+       | LBRACK (patternEntry (COMMA patternEntry)* COMMA?)? RBRACK
+       | LBRACE (dictPatternEntry (COMMA dictPatternEntry)* COMMA?)? RBRACE
+       ;
+patternEntry: STAR ID | pattern;
+// keys work like in dict literals: `{a}` is short for `{a: a}`, and identifier keys are used as strings.
+dictPatternEntry: ID | key=expr COLON pattern;
+
+listEntry: STAR expr | expr;
+// like in JS, `{a}` is short for `{a: a}`, and identifiers as keys are used as strings. `{(a): 1}` uses the value of `a`.
+dictEntry: TWOSTAR expr | ID | key=expr COLON value=expr;
+
+funcArgs: posArgs COMMA? | (posArgs COMMA)? kwArgs COMMA?;
+posArgs: expr (COMMA expr)*;
+kwArgs: kwArg (COMMA kwArg)*;
+kwArg: ID EQ expr;
+
+// TODO more functionality for import targets
+importStmt: IMPORT (ID EQ)? importSource
+           | IMPORT (STAR | importSelector (COMMA importSelector)*) FROM importSource;
+importSource: expr;
+importSelector: STAR | (ID EQ)? ID;
+
+number: numInt | numFloat;
+numInt: DEC_INT | HEX_INT | BIN_INT;
+numFloat: DEC_FLOAT | HEX_FLOAT | BIN_FLOAT;
+
+bool: TRUE | FALSE;
+
+
+
+/// lexer
+
+//keywords
+TRUE: 'true';
+FALSE: 'false';
+LOCAL: 'local';
+GLOBAL: 'global';
+UNLOC: 'unloc';
+FUNC: 'func';
+IMPORT: 'import';
+FROM: 'from';
+AS: 'as';
+ARG: 'arg';
+RUN: 'run';
+EXPORT: 'export';
+CONST: 'const';
+IF: 'if';
+THEN: 'then';
+ELSE: 'else';
+LOOP: 'loop';
+WHILE: 'while';
+BREAK: 'break';
+CONTINUE: 'continue';
+FOR: 'for';
+IN: 'in';
+
+// identifiers (makes sense right)
+ID: ID_LETTER (ID_LETTER | DEC_DIGIT) *;
+fragment ID_LETTER: [a-zA-Z_$];
+
+// strings; TODO: add better support for the lexing of dolstrings
+LITERAL : '\'' (~['$] | {recog.strict_dolstr}? '$' | ESCAPE_CHARS )* '\'';
+DOLSTRING : ('$\'' | {!recog.strict_dolstr}? '\'') (~['$] | ESCAPE_CHARS )* (('$' ID | '{' DOLSTR_NESTED '}') (~['$] | ESCAPE_CHARS )*)+ '\'';
+fragment DOLSTR_NESTED: ~[{}]* ('{' DOLSTR_NESTED '}' ~[{}]*)*;
+fragment ESCAPE_CHARS : '\\' ([$'bnrt\\] | 'x' HEX_DIGIT HEX_DIGIT | 'u' HEX_DIGIT HEX_DIGIT HEX_DIGIT HEX_DIGIT);
+
+// file paths: either it's obvious that we have a path, or we explicitly denote it with ~
+// TODO: allow variables to be entered
+FILE_PATH: '~'? '.'? '.'? '/' (FILE_PATH_SEGMENT ('/' FILE_PATH_SEGMENT)* '/'?)?
+         | '~' FILE_PATH_SEGMENT ('/' FILE_PATH_SEGMENT)* '/'?;
+fragment FILE_PATH_SEGMENT: FILE_PATH_CHAR+
+                          | '\'' (~'\'' | ESCAPE_CHARS) '\'';
+// `*` and `?` make the path a glob
+fragment FILE_PATH_CHAR: [a-zA-Z0-9_\-+?*"~%.];
+
+// integers and floating point numbers
+fragment NUM_SIGN : [+\-];
+fragment DEC_DIGIT: [0-9];
+fragment HEX_DIGIT: [0-9A-Fa-f];
+fragment OCT_DIGIT: [0-7];
+
+DEC_INT : NUM_SIGN? ('0' | [1-9] ('_'? DEC_DIGIT)*) ;
+HEX_INT : NUM_SIGN? '0x' HEX_DIGIT ('_'? HEX_DIGIT)* ;
+BIN_INT : NUM_SIGN? '0b' [01] ('_'? [01])* ;
+
+// exponents can themselves be written in any base. `e` scales by powers of 10, `p` by powers of 2;
+// hex mantissas only allow `p` because `e` is a hex digit.
+fragment EXPONENT: DEC_INT | HEX_INT | BIN_INT;
+DEC_FLOAT : (DEC_INT '.' (DEC_DIGIT ('_'? DEC_DIGIT)*)? | NUM_SIGN? '.' DEC_DIGIT ('_'? DEC_DIGIT)*) ([eEpP] EXPONENT)?
+          | DEC_INT [eEpP] EXPONENT ;
+HEX_FLOAT : (HEX_INT '.' (HEX_DIGIT ('_'? HEX_DIGIT)*)? | NUM_SIGN? '0x.' HEX_DIGIT ('_'? HEX_DIGIT)*) ([pP] EXPONENT)?
+          | HEX_INT [pP] EXPONENT ;
+BIN_FLOAT : (BIN_INT '.' ([01] ('_'? [01])*)? | NUM_SIGN? '0b.' [01] ('_'? [01])*) ([eEpP] EXPONENT)?
+          | BIN_INT [eEpP] EXPONENT ;
+
+// operators
+AND : '&&';
+BITAND: '&';
+OR : '||';
+BITOR : '|';
+NOT : '!';
+BITNOT: '!!';
+XOR: '^';
+PLUS: '+';
+MINUS: '-';
+STAR: '*';
+TWOSTAR: '**';
+SLASH: '/';
+MOD: '%';
+ATOP: '@';
+
+BITANDEQ : '&=';
+BITOREQ : '|=';
+XOREQ: '^=';
+PLUSEQ: '+=';
+MINUSEQ: '-=';
+MULEQ: '*=';
+POWEQ: '**=';
+DIVEQ: '/=';
+MODEQ: '%=';
+ATOPEQ: '@=';
+
+INC: '++';
+DEC: '--';
+
+// misc characters
+DOT: '.';
+COMMA: ',';
+COLON: ':';
+SEMICOLON: ';';
+EQ: '=';
+EQEQ: '==';
+NEQ: '!=';
+GT: '>';
+GEQ: '>=';
+LT: '<';
+LEQ: '<=';
+RARROW: '->';
+
+STATIC_INST: '#!';
+STATIC_EXEC: '#!exec';
+
+
+// documentation & general comments. They are kept on the hidden channel: doc comments so the compiler
+// can attach them to the declaration that follows, all of them so the formatter can preserve them.
+// A line comment leaves its line break alone, which still ends the statement before the comment.
+DOCBCOMMENT: '##<' (BCOMMENT | DOCBCOMMENT | ~'>' | '>' ~'#')* '>##' -> channel(HIDDEN);
+DOCCOMMENT: '##' ~'\n'* '\n' -> channel(HIDDEN);
+BCOMMENT: '#<' (BCOMMENT | DOCBCOMMENT | ~'>' | '>' ~'#')* '>#' -> channel(HIDDEN);
+COMMENT : '#' (~[!\n] ~'\n'*)? -> channel(HIDDEN);
+
+
+// bracket level influences newline parsing. Mismatched brackets are left to the parser to report;
+// the lexer just keeps going, as if the brackets were balanced at the next closing brace.
+LPAREN : '(' {recog.nesting+=1;} ;
+RPAREN : ')' {recog.nesting = recog.nesting.saturating_sub(1);} ;
+LBRACK : '[' {recog.nesting+=1;} ;
+RBRACK : ']' {recog.nesting = recog.nesting.saturating_sub(1);} ;
+LBRACE : '{' {
+  let nesting = recog.nesting;
+  recog.bracket_stack.push(nesting);
+  recog.nesting = 0;
+} ;
+RBRACE : '}' {
+  recog.nesting = recog.bracket_stack.pop().unwrap_or(0);
+} ;
+
+// how to work with whitespace & newlines
+WS : [ \t] -> skip;
+LINE_ESCAPE: '\\' NL -> skip;
+IGNORE_NEWLINE
+:
+ '\r'? '\n' {recog.nesting > 0}? -> skip
+;
+NL : '\r'?'\n';
+
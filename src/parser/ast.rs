@@ -0,0 +1,547 @@
+use std::{
+    fmt::{self, Display},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+/**
+A range of the source, from `start` (inclusive) to `end` (exclusive). Positions are (line, column),
+lines counting from 1 and columns (in characters) from 0, like the positions of tokens.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: (isize, isize),
+    pub end: (isize, isize),
+}
+
+impl Span {
+    pub fn new(start: (isize, isize), end: (isize, isize)) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both spans.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn line(&self) -> isize {
+        self.start.0
+    }
+
+    pub fn contains(&self, pos: (isize, isize)) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// The source text the span covers. Lines count from 1 and columns from 0, in characters.
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        let offset = |(line, column): (isize, isize)| {
+            let line_start: usize = source.split_inclusive('\n').take(line as usize - 1).map(str::len).sum();
+            let rest = &source[line_start..];
+            line_start + rest.char_indices().nth(column as usize).map_or(rest.len(), |(i, _)| i)
+        };
+        &source[offset(self.start)..offset(self.end)]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// A whole script.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct File {
+    /// the interpreter given by `#!exec` on the first line
+    pub exec: Option<ExecLine>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecLine {
+    pub path: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StmtKind {
+    /// `#!name args...`; the arguments are the text of the tokens up to the end of the line
+    Static { name: Ident, args: Vec<String> },
+    /// `arg name: type = default`
+    Arg { name: Ident, ty: Option<TypeExpr>, default: Option<Expr> },
+    /// `export { ... }`
+    Export(Block),
+    /// `run { ... }`
+    Run(Block),
+    Block(Block),
+    Func(FuncDef),
+    Var(VarDecl),
+    Assign { target: Expr, op: AssignOp, value: Expr },
+    Expr(Expr),
+    Break,
+    Continue,
+    If { cond: Expr, then: Box<Stmt>, otherwise: Option<Box<Stmt>> },
+    /// `loop body while cond`, which runs the body at least once
+    DoWhile { body: Box<Stmt>, cond: Expr },
+    /// `while cond loop body`
+    While { cond: Expr, body: Box<Stmt> },
+    For { pattern: Pattern, iter: Expr, body: Box<Stmt> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+    pub body: Vec<Stmt>,
+    /// from the opening to the closing brace
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FuncDef {
+    pub export: bool,
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub returns: TypeExpr,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Option<TypeExpr>,
+    pub default: Option<Expr>,
+    pub span: Span,
+}
+
+/// `local`, `const` and `export` declarations. `export x` is an exported local.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VarDecl {
+    pub export: bool,
+    pub constant: bool,
+    pub pattern: Pattern,
+    pub ty: Option<TypeExpr>,
+    pub value: Option<Expr>,
+    /// whether the value is assigned with `<=`, ie. copied
+    pub clone: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssignOp {
+    /// `=`, which assigns objects by reference
+    Assign,
+    /// `<=`, which assigns a copy made by `$clone`
+    Clone,
+    /// `+=` and the like
+    Compound(BinaryOp),
+}
+
+/// A type annotation. For now, types are only referred to by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TypeExpr {
+    Name(Ident),
+}
+
+impl TypeExpr {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeExpr::Name(name) => name.span,
+        }
+    }
+}
+
+impl Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Name(name) => f.write_str(&name.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExprKind {
+    /// an integer literal as written, eg. `-0x_ff`
+    Int(String),
+    /// a float literal as written, eg. `1.5e3`
+    Float(String),
+    /// a string literal as written, with its quotes and escape sequences
+    Str(String),
+    Path(String),
+    Bool(bool),
+    Ident(String),
+    List(Vec<ListEntry>),
+    /// a tuple literal, which has at least one comma: `()`, `(a,)`, `(a, b)`
+    Tuple(Vec<ListEntry>),
+    Dict(Vec<DictEntry>),
+    /// `(expr)`
+    Paren(Box<Expr>),
+    Index { target: Box<Expr>, index: Box<Expr> },
+    Slice { target: Box<Expr>, start: Option<Box<Expr>>, stop: Option<Box<Expr>>, step: Option<Box<Expr>> },
+    Import(Box<Import>),
+    /// `global name`
+    Global(Ident),
+    /// `unloc name`
+    Unloc(Ident),
+    Call { callee: Box<Expr>, args: Vec<Expr>, kwargs: Vec<KwArg> },
+    Unary { op: UnaryOp, operand: Box<Expr> },
+    /// `++x`, `x--` etc.
+    Update { op: UpdateOp, postfix: bool, target: Box<Expr> },
+    Binary { op: BinaryOp, lhs: Box<Expr>, rhs: Box<Expr> },
+    /// `target.field`
+    Dot { target: Box<Expr>, field: Ident },
+    /// `value as type`
+    Cast { value: Box<Expr>, ty: TypeExpr },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnaryOp {
+    /// `!`
+    Not,
+    /// `!!`
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateOp {
+    Inc,
+    Dec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinaryOp {
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    Xor,
+    /// `@`
+    AtOperator,
+    Pow,
+    Mul,
+    Div,
+    Mod,
+    Plus,
+    Minus,
+    Eq,
+    Neq,
+    /// `item in container`
+    In,
+}
+
+/// An entry of a list or tuple literal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ListEntry {
+    Item(Expr),
+    /// `*expr`
+    Spread(Expr),
+}
+
+impl ListEntry {
+    pub fn expr(&self) -> &Expr {
+        match self {
+            ListEntry::Item(expr) | ListEntry::Spread(expr) => expr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DictEntry {
+    /// `**expr`
+    Spread(Expr),
+    /// `{a}`, which is short for `{a: a}`
+    Shorthand(Ident),
+    /// `key: value`; an identifier as the key is used as a string
+    Pair { key: Expr, value: Expr },
+}
+
+/// A keyword argument of a call, `name=value`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KwArg {
+    pub name: Ident,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Import {
+    pub kind: ImportKind,
+    pub source: Expr,
+}
+
+impl Import {
+    /**
+    The name of the imported module: the name given by `import name = source`, or else the one `source` implies,
+    like `stdout` for `import stdout()` or `lib` for `import ./lib.m()`.
+     */
+    pub fn module_name(&self) -> Option<String> {
+        match &self.kind {
+            ImportKind::Module(Some(name)) => Some(name.name.clone()),
+            _ => Self::source_name(&self.source),
+        }
+    }
+
+    fn source_name(source: &Expr) -> Option<String> {
+        match &source.kind {
+            ExprKind::Call { callee, .. } => Self::source_name(callee),
+            ExprKind::Ident(name) => Some(name.clone()),
+            ExprKind::Dot { field, .. } => Some(field.name.clone()),
+            ExprKind::Path(path) => Some(Path::new(path).file_stem()?.to_string_lossy().into_owned()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ImportKind {
+    /// `import source` or `import name = source`
+    Module(Option<Ident>),
+    /// `import a, b = c, * from source`; a lone `*` is a single `All` selector
+    From(Vec<ImportSelector>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ImportSelector {
+    /// `*`
+    All,
+    /// `name` or `alias = name`
+    Name { alias: Option<Ident>, name: Ident },
+}
+
+impl ImportSelector {
+    /// The name the selector binds in the importing script.
+    pub fn binding(&self) -> Option<&Ident> {
+        match self {
+            ImportSelector::All => None,
+            ImportSelector::Name { alias, name } => Some(alias.as_ref().unwrap_or(name)),
+        }
+    }
+}
+
+/// The targets of a destructuring declaration, eg. `(k, v)`, `[first, *rest]` or `{name, size: (w, h)}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PatternKind {
+    Name(Ident),
+    Tuple(Vec<PatternEntry>),
+    List(Vec<PatternEntry>),
+    Dict(Vec<DictPatternEntry>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PatternEntry {
+    Pattern(Pattern),
+    /// `*rest`
+    Rest(Ident),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DictPatternEntry {
+    /// `{a}`, which is short for `{a: a}`
+    Shorthand(Ident),
+    Pair { key: Expr, pattern: Pattern },
+}
+
+impl Pattern {
+    /// The variables bound by the pattern, in order.
+    pub fn names(&self) -> Vec<&Ident> {
+        let mut names = Vec::new();
+        match &self.kind {
+            PatternKind::Name(name) => names.push(name),
+            PatternKind::Tuple(entries) | PatternKind::List(entries) => {
+                for entry in entries {
+                    match entry {
+                        PatternEntry::Rest(name) => names.push(name),
+                        PatternEntry::Pattern(pattern) => names.extend(pattern.names()),
+                    }
+                }
+            }
+            PatternKind::Dict(entries) => {
+                for entry in entries {
+                    match entry {
+                        DictPatternEntry::Shorthand(name) => names.push(name),
+                        DictPatternEntry::Pair { pattern, .. } => names.extend(pattern.names()),
+                    }
+                }
+            }
+        }
+        names
+    }
+}
+
+/**
+Walks a syntax tree. By default, each method visits the children of its node through the matching `walk_` function,
+so a visitor only overrides the nodes it cares about, and calls `walk_` itself to keep going below them.
+ */
+pub trait Visitor: Sized {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern);
+    }
+}
+
+pub fn walk_file(visitor: &mut impl Visitor, file: &File) {
+    for stmt in &file.body {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_block(visitor: &mut impl Visitor, block: &Block) {
+    for stmt in &block.body {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt(visitor: &mut impl Visitor, stmt: &Stmt) {
+    match &stmt.kind {
+        StmtKind::Static { .. } | StmtKind::Break | StmtKind::Continue => {}
+        StmtKind::Arg { default, .. } => {
+            if let Some(default) = default {
+                visitor.visit_expr(default);
+            }
+        }
+        StmtKind::Export(block) | StmtKind::Run(block) | StmtKind::Block(block) => visitor.visit_block(block),
+        StmtKind::Func(func) => {
+            for default in func.params.iter().filter_map(|param| param.default.as_ref()) {
+                visitor.visit_expr(default);
+            }
+            visitor.visit_block(&func.body);
+        }
+        StmtKind::Var(decl) => {
+            visitor.visit_pattern(&decl.pattern);
+            if let Some(value) = &decl.value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::Assign { target, value, .. } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        StmtKind::Expr(expr) => visitor.visit_expr(expr),
+        StmtKind::If { cond, then, otherwise } => {
+            visitor.visit_expr(cond);
+            visitor.visit_stmt(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_stmt(otherwise);
+            }
+        }
+        StmtKind::DoWhile { body, cond } => {
+            visitor.visit_stmt(body);
+            visitor.visit_expr(cond);
+        }
+        StmtKind::While { cond, body } => {
+            visitor.visit_expr(cond);
+            visitor.visit_stmt(body);
+        }
+        StmtKind::For { pattern, iter, body } => {
+            visitor.visit_pattern(pattern);
+            visitor.visit_expr(iter);
+            visitor.visit_stmt(body);
+        }
+    }
+}
+
+pub fn walk_expr(visitor: &mut impl Visitor, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::Int(_)
+        | ExprKind::Float(_)
+        | ExprKind::Str(_)
+        | ExprKind::Path(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Ident(_)
+        | ExprKind::Global(_)
+        | ExprKind::Unloc(_) => {}
+        ExprKind::List(entries) | ExprKind::Tuple(entries) => {
+            for entry in entries {
+                visitor.visit_expr(entry.expr());
+            }
+        }
+        ExprKind::Dict(entries) => {
+            for entry in entries {
+                match entry {
+                    DictEntry::Spread(expr) => visitor.visit_expr(expr),
+                    DictEntry::Shorthand(_) => {}
+                    DictEntry::Pair { key, value } => {
+                        visitor.visit_expr(key);
+                        visitor.visit_expr(value);
+                    }
+                }
+            }
+        }
+        ExprKind::Paren(inner) => visitor.visit_expr(inner),
+        ExprKind::Index { target, index } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Slice { target, start, stop, step } => {
+            visitor.visit_expr(target);
+            for bound in [start, stop, step].into_iter().flatten() {
+                visitor.visit_expr(bound);
+            }
+        }
+        ExprKind::Import(import) => visitor.visit_expr(&import.source),
+        ExprKind::Call { callee, args, kwargs } => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+            for kwarg in kwargs {
+                visitor.visit_expr(&kwarg.value);
+            }
+        }
+        ExprKind::Unary { operand, .. } => visitor.visit_expr(operand),
+        ExprKind::Update { target, .. } => visitor.visit_expr(target),
+        ExprKind::Binary { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        ExprKind::Dot { target, .. } => visitor.visit_expr(target),
+        ExprKind::Cast { value, .. } => visitor.visit_expr(value),
+    }
+}
+
+pub fn walk_pattern(visitor: &mut impl Visitor, pattern: &Pattern) {
+    match &pattern.kind {
+        PatternKind::Name(_) => {}
+        PatternKind::Tuple(entries) | PatternKind::List(entries) => {
+            for entry in entries {
+                if let PatternEntry::Pattern(pattern) = entry {
+                    visitor.visit_pattern(pattern);
+                }
+            }
+        }
+        PatternKind::Dict(entries) => {
+            for entry in entries {
+                if let DictPatternEntry::Pair { key, pattern } = entry {
+                    visitor.visit_expr(key);
+                    visitor.visit_pattern(pattern);
+                }
+            }
+        }
+    }
+}
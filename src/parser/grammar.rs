@@ -0,0 +1,1158 @@
+use super::{
+    ast::{self, *},
+    lexer::{error_display, Lexer, Token, TokenKind},
+    SyntaxError, SyntaxErrorKind,
+};
+
+use TokenKind::*;
+
+/// A rule failed after reporting a syntax error; the statement it is part of is skipped.
+struct Failed;
+
+type PResult<T> = Result<T, Failed>;
+
+/// A rule that was parsed speculatively, see [`Parser::attempt`].
+struct Attempt<T> {
+    result: PResult<T>,
+    /// the token after the rule
+    pos: usize,
+    errors: Vec<SyntaxError>,
+    /// whether the rule is followed by something that can end a statement
+    ends_stmt: bool,
+}
+
+impl<T> Attempt<T> {
+    fn ok(&self) -> bool {
+        self.result.is_ok() && self.errors.is_empty() && self.ends_stmt
+    }
+
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Attempt<U> {
+        Attempt { result: self.result.map(f), pos: self.pos, errors: self.errors, ends_stmt: self.ends_stmt }
+    }
+}
+
+/**
+The levels of operator precedence, from the loosest binding to the tightest; see the table above `expr` in `Msh.g4`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    /// `as`
+    Cast,
+    /// `||`
+    Or,
+    /// `&&`
+    And,
+    /// `==`, `!=` and `in`
+    Comparison,
+    /// `|`
+    BitOr,
+    /// `^`
+    Xor,
+    /// `&`
+    BitAnd,
+    /// `+` and `-`
+    Additive,
+    /// `*`, `/`, `%` and `@`
+    Multiplicative,
+    /// the prefix operators `!`, `!!`, `++` and `--`
+    Unary,
+    /// `**`, the only right-associative operator
+    Power,
+    /// indexing, slices, calls, `.`, and `++` and `--` after their operand
+    Postfix,
+}
+
+impl Precedence {
+    /// The level above this one, for the right operand of a left-associative operator.
+    fn tighter(self) -> Self {
+        use Precedence::*;
+        match self {
+            Cast => Or,
+            Or => And,
+            And => Comparison,
+            Comparison => BitOr,
+            BitOr => Xor,
+            Xor => BitAnd,
+            BitAnd => Additive,
+            Additive => Multiplicative,
+            Multiplicative => Unary,
+            Unary => Power,
+            Power | Postfix => Postfix,
+        }
+    }
+}
+
+/// The binary operators and their precedence.
+fn binary_op(kind: TokenKind) -> Option<(BinaryOp, Precedence)> {
+    let op = match kind {
+        Or => (BinaryOp::Or, Precedence::Or),
+        And => (BinaryOp::And, Precedence::And),
+        EqEq => (BinaryOp::Eq, Precedence::Comparison),
+        Neq => (BinaryOp::Neq, Precedence::Comparison),
+        In => (BinaryOp::In, Precedence::Comparison),
+        BitOr => (BinaryOp::BitOr, Precedence::BitOr),
+        Xor => (BinaryOp::Xor, Precedence::Xor),
+        BitAnd => (BinaryOp::BitAnd, Precedence::BitAnd),
+        Plus => (BinaryOp::Plus, Precedence::Additive),
+        Minus => (BinaryOp::Minus, Precedence::Additive),
+        Star => (BinaryOp::Mul, Precedence::Multiplicative),
+        Slash => (BinaryOp::Div, Precedence::Multiplicative),
+        Mod => (BinaryOp::Mod, Precedence::Multiplicative),
+        AtOp => (BinaryOp::AtOperator, Precedence::Multiplicative),
+        TwoStar => (BinaryOp::Pow, Precedence::Power),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// The precedence of the operators that follow their operand: indexing, calls, `++`, `.` and `as`.
+fn postfix_precedence(kind: TokenKind) -> Option<Precedence> {
+    match kind {
+        LBrack | LParen | Inc | Dec | Dot => Some(Precedence::Postfix),
+        As => Some(Precedence::Cast),
+        _ => None,
+    }
+}
+
+/// Literals and identifiers, which are a single token.
+fn literal(token: Token) -> Option<ExprKind> {
+    let text = token.text.to_owned();
+    let kind = match token.kind {
+        DecInt | HexInt | BinInt => ExprKind::Int(text),
+        DecFloat | HexFloat | BinFloat => ExprKind::Float(text),
+        Literal => ExprKind::Str(text),
+        FilePath => ExprKind::Path(text),
+        True => ExprKind::Bool(true),
+        False => ExprKind::Bool(false),
+        Id => ExprKind::Ident(text),
+        _ => return None,
+    };
+    Some(kind)
+}
+
+fn assign_op(kind: TokenKind) -> Option<AssignOp> {
+    let op = match kind {
+        Eq => AssignOp::Assign,
+        Leq => AssignOp::Clone,
+        PlusEq => AssignOp::Compound(BinaryOp::Plus),
+        MinusEq => AssignOp::Compound(BinaryOp::Minus),
+        MulEq => AssignOp::Compound(BinaryOp::Mul),
+        PowEq => AssignOp::Compound(BinaryOp::Pow),
+        DivEq => AssignOp::Compound(BinaryOp::Div),
+        ModEq => AssignOp::Compound(BinaryOp::Mod),
+        AtOpEq => AssignOp::Compound(BinaryOp::AtOperator),
+        BitAndEq => AssignOp::Compound(BinaryOp::BitAnd),
+        BitOrEq => AssignOp::Compound(BinaryOp::BitOr),
+        XorEq => AssignOp::Compound(BinaryOp::Xor),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/**
+A recursive-descent parser for `Msh.g4`, with a method for each rule of the grammar; expressions are parsed by
+precedence climbing. Where the grammar needs more than a token of lookahead to choose an alternative, like
+between a block and a dict literal at the start of a statement, the parser tries the alternatives in the order
+ANTLR prefers them, and takes the first one that parses.
+
+After a syntax error, the parser skips to the end of the statement, so that all errors of a script are reported.
+ */
+struct Parser<'a> {
+    /// the tokens without comments, ending with `Eof`
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    errors: Vec<SyntaxError>,
+    /// the blocks the parser is in
+    blocks: usize,
+    /// set while looking ahead to find the end of a static instruction
+    in_static: bool,
+    /// whether the statement is the body of a `loop`, outside of blocks, which ends at `while`
+    loop_body: bool,
+    /// a token an expression stops before, see `for_stat`
+    stop_at: Option<usize>,
+    /// where the expression being parsed could have stopped, if requested
+    cut_points: Option<Vec<usize>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Token<'a> {
+        self.tokens[self.pos]
+    }
+
+    fn peek_kind(&self, offset: usize) -> TokenKind {
+        self.tokens.get(self.pos + offset).map_or(Eof, |token| token.kind)
+    }
+
+    fn at(&self, kind: TokenKind) -> bool {
+        self.peek().kind == kind
+    }
+
+    fn bump(&mut self) -> Token<'a> {
+        let token = self.peek();
+        if token.kind != Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, kind: TokenKind) -> Option<Token<'a>> {
+        self.at(kind).then(|| self.bump())
+    }
+
+    /// The span from a position to the end of the last token parsed.
+    fn span_from(&self, start: (isize, isize)) -> Span {
+        let end = match self.pos.checked_sub(1) {
+            Some(last) => self.tokens[last].end(),
+            None => start,
+        };
+        Span::new(start, end.max(start))
+    }
+
+    fn report(&mut self, kind: SyntaxErrorKind, msg: String) -> Failed {
+        let token = self.peek();
+        let text = match token.kind {
+            Eof => "<EOF>".to_owned(),
+            _ => token.text.to_owned(),
+        };
+        self.errors.push(SyntaxError { kind, msg, line: token.line, column: token.column, token: Some(text) });
+        Failed
+    }
+
+    fn token_display(&self) -> String {
+        match self.peek().kind {
+            Eof => "<EOF>".to_owned(),
+            _ => error_display(self.peek().text),
+        }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> Failed {
+        let msg = format!("mismatched input '{}' expecting {}", self.token_display(), expected);
+        self.report(SyntaxErrorKind::UnexpectedToken, msg)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> PResult<Token<'a>> {
+        if let Some(token) = self.eat(kind) {
+            return Ok(token);
+        }
+        // at the end of a statement or bracket, the token is missing rather than something else being in its way
+        if matches!(self.peek().kind, Eof | Nl | Semicolon | RParen | RBrack | RBrace) {
+            let msg = format!("missing {} at '{}'", kind.describe(), self.token_display());
+            return Err(self.report(SyntaxErrorKind::MissingToken, msg));
+        }
+        Err(self.unexpected(&kind.describe()))
+    }
+
+    fn ident(&mut self) -> PResult<Ident> {
+        let token = self.expect(Id)?;
+        Ok(Ident { name: token.text.to_owned(), span: token.span() })
+    }
+
+    fn at_stmt_end(&self) -> bool {
+        matches!(self.peek().kind, Semicolon | Nl | RBrace | Eof | Else | While)
+    }
+
+    /**
+    Parse a rule without committing to it: the parser is reset afterwards, and the attempt can be committed
+    with `commit` if it's the alternative to take.
+     */
+    fn attempt<T>(&mut self, rule: impl FnOnce(&mut Self) -> PResult<T>) -> Attempt<T> {
+        let (pos, errors) = (self.pos, self.errors.len());
+        let result = rule(self);
+        let attempt =
+            Attempt { result, pos: self.pos, errors: self.errors.split_off(errors), ends_stmt: self.at_stmt_end() };
+        self.pos = pos;
+        attempt
+    }
+
+    fn commit<T>(&mut self, attempt: Attempt<T>) -> PResult<T> {
+        self.pos = attempt.pos;
+        self.errors.extend(attempt.errors);
+        attempt.result
+    }
+
+    /// Of two failed attempts, the one that got further, or the first one.
+    fn commit_furthest<T>(&mut self, first: Attempt<T>, second: Attempt<T>) -> PResult<T> {
+        let reached = |attempt: &Attempt<T>| match attempt.errors.first() {
+            Some(error) => (error.line, error.column),
+            None => self.tokens[attempt.pos].pos(),
+        };
+        match reached(&second) > reached(&first) {
+            true => self.commit(second),
+            false => self.commit(first),
+        }
+    }
+
+    /// Skip to the end of the statement after a syntax error: to the next line break or `;`, or the end of the block.
+    fn recover(&mut self) {
+        let mut depth = 0_usize;
+        loop {
+            match self.peek().kind {
+                Eof => return,
+                Nl | Semicolon if depth == 0 => return,
+                RBrace if depth == 0 && self.blocks > 0 => return,
+                LBrace => depth += 1,
+                RBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    /// `file: (STATIC_EXEC execLine NL)? instructions EOF`
+    fn file(&mut self) -> File {
+        let mut exec = None;
+        if self.eat(StaticExec).is_some() {
+            let line = self.expect(FilePath).and_then(|path| {
+                exec = Some(ExecLine { path: path.text.to_owned(), span: path.span() });
+                self.expect(Nl)
+            });
+            if line.is_err() {
+                self.recover();
+            }
+        }
+        let body = self.instructions(false);
+        File { exec, body }
+    }
+
+    /// `instructions: NL* (tlstat ((SEMICOLON | SEMICOLON? NL) tlstat?)*)?`, or the rest of it after a statement.
+    fn instructions(&mut self, mut started: bool) -> Vec<Stmt> {
+        let mut body = Vec::new();
+        // whether a statement can follow, ie. at the start or after a separator
+        let mut separated = true;
+        loop {
+            match self.peek().kind {
+                Eof => break,
+                RBrace if self.blocks > 0 => break,
+                Nl => separated = true,
+                Semicolon if started => separated = true,
+                _ if !separated => {
+                    self.unexpected("';' or a line break");
+                    self.recover();
+                    continue;
+                }
+                _ => {
+                    (started, separated) = (true, false);
+                    match self.tlstat() {
+                        Ok(stmt) => body.push(stmt),
+                        Err(Failed) => self.recover(),
+                    }
+                    continue;
+                }
+            }
+            self.bump();
+        }
+        body
+    }
+
+    /// `tlstat: staticInst | argdecl | exportRunBlock | stat`
+    fn tlstat(&mut self) -> PResult<Stmt> {
+        let start = self.peek().pos();
+        let kind = match self.peek().kind {
+            StaticInst => {
+                self.bump();
+                let name = self.ident()?;
+                let mut args = Vec::new();
+                loop {
+                    match self.peek().kind {
+                        Nl | Eof => break,
+                        RBrace if self.ends_static() => break,
+                        Semicolon if self.separates_static() => break,
+                        _ => args.push(self.bump().text.to_owned()),
+                    }
+                }
+                StmtKind::Static { name, args }
+            }
+            Arg => {
+                self.bump();
+                let name = self.ident()?;
+                let ty = self.type_annotation()?;
+                let default = match self.eat(Eq) {
+                    Some(_) => Some(self.expr()?),
+                    None => None,
+                };
+                StmtKind::Arg { name, ty, default }
+            }
+            Run => {
+                self.bump();
+                StmtKind::Run(self.block()?)
+            }
+            // `export {a}` exports a block, but `export {a, b}` declares variables
+            Export if self.peek_kind(1) == LBrace => {
+                let block = self.attempt(|p| {
+                    p.bump();
+                    p.block()
+                });
+                if block.ok() {
+                    StmtKind::Export(self.commit(block)?)
+                } else {
+                    let decl = self.attempt(|p| p.stat());
+                    if decl.ok() {
+                        return self.commit(decl);
+                    }
+                    self.commit_furthest(block.map(StmtKind::Export), decl.map(|stmt| stmt.kind))?
+                }
+            }
+            _ => return self.stat(),
+        };
+        Ok(Stmt { kind, span: self.span_from(start) })
+    }
+
+    /**
+    Whether a `}` in the arguments of a static instruction closes the block it's in: like ANTLR, the arguments
+    only stop before it if the rest of the script doesn't close the block, and the script doesn't parse when
+    the rest of the line is taken as arguments.
+     */
+    fn ends_static(&mut self) -> bool {
+        if !self.closes_block() {
+            return false;
+        }
+        // when looking ahead from another static instruction, just check the braces
+        if self.in_static {
+            return true;
+        }
+        self.in_static = true;
+        let arguments = self.attempt(|p| {
+            p.bump();
+            p.rest_as_arguments()
+        });
+        self.in_static = false;
+        !Self::parsed(arguments)
+    }
+
+    /// Whether a `}` could close the block it's in, given the closing braces after it.
+    fn closes_block(&self) -> bool {
+        self.unmatched_braces(self.pos + 1) < self.blocks
+    }
+
+    /// The closing braces from a token on that aren't matched by an opening one, ie. the blocks they can close.
+    fn unmatched_braces(&self, from: usize) -> usize {
+        let (mut open, mut unmatched) = (0, 0);
+        for token in &self.tokens[from..] {
+            match token.kind {
+                LBrace => open += 1,
+                RBrace if open > 0 => open -= 1,
+                RBrace => unmatched += 1,
+                _ => {}
+            }
+        }
+        unmatched
+    }
+
+    /**
+    Whether a `;` in the arguments of a static instruction separates it from the next statement: like ANTLR, only
+    if the rest of the script doesn't parse when the rest of the line is taken as arguments, but does otherwise.
+     */
+    fn separates_static(&mut self) -> bool {
+        // the statements after this one may have static instructions of their own, which just take the whole line
+        if self.in_static {
+            return false;
+        }
+        self.in_static = true;
+        let separated = self.attempt(|p| {
+            p.bump();
+            p.rest_parses()
+        });
+        let arguments = self.attempt(|p| p.rest_as_arguments());
+        self.in_static = false;
+        !Self::parsed(arguments) && Self::parsed(separated)
+    }
+
+    /// Take the rest of the line as arguments of a static instruction, then parse the statements after it.
+    fn rest_as_arguments(&mut self) -> PResult<bool> {
+        loop {
+            match self.peek().kind {
+                Nl | Eof => break,
+                RBrace if self.closes_block() => break,
+                _ => self.bump(),
+            };
+        }
+        self.rest_parses()
+    }
+
+    /// Parse the statements that follow, returning whether the blocks the parser is in are closed after them.
+    fn rest_parses(&mut self) -> PResult<bool> {
+        let balanced = self.unmatched_braces(self.pos) == self.blocks;
+        self.instructions(true);
+        Ok(balanced)
+    }
+
+    /// Whether the statements after a static instruction parsed, see `rest_parses`.
+    fn parsed(attempt: Attempt<bool>) -> bool {
+        attempt.errors.is_empty() && attempt.result.unwrap_or(false)
+    }
+
+    /// `block: LBRACE instructions RBRACE`
+    fn block(&mut self) -> PResult<Block> {
+        let start = self.expect(LBrace)?.pos();
+        let loop_body = std::mem::replace(&mut self.loop_body, false);
+        self.blocks += 1;
+        let body = self.instructions(false);
+        self.blocks -= 1;
+        self.loop_body = loop_body;
+        self.expect(RBrace)?;
+        Ok(Block { body, span: self.span_from(start) })
+    }
+
+    /// `stat`: a block, declaration, assignment, expression, `break`, `continue`, `if` or loop.
+    fn stat(&mut self) -> PResult<Stmt> {
+        let start = self.peek().pos();
+        let kind = match self.peek().kind {
+            // `{a}` is a block, but `{a, b}` or `{a} = x` is a dict
+            LBrace => {
+                let block = self.attempt(|p| p.block().map(StmtKind::Block));
+                if block.ok() {
+                    self.commit(block)?
+                } else {
+                    let expr = self.attempt(|p| p.expr_stat());
+                    match expr.ok() {
+                        true => self.commit(expr)?,
+                        false => self.commit_furthest(block, expr)?,
+                    }
+                }
+            }
+            Func => StmtKind::Func(self.funcdef()?),
+            Export if self.peek_kind(1) == Func => StmtKind::Func(self.funcdef()?),
+            Export | Local | Const => StmtKind::Var(self.vardecl()?),
+            Break => {
+                self.bump();
+                StmtKind::Break
+            }
+            Continue => {
+                self.bump();
+                StmtKind::Continue
+            }
+            If => {
+                self.bump();
+                let cond = self.expr()?;
+                self.eat(Nl);
+                self.expect(Then)?;
+                let then = Box::new(self.stat()?);
+                // the line break before `else`; without an `else`, the statement takes it if nothing else needs it
+                let otherwise = match (self.peek().kind, self.peek_kind(1)) {
+                    (Nl, Else) | (Else, _) => {
+                        self.eat(Nl);
+                        self.bump();
+                        Some(Box::new(self.stat()?))
+                    }
+                    (Nl, next) if matches!(next, Eof | Nl | Semicolon | RBrace) || next == While && self.loop_body => {
+                        self.bump();
+                        None
+                    }
+                    _ => None,
+                };
+                StmtKind::If { cond, then, otherwise }
+            }
+            Loop => {
+                self.bump();
+                let loop_body = std::mem::replace(&mut self.loop_body, true);
+                let body = self.stat();
+                self.loop_body = loop_body;
+                let body = Box::new(body?);
+                self.expect(While)?;
+                StmtKind::DoWhile { body, cond: self.expr()? }
+            }
+            While => {
+                self.bump();
+                let cond = self.expr()?;
+                self.expect(Loop)?;
+                StmtKind::While { cond, body: Box::new(self.stat()?) }
+            }
+            For => self.for_stat()?,
+            _ => self.expr_stat()?,
+        };
+        Ok(Stmt { kind, span: self.span_from(start) })
+    }
+
+    /**
+    `FOR pattern IN expr stat`. The iterable is parsed as far as it goes, unless the statement doesn't parse then;
+    like in ANTLR, `for x in f (x)` has the body `(x)`, since `f(x)` would leave no body.
+     */
+    fn for_stat(&mut self) -> PResult<StmtKind> {
+        self.bump();
+        let pattern = self.pattern()?;
+        self.expect(In)?;
+        let start = self.pos;
+        let mut cut_points = Vec::new();
+        let mut parsed = self.attempt(|p| {
+            p.cut_points = Some(Vec::new());
+            let iter = p.expr();
+            cut_points = p.cut_points.take().unwrap_or_default();
+            Ok((iter?, p.stat()?))
+        });
+        // the latest place the iterable can stop for the statement to parse
+        for &cut in cut_points.iter().rev() {
+            if parsed.ok() {
+                break;
+            }
+            let shorter = self.attempt(|p| {
+                p.pos = start;
+                p.stop_at = Some(cut);
+                let iter = p.expr();
+                p.stop_at = None;
+                if p.pos != cut {
+                    return Err(Failed);
+                }
+                Ok((iter?, p.stat()?))
+            });
+            if shorter.ok() {
+                parsed = shorter;
+            }
+        }
+        let (iter, body) = self.commit(parsed)?;
+        Ok(StmtKind::For { pattern, iter, body: Box::new(body) })
+    }
+
+    /**
+    `expr | assignment`. An `ID EQ` after `import` is always its alias, so `import a = ./b` as a statement imports
+    `./b` as `a`, rather than assigning `./b` to `import a`.
+     */
+    fn expr_stat(&mut self) -> PResult<StmtKind> {
+        let target = self.expr()?;
+        if let Some(op) = assign_op(self.peek().kind) {
+            self.bump();
+            let value = self.expr()?;
+            return Ok(StmtKind::Assign { target, op, value });
+        }
+        Ok(StmtKind::Expr(target))
+    }
+
+    /// `funcdef: EXPORT? FUNC ID LPAREN funcFormalArgs? RPAREN (RARROW typedef) block`
+    fn funcdef(&mut self) -> PResult<FuncDef> {
+        let export = self.eat(Export).is_some();
+        self.expect(Func)?;
+        let name = self.ident()?;
+        self.expect(LParen)?;
+        let (params, _) = self.list(RParen, |p| {
+            let start = p.peek().pos();
+            let name = p.ident()?;
+            let ty = p.type_annotation()?;
+            let default = match p.eat(Eq) {
+                Some(_) => Some(p.expr()?),
+                None => None,
+            };
+            Ok(Param { name, ty, default, span: p.span_from(start) })
+        })?;
+        self.expect(RArrow)?;
+        let returns = self.typedef()?;
+        Ok(FuncDef { export, name, params, returns, body: self.block()? })
+    }
+
+    /// `vardecl: (EXPORT? (LOCAL|CONST) | EXPORT) pattern (COLON typedef)? ((EQ | LEQ) expr)?`
+    fn vardecl(&mut self) -> PResult<VarDecl> {
+        let export = self.eat(Export).is_some();
+        let constant = match self.peek().kind {
+            Const => true,
+            Local => false,
+            _ if export => false,
+            _ => return Err(self.unexpected("'local' or 'const'")),
+        };
+        self.eat(Const).or_else(|| self.eat(Local));
+        let pattern = self.pattern()?;
+        let ty = self.type_annotation()?;
+        let clone = self.at(Leq);
+        let value = match self.eat(Eq).or_else(|| self.eat(Leq)) {
+            Some(_) => Some(self.expr()?),
+            None => None,
+        };
+        Ok(VarDecl { export, constant, pattern, ty, value, clone })
+    }
+
+    /// `(COLON typedef)?`
+    fn type_annotation(&mut self) -> PResult<Option<TypeExpr>> {
+        match self.eat(Colon) {
+            Some(_) => Ok(Some(self.typedef()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// `typedef: ID`
+    fn typedef(&mut self) -> PResult<TypeExpr> {
+        Ok(TypeExpr::Name(self.ident()?))
+    }
+
+    /// `(item (COMMA item)* COMMA?)? close`, after the opening bracket. Also returns whether there was a comma.
+    fn list<T>(&mut self, close: TokenKind, mut item: impl FnMut(&mut Self) -> PResult<T>) -> PResult<(Vec<T>, bool)> {
+        let mut items = Vec::new();
+        let mut comma = false;
+        while !self.at(close) {
+            items.push(item(self)?);
+            if self.eat(Comma).is_none() {
+                break;
+            }
+            comma = true;
+        }
+        self.expect(close)?;
+        Ok((items, comma))
+    }
+
+    /// `pattern: ID | LPAREN patternEntry... RPAREN | LBRACK ... RBRACK | LBRACE dictPatternEntry... RBRACE`
+    fn pattern(&mut self) -> PResult<Pattern> {
+        let start = self.peek().pos();
+        // `patternEntry: STAR ID | pattern`
+        let entry = |p: &mut Self| match p.eat(Star) {
+            Some(_) => Ok(PatternEntry::Rest(p.ident()?)),
+            None => Ok(PatternEntry::Pattern(p.pattern()?)),
+        };
+        if !matches!(self.peek().kind, Id | LParen | LBrack | LBrace) {
+            return Err(self.unexpected("a name, '(', '[' or '{'"));
+        }
+        let token = self.bump();
+        let kind = match token.kind {
+            Id => PatternKind::Name(Ident { name: token.text.to_owned(), span: token.span() }),
+            LParen => match self.list(RParen, entry)? {
+                // like in tuple literals, `(a)` is just `a`
+                (mut entries, false) if matches!(entries[..], [PatternEntry::Pattern(_)]) => match entries.pop() {
+                    Some(PatternEntry::Pattern(inner)) => return Ok(inner),
+                    _ => unreachable!(),
+                },
+                (entries, _) => PatternKind::Tuple(entries),
+            },
+            LBrack => PatternKind::List(self.list(RBrack, entry)?.0),
+            LBrace => {
+                // `dictPatternEntry: ID | key=expr COLON pattern`
+                let (entries, _) = self.list(RBrace, |p| {
+                    if p.at(Id) && matches!(p.peek_kind(1), Comma | RBrace) {
+                        return Ok(DictPatternEntry::Shorthand(p.ident()?));
+                    }
+                    let key = p.expr()?;
+                    p.expect(Colon)?;
+                    Ok(DictPatternEntry::Pair { key, pattern: p.pattern()? })
+                })?;
+                PatternKind::Dict(entries)
+            }
+            _ => unreachable!(),
+        };
+        Ok(Pattern { kind, span: self.span_from(start) })
+    }
+
+    /// `listEntry: STAR expr | expr`
+    fn list_entry(&mut self) -> PResult<ListEntry> {
+        match self.eat(Star) {
+            Some(_) => Ok(ListEntry::Spread(self.expr()?)),
+            None => Ok(ListEntry::Item(self.expr()?)),
+        }
+    }
+
+    /// `dictEntry: TWOSTAR expr | ID | key=expr COLON value=expr`
+    fn dict_entry(&mut self) -> PResult<DictEntry> {
+        if self.eat(TwoStar).is_some() {
+            return Ok(DictEntry::Spread(self.expr()?));
+        }
+        if self.at(Id) && matches!(self.peek_kind(1), Comma | RBrace) {
+            return Ok(DictEntry::Shorthand(self.ident()?));
+        }
+        let key = self.expr()?;
+        self.expect(Colon)?;
+        Ok(DictEntry::Pair { key, value: self.expr()? })
+    }
+
+    /**
+    `importStmt: IMPORT (ID EQ)? importSource | IMPORT (STAR | importSelector (COMMA importSelector)*) FROM ...`;
+    the second form is recognized by the `from` after the selectors.
+     */
+    fn import(&mut self) -> PResult<ast::Import> {
+        self.expect(Import)?;
+        let kind = if self.selectors_ahead() {
+            let selectors = match (self.peek().kind, self.peek_kind(1)) {
+                (Star, From) => {
+                    self.bump();
+                    vec![ImportSelector::All]
+                }
+                _ => {
+                    let mut selectors = Vec::new();
+                    loop {
+                        // `importSelector: STAR | (ID EQ)? ID`
+                        selectors.push(match self.eat(Star) {
+                            Some(_) => ImportSelector::All,
+                            None => {
+                                let first = self.ident()?;
+                                match self.eat(Eq) {
+                                    Some(_) => ImportSelector::Name { alias: Some(first), name: self.ident()? },
+                                    None => ImportSelector::Name { alias: None, name: first },
+                                }
+                            }
+                        });
+                        if self.eat(Comma).is_none() {
+                            break;
+                        }
+                    }
+                    selectors
+                }
+            };
+            self.expect(From)?;
+            ImportKind::From(selectors)
+        } else if self.at(Id) && self.peek_kind(1) == Eq {
+            let alias = self.ident()?;
+            self.bump();
+            ImportKind::Module(Some(alias))
+        } else {
+            ImportKind::Module(None)
+        };
+        Ok(ast::Import { kind, source: self.expr()? })
+    }
+
+    /// Whether the next tokens are the selectors of an import, followed by `from`.
+    fn selectors_ahead(&self) -> bool {
+        let mut i = 0;
+        loop {
+            match (self.peek_kind(i), self.peek_kind(i + 1), self.peek_kind(i + 2)) {
+                (Id, Eq, Id) => i += 3,
+                (Id | Star, ..) => i += 1,
+                _ => return false,
+            }
+            match self.peek_kind(i) {
+                Comma => i += 1,
+                From => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    /// `expr`
+    fn expr(&mut self) -> PResult<Expr> {
+        self.operators(Precedence::Cast)
+    }
+
+    /// `expr`, with the operators binding at least as tight as `min`.
+    fn operators(&mut self, min: Precedence) -> PResult<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            let kind = self.peek().kind;
+            if self.stop_at == Some(self.pos) {
+                break;
+            }
+            let precedence = match (binary_op(kind), postfix_precedence(kind)) {
+                (Some((_, precedence)), _) | (None, Some(precedence)) => precedence,
+                (None, None) => break,
+            };
+            if precedence < min {
+                break;
+            }
+            if let Some(cut_points) = &mut self.cut_points {
+                cut_points.push(self.pos);
+            }
+            let start = expr.span.start;
+            let target = Box::new(expr);
+            let kind = match self.bump().kind {
+                LBrack => self.index(target)?,
+                LParen => self.call(target)?,
+                Inc => ExprKind::Update { op: UpdateOp::Inc, postfix: true, target },
+                Dec => ExprKind::Update { op: UpdateOp::Dec, postfix: true, target },
+                Dot => ExprKind::Dot { target, field: self.ident()? },
+                As => ExprKind::Cast { value: target, ty: self.typedef()? },
+                kind => match binary_op(kind) {
+                    Some((op, precedence)) => {
+                        let min = match precedence {
+                            Precedence::Power => precedence,
+                            _ => precedence.tighter(),
+                        };
+                        ExprKind::Binary { op, lhs: target, rhs: Box::new(self.operators(min)?) }
+                    }
+                    None => unreachable!(),
+                },
+            };
+            expr = Expr { kind, span: self.span_from(start) };
+        }
+        Ok(expr)
+    }
+
+    /// `expr LBRACK index=expr RBRACK | expr LBRACK start=expr? COLON stop=expr? (COLON step=expr?)? RBRACK`
+    fn index(&mut self, target: Box<Expr>) -> PResult<ExprKind> {
+        let bound = |p: &mut Self| match p.peek().kind {
+            Colon | RBrack => Ok(None),
+            _ => Ok(Some(Box::new(p.expr()?))),
+        };
+        let start = bound(self)?;
+        let kind = match (self.eat(Colon), start) {
+            (Some(_), start) => {
+                let stop = bound(self)?;
+                let step = match self.eat(Colon) {
+                    Some(_) => bound(self)?,
+                    None => None,
+                };
+                ExprKind::Slice { target, start, stop, step }
+            }
+            (None, Some(index)) => ExprKind::Index { target, index },
+            (None, None) => return Err(self.unexpected("an expression")),
+        };
+        self.expect(RBrack)?;
+        Ok(kind)
+    }
+
+    /// `expr LPAREN funcArgs? RPAREN`, where `funcArgs` are positional arguments followed by keyword arguments.
+    fn call(&mut self, callee: Box<Expr>) -> PResult<ExprKind> {
+        let mut args = Vec::new();
+        let mut kwargs = Vec::new();
+        self.list(RParen, |p| {
+            if p.at(Id) && p.peek_kind(1) == Eq {
+                let name = p.ident()?;
+                p.bump();
+                kwargs.push(KwArg { name, value: p.expr()? });
+            } else if kwargs.is_empty() {
+                args.push(p.expr()?);
+            } else {
+                return Err(p.unexpected("a keyword argument"));
+            }
+            Ok(())
+        })?;
+        Ok(ExprKind::Call { callee, args, kwargs })
+    }
+
+    /// The expressions that don't start with another expression: literals, names, brackets and prefix operators.
+    fn primary(&mut self) -> PResult<Expr> {
+        let token = self.peek();
+        if let Some(kind) = literal(token) {
+            self.bump();
+            return Ok(Expr { kind, span: token.span() });
+        }
+        let kind = match token.kind {
+            LBrack => {
+                self.bump();
+                ExprKind::List(self.list(RBrack, Self::list_entry)?.0)
+            }
+            LBrace => {
+                self.bump();
+                ExprKind::Dict(self.list(RBrace, Self::dict_entry)?.0)
+            }
+            // a tuple needs at least one comma to be distinguished from brackets: `()`, `(a,)`, `(a, b)`
+            LParen => {
+                self.bump();
+                match self.list(RParen, Self::list_entry)? {
+                    (mut entries, false) if matches!(entries[..], [ListEntry::Item(_)]) => match entries.pop() {
+                        Some(ListEntry::Item(inner)) => ExprKind::Paren(Box::new(inner)),
+                        _ => unreachable!(),
+                    },
+                    (entries, false) if !entries.is_empty() => {
+                        self.pos -= 1;
+                        return Err(self.unexpected("','"));
+                    }
+                    (entries, _) => ExprKind::Tuple(entries),
+                }
+            }
+            Import => ExprKind::Import(Box::new(self.import()?)),
+            Global | Unloc => {
+                self.bump();
+                let name = self.ident()?;
+                match token.kind {
+                    Global => ExprKind::Global(name),
+                    _ => ExprKind::Unloc(name),
+                }
+            }
+            Not | BitNot => {
+                self.bump();
+                let op = match token.kind {
+                    Not => UnaryOp::Not,
+                    _ => UnaryOp::BitNot,
+                };
+                let operand = Box::new(self.operators(Precedence::Unary)?);
+                ExprKind::Unary { op, operand }
+            }
+            Inc | Dec => {
+                self.bump();
+                let op = match token.kind {
+                    Inc => UpdateOp::Inc,
+                    _ => UpdateOp::Dec,
+                };
+                let target = Box::new(self.operators(Precedence::Unary)?);
+                ExprKind::Update { op, postfix: false, target }
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        Ok(Expr { kind, span: self.span_from(token.pos()) })
+    }
+}
+
+/// Parse a script with the recursive-descent parser, returning what it could parse and the syntax errors.
+pub(super) fn parse(source: &str) -> (File, Vec<SyntaxError>) {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.by_ref().filter(|token| !token.is_comment()).collect();
+    let mut errors = lexer.into_errors();
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        errors: Vec::new(),
+        blocks: 0,
+        in_static: false,
+        loop_body: false,
+        stop_at: None,
+        cut_points: None,
+    };
+    let file = parser.file();
+    errors.extend(parser.errors);
+    errors.sort_by_key(|error| (error.line, error.column));
+    (file, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stmts(source: &str) -> Vec<StmtKind> {
+        let (file, errors) = parse(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        file.body.into_iter().map(|stmt| stmt.kind).collect()
+    }
+
+    #[test]
+    fn blocks_and_dicts() {
+        assert!(matches!(&stmts("{a}\n")[..], [StmtKind::Block(_)]));
+        assert!(matches!(&stmts("{a, b}\n")[..], [StmtKind::Expr(Expr { kind: ExprKind::Dict(_), .. })]));
+        assert!(matches!(&stmts("{a} = x\n")[..], [StmtKind::Assign { .. }]));
+        assert!(matches!(&stmts("export {a}\n")[..], [StmtKind::Export(_)]));
+        assert!(matches!(&stmts("export {a, b}\n")[..], [StmtKind::Var(VarDecl { export: true, value: None, .. })]));
+    }
+
+    #[test]
+    fn import_aliases() {
+        let [StmtKind::Expr(expr)] = &stmts("import a = ./b\n")[..] else { panic!() };
+        let ExprKind::Import(import) = &expr.kind else { panic!("{:?}", expr) };
+        assert!(matches!(&import.kind, ImportKind::Module(Some(alias)) if alias.name == "a"));
+
+        let [StmtKind::Assign { value, .. }] = &stmts("x = import a = ./b\n")[..] else { panic!() };
+        let ExprKind::Import(import) = &value.kind else { panic!("{:?}", value) };
+        assert!(matches!(&import.kind, ImportKind::Module(Some(alias)) if alias.name == "a"));
+    }
+
+    #[test]
+    fn static_arguments() {
+        let args = |source: &str| {
+            let [StmtKind::Block(block)] = &stmts(source)[..] else { panic!() };
+            let StmtKind::Static { args, .. } = &block.body[0].kind else { panic!("{:?}", block) };
+            args.join(" ")
+        };
+        assert_eq!(args("{ #!x a }\n"), "a");
+        assert_eq!(args("{ #!x a } b }\n"), "a } b");
+        assert_eq!(args("{ #!x a }; b }\n"), "a } ; b");
+        // the second `}` could close the block, but then the rest of the line wouldn't parse
+        assert_eq!(args("{\n#!x a } b }; f(, k = 1) {\n}\n"), "a } b } ; f ( , k = 1 ) {");
+    }
+
+    #[test]
+    fn for_bodies() {
+        let [StmtKind::For { iter, body, .. }] = &stmts("for x in f (a)\n")[..] else { panic!() };
+        assert_eq!(iter.kind, ExprKind::Ident("f".to_owned()));
+        assert!(matches!(body.kind, StmtKind::Expr(Expr { kind: ExprKind::Paren(_), .. })));
+
+        let [StmtKind::For { iter, .. }] = &stmts("for x in f(a) {}\n")[..] else { panic!() };
+        assert!(matches!(iter.kind, ExprKind::Call { .. }));
+    }
+
+    #[test]
+    fn line_breaks_around_else() {
+        let [StmtKind::If { otherwise: Some(_), .. }] = &stmts("if a\nthen b\nelse c\n")[..] else { panic!() };
+        assert_eq!(stmts("if a then b\nc\n").len(), 2);
+        assert!(!parse("if a then b\n\nelse c\n").1.is_empty());
+    }
+
+    /// The binary operators from the loosest binding to the tightest, a level per line. `/` is left out: on its own,
+    /// it is a path.
+    const BINARY: &[&[(&str, &str)]] = &[
+        &[("||", "Or")],
+        &[("&&", "And")],
+        &[("==", "Eq"), ("!=", "Neq"), ("in", "In")],
+        &[("|", "BitOr")],
+        &[("^", "Xor")],
+        &[("&", "BitAnd")],
+        &[("+", "Plus"), ("-", "Minus")],
+        &[("*", "Mul"), ("%", "Mod"), ("@", "AtOperator")],
+        &[("**", "Pow")],
+    ];
+
+    const PREFIX: &[(&str, &str)] = &[("!", "Not"), ("!!", "BitNot"), ("++", "Inc"), ("--", "Dec")];
+
+    /// The postfix operators applied to `b`, and how they are rendered.
+    const POSTFIX: &[(&str, &str)] =
+        &[("b[x]", "b[x]"), ("b[x:]", "b[x:]"), ("b(x)", "b(x)"), ("b.x", "b.x"), ("b++", "b Inc"), ("b--", "b Dec")];
+
+    /// Render an expression with every operation in brackets.
+    fn render(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Ident(name) => name.clone(),
+            ExprKind::Binary { op, lhs, rhs } => format!("({} {:?} {})", render(lhs), op, render(rhs)),
+            ExprKind::Unary { op, operand } => format!("({:?} {})", op, render(operand)),
+            ExprKind::Update { op, postfix: false, target } => format!("({:?} {})", op, render(target)),
+            ExprKind::Update { op, postfix: true, target } => format!("({} {:?})", render(target), op),
+            ExprKind::Index { target, index } => format!("({}[{}])", render(target), render(index)),
+            ExprKind::Slice { target, start: Some(start), stop: None, step: None } => {
+                format!("({}[{}:])", render(target), render(start))
+            }
+            ExprKind::Call { callee, args, .. } => format!("({}({}))", render(callee), render(&args[0])),
+            ExprKind::Dot { target, field } => format!("({}.{})", render(target), field.name),
+            ExprKind::Cast { value, ty: TypeExpr::Name(ty) } => format!("({} as {})", render(value), ty.name),
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    fn parsed(source: &str) -> String {
+        match &stmts(&format!("{}\n", source))[..] {
+            [StmtKind::Expr(expr)] => render(expr),
+            stmts => panic!("{:?}", stmts),
+        }
+    }
+
+    #[test]
+    fn binary_operator_pairs() {
+        let ops = || BINARY.iter().enumerate().flat_map(|(level, ops)| ops.iter().map(move |op| (level, op)));
+        for (left_level, (left, left_name)) in ops() {
+            for (right_level, (right, right_name)) in ops() {
+                let source = format!("a {} b {} c", left, right);
+                // only `**` is right-associative
+                let expected = if left_level > right_level || left_level == right_level && *left != "**" {
+                    format!("((a {} b) {} c)", left_name, right_name)
+                } else {
+                    format!("(a {} (b {} c))", left_name, right_name)
+                };
+                assert_eq!(parsed(&source), expected, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn prefix_operators() {
+        for (prefix, name) in PREFIX {
+            for (op, op_name) in BINARY.iter().copied().flatten() {
+                // `**` binds tighter than the prefix operators, the other binary operators don't
+                let expected = match *op {
+                    "**" => format!("({} (a Pow b))", name),
+                    _ => format!("(({} a) {} b)", name, op_name),
+                };
+                assert_eq!(parsed(&format!("{}a {} b", prefix, op)), expected);
+                assert_eq!(parsed(&format!("a {} {}b", op, prefix)), format!("(a {} ({} b))", op_name, name));
+            }
+            for (inner, inner_name) in PREFIX {
+                assert_eq!(parsed(&format!("{} {}a", prefix, inner)), format!("({} ({} a))", name, inner_name));
+            }
+        }
+    }
+
+    #[test]
+    fn postfix_operators() {
+        for (postfix, rendered) in POSTFIX {
+            for (op, op_name) in BINARY.iter().copied().flatten() {
+                assert_eq!(parsed(&format!("a {} {}", op, postfix)), format!("(a {} ({}))", op_name, rendered));
+                let swapped = postfix.replacen('b', "a", 1);
+                let expected = format!("(({}) {} b)", rendered.replacen('b', "a", 1), op_name);
+                assert_eq!(parsed(&format!("{} {} b", swapped, op)), expected);
+            }
+            for (prefix, name) in PREFIX {
+                assert_eq!(parsed(&format!("{}{}", prefix, postfix)), format!("({} ({}))", name, rendered));
+            }
+        }
+        assert_eq!(parsed("a.b[c](d).e++"), "(((((a.b)[c])(d)).e) Inc)");
+    }
+
+    #[test]
+    fn casts() {
+        for (op, op_name) in BINARY.iter().copied().flatten() {
+            assert_eq!(parsed(&format!("a {} b as t", op)), format!("((a {} b) as t)", op_name));
+            assert_eq!(parsed(&format!("a as t {} b", op)), format!("((a as t) {} b)", op_name));
+        }
+        for (prefix, name) in PREFIX {
+            assert_eq!(parsed(&format!("{}a as t", prefix)), format!("(({} a) as t)", name));
+        }
+        for (postfix, rendered) in POSTFIX {
+            assert_eq!(parsed(&format!("{} as t", postfix)), format!("(({}) as t)", rendered));
+        }
+        assert_eq!(parsed("a as t as u"), "((a as t) as u)");
+    }
+}
@@ -0,0 +1,803 @@
+use std::collections::BTreeSet;
+
+use super::{ast::Span, SyntaxError, SyntaxErrorKind};
+
+/// The kinds of tokens; they are the tokens of `Msh.g4`, without the ones the lexer skips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    True,
+    False,
+    Local,
+    Global,
+    Unloc,
+    Func,
+    Import,
+    From,
+    As,
+    Arg,
+    Run,
+    Export,
+    Const,
+    If,
+    Then,
+    Else,
+    Loop,
+    While,
+    Break,
+    Continue,
+    For,
+    In,
+    Id,
+    /// a string without interpolation, like `'a\n'`
+    Literal,
+    /// a string with `$name` or `{expr}` interpolations, which the parser doesn't accept yet
+    DolString,
+    FilePath,
+    DecInt,
+    HexInt,
+    BinInt,
+    DecFloat,
+    HexFloat,
+    BinFloat,
+    And,
+    BitAnd,
+    Or,
+    BitOr,
+    Not,
+    BitNot,
+    Xor,
+    Plus,
+    Minus,
+    Star,
+    TwoStar,
+    Slash,
+    Mod,
+    AtOp,
+    BitAndEq,
+    BitOrEq,
+    XorEq,
+    PlusEq,
+    MinusEq,
+    MulEq,
+    PowEq,
+    DivEq,
+    ModEq,
+    AtOpEq,
+    Inc,
+    Dec,
+    Dot,
+    Comma,
+    Colon,
+    Semicolon,
+    Eq,
+    EqEq,
+    Neq,
+    Gt,
+    Geq,
+    Lt,
+    Leq,
+    RArrow,
+    StaticInst,
+    StaticExec,
+    DocBComment,
+    /// a line doc comment, which includes its line break
+    DocComment,
+    BComment,
+    Comment,
+    LParen,
+    RParen,
+    LBrack,
+    RBrack,
+    LBrace,
+    RBrace,
+    /// a line break outside of parentheses and brackets, which ends a statement
+    Nl,
+    Eof,
+}
+
+use TokenKind::*;
+
+const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("true", True),
+    ("false", False),
+    ("local", Local),
+    ("global", Global),
+    ("unloc", Unloc),
+    ("func", Func),
+    ("import", Import),
+    ("from", From),
+    ("as", As),
+    ("arg", Arg),
+    ("run", Run),
+    ("export", Export),
+    ("const", Const),
+    ("if", If),
+    ("then", Then),
+    ("else", Else),
+    ("loop", Loop),
+    ("while", While),
+    ("break", Break),
+    ("continue", Continue),
+    ("for", For),
+    ("in", In),
+];
+
+/// The tokens with a fixed text, in the order of the grammar, which decides between matches of the same length.
+const OPERATORS: &[(&str, TokenKind)] = &[
+    ("&&", And),
+    ("&", BitAnd),
+    ("||", Or),
+    ("|", BitOr),
+    ("!", Not),
+    ("!!", BitNot),
+    ("^", Xor),
+    ("+", Plus),
+    ("-", Minus),
+    ("*", Star),
+    ("**", TwoStar),
+    ("/", Slash),
+    ("%", Mod),
+    ("@", AtOp),
+    ("&=", BitAndEq),
+    ("|=", BitOrEq),
+    ("^=", XorEq),
+    ("+=", PlusEq),
+    ("-=", MinusEq),
+    ("*=", MulEq),
+    ("**=", PowEq),
+    ("/=", DivEq),
+    ("%=", ModEq),
+    ("@=", AtOpEq),
+    ("++", Inc),
+    ("--", Dec),
+    (".", Dot),
+    (",", Comma),
+    (":", Colon),
+    (";", Semicolon),
+    ("=", Eq),
+    ("==", EqEq),
+    ("!=", Neq),
+    (">", Gt),
+    (">=", Geq),
+    ("<", Lt),
+    ("<=", Leq),
+    ("->", RArrow),
+    ("#!", StaticInst),
+    ("#!exec", StaticExec),
+];
+
+const BRACKETS: &[(&str, TokenKind)] =
+    &[("(", LParen), (")", RParen), ("[", LBrack), ("]", RBrack), ("{", LBrace), ("}", RBrace)];
+
+impl TokenKind {
+    /// How the token is shown in error messages, like `')'` or `an identifier`.
+    pub fn describe(self) -> String {
+        let fixed = KEYWORDS.iter().chain(OPERATORS).chain(BRACKETS).find(|(_, kind)| *kind == self);
+        if let Some((text, _)) = fixed {
+            return format!("'{}'", text);
+        }
+        match self {
+            Id => "an identifier",
+            Literal | DolString => "a string",
+            FilePath => "a path",
+            DecInt | HexInt | BinInt | DecFloat | HexFloat | BinFloat => "a number",
+            DocBComment | DocComment | BComment | Comment => "a comment",
+            Nl => "a line break",
+            Eof => "the end of the file",
+            _ => unreachable!("{:?} has a fixed text", self),
+        }
+        .to_owned()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// counting from 1
+    pub line: isize,
+    /// counting characters from 0
+    pub column: isize,
+}
+
+impl Token<'_> {
+    pub fn pos(&self) -> (isize, isize) {
+        (self.line, self.column)
+    }
+
+    /// The position right after the token; strings and block comments can span several lines.
+    pub fn end(&self) -> (isize, isize) {
+        match self.text.rfind('\n') {
+            Some(i) => {
+                (self.line + self.text.matches('\n').count() as isize, self.text[i + 1..].chars().count() as isize)
+            }
+            None => (self.line, self.column + self.text.chars().count() as isize),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.pos(), self.end())
+    }
+
+    /// Comments aren't part of the syntax, but doc comments and the formatter need them.
+    pub fn is_comment(&self) -> bool {
+        matches!(self.kind, DocBComment | DocComment | BComment | Comment)
+    }
+}
+
+/// What a lexer rule matched: a token, or something to skip like whitespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    Token(TokenKind),
+    Skip,
+    /// a line break inside of parentheses or brackets, which is skipped
+    IgnoreNewline,
+}
+
+fn is_id_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c == b'$'
+}
+
+fn is_id_continue(c: u8) -> bool {
+    is_id_start(c) || c.is_ascii_digit()
+}
+
+fn is_path_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"_-+?*\"~%.".contains(&c)
+}
+
+fn is_hex(c: u8) -> bool {
+    c.is_ascii_hexdigit()
+}
+
+fn is_dec(c: u8) -> bool {
+    c.is_ascii_digit()
+}
+
+fn is_bin(c: u8) -> bool {
+    c == b'0' || c == b'1'
+}
+
+/// The length of the (UTF-8 encoded) character starting with a byte.
+fn char_len(first: u8) -> usize {
+    match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+/// The end of `D ('_'? D)*` starting at `i`, if there is a first digit.
+fn digits(s: &[u8], i: usize, digit: fn(u8) -> bool) -> Option<usize> {
+    if !s.get(i).is_some_and(|&c| digit(c)) {
+        return None;
+    }
+    let mut end = i + 1;
+    loop {
+        match s.get(end..end + 2) {
+            Some([b'_', c]) if digit(*c) => end += 2,
+            _ if s.get(end).is_some_and(|&c| digit(c)) => end += 1,
+            _ => return Some(end),
+        }
+    }
+}
+
+fn sign(s: &[u8], i: usize) -> usize {
+    match s.get(i) {
+        Some(b'+' | b'-') => i + 1,
+        _ => i,
+    }
+}
+
+fn dec_int(s: &[u8], i: usize) -> Option<usize> {
+    let i = sign(s, i);
+    match s.get(i)? {
+        b'0' => Some(i + 1),
+        b'1'..=b'9' => digits(s, i, is_dec),
+        _ => None,
+    }
+}
+
+/// An integer of base 16 or 2, after its `0x` or `0b` prefix.
+fn prefixed_int(s: &[u8], i: usize, prefix: &[u8], digit: fn(u8) -> bool) -> Option<usize> {
+    let i = sign(s, i);
+    s[i..].starts_with(prefix).then_some(())?;
+    digits(s, i + prefix.len(), digit)
+}
+
+fn exponent(s: &[u8], i: usize) -> Option<usize> {
+    [dec_int(s, i), prefixed_int(s, i, b"0x", is_hex), prefixed_int(s, i, b"0b", is_bin)].into_iter().flatten().max()
+}
+
+/**
+A float, given how its integer part is matched: the integer part and a dot with an optional fraction, or a
+dot and a fraction (`.5`, `0x.8`), each with an optional exponent; or the integer part with an exponent.
+ */
+fn float(
+    s: &[u8],
+    i: usize,
+    int: impl Fn(usize) -> Option<usize>,
+    prefix: &[u8],
+    digit: fn(u8) -> bool,
+    exp: &[u8],
+) -> Option<usize> {
+    let with_exponent = |end: usize| match s.get(end) {
+        Some(c) if exp.contains(c) => exponent(s, end + 1),
+        _ => None,
+    };
+    let int_dot =
+        int(i).filter(|&end| s.get(end) == Some(&b'.')).map(|end| digits(s, end + 1, digit).unwrap_or(end + 1));
+    let start = sign(s, i);
+    let dot_fraction = s[start..].starts_with(prefix).then(|| digits(s, start + prefix.len(), digit)).flatten();
+    let mantissa = [int_dot, dot_fraction].into_iter().flatten().map(|end| with_exponent(end).unwrap_or(end));
+    let int_exponent = int(i).and_then(with_exponent);
+    mantissa.chain(int_exponent).max()
+}
+
+/// The length of an escape sequence (`\n`, `\x41`, `\u00e9`) at `i`.
+fn escape(s: &[u8], i: usize) -> Option<usize> {
+    if s.get(i) != Some(&b'\\') {
+        return None;
+    }
+    let hex_digits = |n: usize| s.get(i + 2..i + 2 + n).is_some_and(|hex| hex.iter().all(|&c| is_hex(c)));
+    match s.get(i + 1)? {
+        b'$' | b'\'' | b'b' | b'n' | b'r' | b't' | b'\\' => Some(2),
+        b'x' if hex_digits(2) => Some(4),
+        b'u' if hex_digits(4) => Some(6),
+        _ => None,
+    }
+}
+
+/// The result of running a rule: the longest match, and where the rule stopped matching.
+struct Run {
+    longest: Option<usize>,
+    died_at: usize,
+}
+
+/**
+Runs a rule that isn't just a regular expression over the input: `step` gets a state and the position of the
+next character, and adds the states it leads to and their position, or returns the end of a complete match.
+ */
+fn simulate<S: Ord + Clone>(
+    s: &[u8],
+    start: Vec<(usize, S)>,
+    step: impl Fn(&S, usize, &mut Vec<(usize, S)>) -> Option<usize>,
+) -> Run {
+    let mut live: BTreeSet<(usize, S)> = start.into_iter().collect();
+    let mut run = Run { longest: None, died_at: 0 };
+    let mut next = Vec::new();
+    while let Some((i, state)) = live.pop_first() {
+        if i >= s.len() {
+            run.died_at = s.len();
+            continue;
+        }
+        if let Some(end) = step(&state, i, &mut next) {
+            run.longest = run.longest.max(Some(end));
+        }
+        if next.is_empty() {
+            run.died_at = run.died_at.max(i);
+        }
+        live.extend(next.drain(..));
+    }
+    run
+}
+
+/// `'\'' (~['$] | ESCAPE_CHARS)* '\''`; a `$` has to be escaped.
+fn literal(s: &[u8]) -> Run {
+    if s[0] != b'\'' {
+        return Run { longest: None, died_at: 0 };
+    }
+    simulate(s, vec![(1, ())], |_, i, next| {
+        match s[i] {
+            b'\'' => return Some(i + 1),
+            b'$' => {}
+            b'\\' => {
+                next.push((i + 1, ()));
+                next.extend(escape(s, i).map(|len| (i + len, ())));
+            }
+            _ => next.push((i + 1, ())),
+        }
+        None
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum DolState {
+    /// in the text, with or without an interpolation before
+    Text { interpolated: bool },
+    /// after a `$`, which has to be followed by a name
+    Dollar,
+    /// inside the braces of `{expr}`, at a depth of nested braces
+    Nested(usize),
+}
+
+/// `'$'`-strings: like literals, but with at least one `$name` or `{expr}` in the text.
+fn dolstring(s: &[u8]) -> Run {
+    let start = match s {
+        [b'$', b'\'', ..] => 2,
+        [b'\'', ..] => 1,
+        _ => return Run { longest: None, died_at: 0 },
+    };
+    simulate(s, vec![(start, DolState::Text { interpolated: false })], |state, i, next| {
+        let c = s[i];
+        match *state {
+            DolState::Text { interpolated } => match c {
+                b'\'' if interpolated => return Some(i + 1),
+                b'\'' => {}
+                b'$' => next.push((i + 1, DolState::Dollar)),
+                _ => {
+                    next.push((i + 1, state.clone()));
+                    next.extend(escape(s, i).map(|len| (i + len, state.clone())));
+                    if c == b'{' {
+                        next.push((i + 1, DolState::Nested(1)));
+                    }
+                }
+            },
+            DolState::Dollar if is_id_start(c) => next.push((i + 1, DolState::Text { interpolated: true })),
+            DolState::Dollar => {}
+            DolState::Nested(depth) => next.push(match c {
+                b'{' => (i + 1, DolState::Nested(depth + 1)),
+                b'}' if depth == 1 => (i + 1, DolState::Text { interpolated: true }),
+                b'}' => (i + 1, DolState::Nested(depth - 1)),
+                _ => (i + 1, DolState::Nested(depth)),
+            }),
+        }
+        None
+    })
+}
+
+/// A segment of a path: path characters, or a single quoted character like `' '`.
+fn path_segment(s: &[u8], i: usize) -> Option<usize> {
+    match *s.get(i)? {
+        c if is_path_char(c) => Some(i + s[i..].iter().take_while(|&&c| is_path_char(c)).count()),
+        b'\'' => {
+            let closed = |end: usize| (s.get(end) == Some(&b'\'')).then_some(end + 1);
+            let single = s.get(i + 1).filter(|&&c| c != b'\'').and_then(|&c| closed(i + 1 + char_len(c)));
+            let escaped = escape(s, i + 1).and_then(|len| closed(i + 1 + len));
+            single.max(escaped)
+        }
+        _ => None,
+    }
+}
+
+/// `SEGMENT ('/' SEGMENT)* '/'?`, starting with the segment at `i`.
+fn path_segments(s: &[u8], i: usize) -> Option<usize> {
+    let mut end = path_segment(s, i)?;
+    while s.get(end) == Some(&b'/') {
+        match path_segment(s, end + 1) {
+            Some(next) => end = next,
+            None => return Some(end + 1),
+        }
+    }
+    Some(end)
+}
+
+/// `~?.?.?/` followed by segments, or `~` followed by segments.
+fn file_path(s: &[u8]) -> Run {
+    let mut root = 0;
+    for c in *b"~.." {
+        if s.get(root) == Some(&c) {
+            root += 1;
+        }
+    }
+    let rooted = (s.get(root) == Some(&b'/')).then(|| path_segments(s, root + 1).unwrap_or(root + 1));
+    let home = (s[0] == b'~').then(|| path_segments(s, 1)).flatten();
+    Run { longest: rooted.max(home), died_at: root }
+}
+
+/// `'#<' ... '>#'` and `'##<' ... '>##'`, whose text may contain more block comments.
+fn block_comment(s: &[u8], doc: bool) -> Option<usize> {
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    enum Micro {
+        Text,
+        /// after a `>`, which either closes the comment or is followed by something else than `#`
+        Gt,
+        /// after the `#` or `##` that may open a nested comment
+        Hash(u8),
+        /// after the `>#` of `>##`
+        CloseDoc,
+    }
+    let open: &[u8] = if doc { b"##<" } else { b"#<" };
+    if !s.starts_with(open) {
+        return None;
+    }
+    // the state is the stack of open comments (whether each is a doc comment) and where the innermost one is at
+    let close = |stack: &[bool], i: usize, next: &mut Vec<(usize, (Vec<bool>, Micro))>| {
+        let parent = &stack[..stack.len() - 1];
+        if parent.is_empty() {
+            return Some(i + 1);
+        }
+        next.push((i + 1, (parent.to_vec(), Micro::Text)));
+        None
+    };
+    let run = simulate(s, vec![(open.len(), (vec![doc], Micro::Text))], |(stack, micro), i, next| {
+        let c = s[i];
+        match micro {
+            Micro::Text if c == b'>' => next.push((i + 1, (stack.clone(), Micro::Gt))),
+            Micro::Text => {
+                next.push((i + 1, (stack.clone(), Micro::Text)));
+                if c == b'#' {
+                    next.push((i + 1, (stack.clone(), Micro::Hash(1))));
+                }
+            }
+            Micro::Gt if c == b'#' => match stack.last() {
+                Some(true) => next.push((i + 1, (stack.clone(), Micro::CloseDoc))),
+                _ => return close(stack, i, next),
+            },
+            Micro::Gt => next.push((i + 1, (stack.clone(), Micro::Text))),
+            Micro::Hash(hashes) => match c {
+                b'<' => next.push((i + 1, ([&stack[..], &[*hashes == 2]].concat(), Micro::Text))),
+                b'#' if *hashes == 1 => next.push((i + 1, (stack.clone(), Micro::Hash(2)))),
+                _ => {}
+            },
+            Micro::CloseDoc if c == b'#' => return close(stack, i, next),
+            Micro::CloseDoc => {}
+        }
+        None
+    });
+    run.longest
+}
+
+/// Matches the rules of the grammar at the start of `s`: the longest match wins, then the rule that comes first.
+fn longest_match(s: &[u8], nesting: usize) -> Result<(Rule, usize), usize> {
+    let mut best: Option<(Rule, usize)> = None;
+    let mut died_at = 0;
+    let mut consider = |rule: TokenKind, len: Option<usize>| {
+        if let Some(len) = len.filter(|&len| len > best.map_or(0, |(_, best)| best)) {
+            best = Some((Rule::Token(rule), len));
+        }
+    };
+
+    if is_id_start(s[0]) {
+        let len = s.iter().take_while(|&&c| is_id_continue(c)).count();
+        let text = std::str::from_utf8(&s[..len]).unwrap_or_default();
+        let keyword = KEYWORDS.iter().find(|(keyword, _)| *keyword == text);
+        consider(keyword.map_or(Id, |(_, kind)| *kind), Some(len));
+    }
+    for (kind, run) in [(Literal, literal(s)), (DolString, dolstring(s)), (FilePath, file_path(s))] {
+        consider(kind, run.longest);
+        died_at = died_at.max(run.died_at);
+    }
+    consider(DecInt, dec_int(s, 0));
+    consider(HexInt, prefixed_int(s, 0, b"0x", is_hex));
+    consider(BinInt, prefixed_int(s, 0, b"0b", is_bin));
+    consider(DecFloat, float(s, 0, |i| dec_int(s, i), b".", is_dec, b"eEpP"));
+    consider(HexFloat, float(s, 0, |i| prefixed_int(s, i, b"0x", is_hex), b"0x.", is_hex, b"pP"));
+    consider(BinFloat, float(s, 0, |i| prefixed_int(s, i, b"0b", is_bin), b"0b.", is_bin, b"eEpP"));
+    for (text, kind) in OPERATORS {
+        consider(*kind, s.starts_with(text.as_bytes()).then_some(text.len()));
+    }
+
+    // documentation and other comments
+    if s[0] == b'#' {
+        consider(DocBComment, block_comment(s, true));
+        let line = s.iter().position(|&c| c == b'\n');
+        consider(DocComment, line.filter(|_| s.starts_with(b"##")).map(|end| end + 1));
+        consider(BComment, block_comment(s, false));
+        let comment = match s.get(1) {
+            Some(b'!' | b'\n') | None => 1,
+            Some(_) => line.unwrap_or(s.len()),
+        };
+        consider(Comment, Some(comment));
+    }
+    for (text, kind) in BRACKETS {
+        consider(*kind, s.starts_with(text.as_bytes()).then_some(1));
+    }
+
+    // whitespace, escaped line breaks, and line breaks inside of brackets are skipped
+    let newline = match s {
+        [b'\n', ..] => Some(1),
+        [b'\r', b'\n', ..] => Some(2),
+        [b'\r', ..] => {
+            died_at = died_at.max(1);
+            None
+        }
+        _ => None,
+    };
+    let skip = match s {
+        [b' ' | b'\t', ..] => Some((Rule::Skip, 1)),
+        [b'\\', b'\n', ..] => Some((Rule::Skip, 2)),
+        [b'\\', b'\r', b'\n', ..] => Some((Rule::Skip, 3)),
+        [b'\\', b'\r', ..] => {
+            died_at = died_at.max(2);
+            None
+        }
+        [b'\\', ..] => {
+            died_at = died_at.max(1);
+            None
+        }
+        _ if nesting > 0 => newline.map(|len| (Rule::IgnoreNewline, len)),
+        _ => newline.map(|len| (Rule::Token(Nl), len)),
+    };
+    if let Some((rule, len)) = skip.filter(|&(_, len)| len > best.map_or(0, |(_, best)| best)) {
+        best = Some((rule, len));
+    }
+    best.ok_or(died_at)
+}
+
+/**
+Splits a script into tokens, comments included, and ends with an `Eof` token. Like the lexer generated from
+`Msh.g4`, the longest match wins (and between matches of the same length, the rule that comes first in the grammar),
+and line breaks inside of parentheses and brackets are skipped. Braces start a new statement context, where line
+breaks count again. Characters that don't form a token are reported as errors, and skipped.
+ */
+pub struct Lexer<'a> {
+    source: &'a str,
+    /// the byte offset of the next token
+    offset: usize,
+    line: isize,
+    column: isize,
+    /// open parentheses and brackets, since the last open brace
+    nesting: usize,
+    /// the nesting outside of each open brace
+    bracket_stack: Vec<usize>,
+    errors: Vec<SyntaxError>,
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Lexer {
+            source,
+            offset: 0,
+            line: 1,
+            column: 0,
+            nesting: 0,
+            bracket_stack: Vec::new(),
+            errors: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// The characters the lexer couldn't make sense of, so far.
+    pub fn into_errors(self) -> Vec<SyntaxError> {
+        self.errors
+    }
+
+    /// Move past some text, returning it.
+    fn advance(&mut self, len: usize) -> &'a str {
+        let text = &self.source[self.offset..self.offset + len];
+        self.offset += len;
+        match text.rfind('\n') {
+            Some(i) => {
+                self.line += text.matches('\n').count() as isize;
+                self.column = text[i + 1..].chars().count() as isize;
+            }
+            None => self.column += text.chars().count() as isize,
+        }
+        text
+    }
+}
+
+/// How text is shown in error messages, with line breaks and tabs escaped.
+pub(crate) fn error_display(text: &str) -> String {
+    text.replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t")
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let rest = &self.source.as_bytes()[self.offset..];
+            if rest.is_empty() {
+                if self.done {
+                    return None;
+                }
+                self.done = true;
+                return Some(Token { kind: Eof, text: "", line: self.line, column: self.column });
+            }
+            let (line, column) = (self.line, self.column);
+            let (rule, len) = match longest_match(rest, self.nesting) {
+                Ok(found) => found,
+                Err(died_at) => {
+                    // like ANTLR, skip up to and including the character no rule could match
+                    let end = match rest.get(died_at) {
+                        Some(&c) => died_at + char_len(c),
+                        None => rest.len(),
+                    };
+                    let text = self.advance(end);
+                    self.errors.push(SyntaxError {
+                        kind: SyntaxErrorKind::InvalidToken,
+                        msg: format!("token recognition error at: '{}'", error_display(text)),
+                        line,
+                        column,
+                        token: Some(text.to_owned()),
+                    });
+                    continue;
+                }
+            };
+            let text = self.advance(len);
+            let Rule::Token(kind) = rule else { continue };
+            match kind {
+                LParen | LBrack => self.nesting += 1,
+                RParen | RBrack => self.nesting = self.nesting.saturating_sub(1),
+                LBrace => {
+                    self.bracket_stack.push(self.nesting);
+                    self.nesting = 0;
+                }
+                // mismatched brackets are left to the parser to report
+                RBrace => self.nesting = self.bracket_stack.pop().unwrap_or(0),
+                _ => {}
+            }
+            return Some(Token { kind, text, line, column });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(TokenKind, &str)> {
+        Lexer::new(source).map(|token| (token.kind, token.text)).collect()
+    }
+
+    #[test]
+    fn longest_match_wins() {
+        assert_eq!(
+            kinds("a -1 ** x**=2 ./lib.m / import"),
+            [
+                (Id, "a"),
+                (DecInt, "-1"),
+                (TwoStar, "**"),
+                (Id, "x"),
+                (PowEq, "**="),
+                (DecInt, "2"),
+                (FilePath, "./lib.m"),
+                (FilePath, "/"),
+                (Import, "import"),
+                (Eof, ""),
+            ]
+        );
+        assert_eq!(
+            kinds("0x1F.8p-2 1.e5 .5 0b1_0 007"),
+            [
+                (HexFloat, "0x1F.8p-2"),
+                (DecFloat, "1.e5"),
+                (DecFloat, ".5"),
+                (BinInt, "0b1_0"),
+                (DecInt, "0"),
+                (DecInt, "0"),
+                (DecInt, "7"),
+                (Eof, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_and_comments() {
+        assert_eq!(
+            kinds("'a\\'b' '$x' #< a #< b >#\n>#\n## doc\n# c"),
+            [
+                (Literal, "'a\\'b'"),
+                (DolString, "'$x'"),
+                (BComment, "#< a #< b >#\n>#"),
+                (Nl, "\n"),
+                (DocComment, "## doc\n"),
+                (Comment, "# c"),
+                (Eof, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_breaks_inside_brackets() {
+        let tokens: Vec<_> = Lexer::new("f(a,\n  b)\n{\n}\n").map(|token| token.kind).collect();
+        assert_eq!(tokens, [Id, LParen, Id, Comma, Id, RParen, Nl, LBrace, Nl, RBrace, Nl, Eof]);
+        let b = Lexer::new("f(a,\n  b)").find(|token| token.text == "b").unwrap();
+        assert_eq!(b.pos(), (2, 2));
+    }
+
+    #[test]
+    fn invalid_characters() {
+        let mut lexer = Lexer::new("a § 'open\n");
+        let tokens: Vec<_> = lexer.by_ref().map(|token| token.kind).collect();
+        assert_eq!(tokens, [Id, Eof]);
+        let errors = lexer.into_errors();
+        assert_eq!(errors[0].msg, "token recognition error at: '§'");
+        assert_eq!((errors[1].line, errors[1].column), (1, 4));
+        assert_eq!(errors[1].token.as_deref(), Some("'open\n"));
+    }
+}